@@ -0,0 +1,437 @@
+// src/backend.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::breaker::Breaker;
+use crate::config::{BackendSettings, ErrorPolicy};
+use crate::error::errno_of;
+use crate::inflight;
+use crate::logging::{log, Subsystem};
+use crate::stats::MountStats;
+use crate::store::{Capacity, Entry, Librfs, Store, Subtree};
+use crate::throttle::TokenBucket;
+use rfs_utils::LogLevel;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::{Builder, Handle, Runtime};
+
+// Access to one pool through a Store, normally librfs. Calls are made from
+// FUSE threads, so every call is bounded by the configured timeout to keep a
+// hung pool from blocking the mount forever, and transient failures are
+// retried before they reach applications. Failures are returned as errnos.
+pub struct Backend {
+    store: Arc<dyn Store>,
+    handle: Handle,
+    settings: BackendSettings,
+    stats: Arc<MountStats>,
+    breaker: Arc<Breaker>,
+    // Consecutive calls that failed even after retries.
+    hard_errors: AtomicU32,
+    // Limits backend calls per second for this mount.
+    iops: Option<TokenBucket>,
+    // The mount's own runtime, if it does not share the daemon's.
+    _runtime: Option<MountRuntime>,
+}
+
+// A runtime owned by one mount. It is shut down without waiting for its
+// tasks, since it may be dropped while a hung call still holds one.
+struct MountRuntime(Option<Runtime>);
+
+impl Drop for MountRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl Backend {
+    // Backend reading the pool at `pool_root` through librfs. Must be called
+    // from within the tokio runtime.
+    pub fn new(
+        pool_root: String,
+        settings: BackendSettings,
+        stats: Arc<MountStats>,
+        breaker: Arc<Breaker>,
+    ) -> Self {
+        Self::from_store(Arc::new(Librfs::new(pool_root)), settings, stats, breaker)
+    }
+
+    // Must be called from within the tokio runtime.
+    pub fn from_store(
+        store: Arc<dyn Store>,
+        settings: BackendSettings,
+        stats: Arc<MountStats>,
+        breaker: Arc<Breaker>,
+    ) -> Self {
+        Self {
+            store,
+            handle: Handle::current(),
+            settings,
+            stats,
+            breaker,
+            hard_errors: AtomicU32::new(0),
+            iops: None,
+            _runtime: None,
+        }
+    }
+
+    // Runs this backend's calls on a runtime of `threads` worker threads,
+    // and as many blocking threads, owned by the backend; 0 keeps the
+    // runtime of the caller, as does a runtime that cannot be started.
+    pub fn with_runtime_threads(mut self, threads: u32) -> Self {
+        if threads == 0 {
+            return self;
+        }
+        let built = Builder::new_multi_thread()
+            .worker_threads(threads as usize)
+            .max_blocking_threads(threads as usize)
+            .thread_name("rfs-backend")
+            .enable_all()
+            .build();
+        match built {
+            Ok(runtime) => {
+                self.handle = runtime.handle().clone();
+                self._runtime = Some(MountRuntime(Some(runtime)));
+            }
+            Err(e) => log(
+                Subsystem::Mount,
+                LogLevel::Warn,
+                &format!("{} cannot start its own runtime, sharing the daemon's: {}", self.stats.mount_point, e),
+            ),
+        }
+        self
+    }
+
+    // Confines this backend to the directory `subpath` of the pool, which it
+    // then presents as the root; "/" keeps the whole pool.
+    pub fn within(mut self, subpath: &str) -> Self {
+        if subpath.trim_end_matches('/').is_empty() {
+            return self;
+        }
+        self.store = Arc::new(Subtree::new(self.store, subpath));
+        self
+    }
+
+    // Limits this backend to `max_iops` calls per second; 0 means unlimited.
+    pub fn with_iops_limit(mut self, max_iops: u32) -> Self {
+        self.iops = (max_iops > 0).then(|| TokenBucket::new(max_iops));
+        self
+    }
+
+    pub fn stats(&self) -> &MountStats {
+        &self.stats
+    }
+
+    pub fn list_directory(&self, path: &str) -> Result<HashMap<String, Entry>, i32> {
+        self.call(path, || self.store.list_directory(path))
+    }
+
+    // Reads the capacity of the filesystem holding the pool, with the
+    // reserve taken out of the available blocks. Writes failing with ENOSPC
+    // inside the reserve wait for a write path (see TODO). statvfs blocks,
+    // so it runs on a blocking thread, bounded by the timeout and the
+    // breaker like every other call; a hung one is left behind there.
+    pub fn space(&self) -> Result<Capacity, i32> {
+        let mut capacity = self.call("/", || {
+            let store = Arc::clone(&self.store);
+            async move {
+                tokio::task::spawn_blocking(move || store.capacity()).await.map_err(io::Error::other).and_then(|r| r)
+            }
+        })?;
+        let reserved = capacity.blocks * u64::from(self.settings.reserve_percent.min(100)) / 100;
+        capacity.blocks_available = capacity.blocks_available.saturating_sub(reserved);
+        Ok(capacity)
+    }
+
+    // Runs a backend call on `path` through the pool's circuit breaker.
+    // While the breaker is open the call fails with EIO without reaching the
+    // pool, and counts neither toward the breaker nor the errors= policy nor
+    // the recent errors, which only show calls the pool failed. Failures
+    // other than missing entries are kept in the mount's recent errors with
+    // the backend's description.
+    fn call<T, E, F, Fut>(&self, path: &str, make_call: F) -> Result<T, i32>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::error::Error + 'static,
+    {
+        if !self.breaker.allow() {
+            return Err(libc::EIO);
+        }
+        let result = self.call_with_retries(make_call);
+        match &result {
+            Err(e) if is_transient(e.errno) => {
+                self.breaker.record_failure();
+                self.record_hard_error();
+            }
+            _ => {
+                self.breaker.record_success();
+                self.hard_errors.store(0, Ordering::Relaxed);
+            }
+        }
+        result.map_err(|e| {
+            if !matches!(e.errno, libc::ENOENT | libc::ENOTDIR) {
+                let op = inflight::current().map(|op| op.op);
+                self.stats.record_error(op, path, e.errno, e.message);
+            }
+            e.errno
+        })
+    }
+
+    // Applies the errors= policy once enough calls in a row have failed.
+    fn record_hard_error(&self) {
+        let count = self.hard_errors.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.settings.errors == ErrorPolicy::RemountRo
+            && count >= self.settings.errors_threshold
+            && self.stats.set_read_only()
+        {
+            log(
+                Subsystem::Mount,
+                LogLevel::Error,
+                &format!(
+                    "{} failed {} backend calls in a row; switching to read-only (errors=remount-ro)",
+                    self.stats.mount_point, count
+                ),
+            );
+        }
+    }
+
+    // Errno for operations that would modify the pool. Mutations are not
+    // supported by librfs yet; a mount switched to read-only reports EROFS.
+    pub fn mutation_errno(&self) -> i32 {
+        if self.stats.is_read_only() { libc::EROFS } else { libc::ENOSYS }
+    }
+
+    // Retries transient failures with jittered exponential backoff until the
+    // attempts or the time budget run out. Calls that lost the connection to
+    // the pool keep retrying for `reconnect_wait` instead, so a restart of
+    // rfsd is ridden out rather than surfaced to applications. Every call
+    // made through here is a read, so repeating it is safe; the connection
+    // is re-established by librfs on the next call.
+    fn call_with_retries<T, E, F, Fut>(&self, mut make_call: F) -> Result<T, CallError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::error::Error + 'static,
+    {
+        let started = Instant::now();
+        let mut backoff = self.settings.retry_initial_backoff();
+        let mut attempt = 0;
+        loop {
+            let error = match self.attempt(make_call()) {
+                Ok(value) => {
+                    self.record_connected();
+                    return Ok(value);
+                }
+                Err(error) => error,
+            };
+            let errno = error.errno;
+            let reconnecting = is_disconnect(errno) && !self.settings.reconnect_wait().is_zero();
+            if reconnecting {
+                self.record_disconnected(errno);
+            } else if !is_transient(errno) {
+                self.record_connected();
+            }
+            let delay = jitter(backoff);
+            let out_of_time = if reconnecting {
+                started.elapsed() + delay > self.settings.reconnect_wait()
+            } else {
+                attempt >= self.settings.retry_attempts || started.elapsed() + delay > self.settings.retry_budget()
+            };
+            if !is_transient(errno) || inflight::current().is_some_and(|op| op.is_cancelled()) || out_of_time {
+                return Err(error);
+            }
+            attempt += 1;
+            self.stats.count(&self.stats.backend_retries, 1);
+            std::thread::sleep(delay);
+            backoff = (backoff * 2).min(self.settings.retry_max_backoff());
+        }
+    }
+
+    fn record_disconnected(&self, errno: i32) {
+        if self.stats.set_disconnected() {
+            log(
+                Subsystem::Mount,
+                LogLevel::Warn,
+                &format!(
+                    "{} lost the connection to its pool ({}); retrying for up to {:?}",
+                    self.stats.mount_point,
+                    std::io::Error::from_raw_os_error(errno),
+                    self.settings.reconnect_wait()
+                ),
+            );
+        }
+    }
+
+    fn record_connected(&self) {
+        if let Some(down) = self.stats.set_connected() {
+            log(
+                Subsystem::Mount,
+                LogLevel::Info,
+                &format!("{} reconnected to its pool after {:.1}s", self.stats.mount_point, down.as_secs_f64()),
+            );
+        }
+    }
+
+    fn attempt<T, E>(&self, call: impl Future<Output = Result<T, E>>) -> Result<T, CallError>
+    where
+        E: std::error::Error + 'static,
+    {
+        if let Some(iops) = &self.iops {
+            let waited = iops.acquire(1.0);
+            self.stats.count(&self.stats.throttle_wait_us, waited.as_micros() as u64);
+        }
+        // A call made for a FUSE operation is abandoned when the watchdog
+        // aborts the operation.
+        let op = inflight::current();
+        if op.as_ref().is_some_and(|op| op.is_cancelled()) {
+            return Err(CallError::new(libc::ETIMEDOUT, "operation aborted by the watchdog"));
+        }
+        let timeout = self.settings.timeout();
+        let outcome = self.handle.block_on(async {
+            let call = async {
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, call).await.ok(),
+                    None => Some(call.await),
+                }
+            };
+            match &op {
+                Some(op) => tokio::select! {
+                    outcome = call => outcome,
+                    _ = op.cancelled() => None,
+                },
+                None => call.await,
+            }
+        });
+        let Some(result) = outcome else {
+            // The handler logs the failed operation; only count it here.
+            self.stats.count(&self.stats.backend_timeouts, 1);
+            let message = match timeout {
+                Some(timeout) if !op.as_ref().is_some_and(|op| op.is_cancelled()) => {
+                    format!("timed out after {:?}", timeout)
+                }
+                _ => "operation aborted by the watchdog".to_string(),
+            };
+            return Err(CallError::new(libc::ETIMEDOUT, message));
+        };
+        result.map_err(|e| CallError::new(errno_of(&e), e.to_string()))
+    }
+}
+
+// A failed backend call: the errno reported to the kernel and the backend's
+// own description of the failure.
+struct CallError {
+    errno: i32,
+    message: String,
+}
+
+impl CallError {
+    fn new(errno: i32, message: impl Into<String>) -> Self {
+        Self {
+            errno,
+            message: message.into(),
+        }
+    }
+}
+
+// Errors that may go away by themselves, such as a pool that is briefly
+// overloaded or a network hiccup. Everything else is returned immediately.
+fn is_transient(errno: i32) -> bool {
+    matches!(
+        errno,
+        libc::EIO
+            | libc::ETIMEDOUT
+            | libc::EAGAIN
+            | libc::EBUSY
+            | libc::EINTR
+            | libc::ECONNRESET
+            | libc::ECONNREFUSED
+            | libc::ECONNABORTED
+            | libc::ENOTCONN
+            | libc::EPIPE
+            | libc::ESHUTDOWN
+            | libc::ENETUNREACH
+            | libc::EHOSTUNREACH
+    )
+}
+
+// Errors meaning the pool service went away, as while rfsd restarts.
+fn is_disconnect(errno: i32) -> bool {
+    matches!(
+        errno,
+        libc::ECONNREFUSED | libc::ECONNRESET | libc::ECONNABORTED | libc::ENOTCONN | libc::EPIPE | libc::ESHUTDOWN
+    )
+}
+
+// Picks a random delay between half and all of `backoff`, so requests that
+// failed together do not all retry at the same moment.
+fn jitter(backoff: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let half = backoff / 2;
+    half + half.mul_f64((random % 1000) as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Backend;
+    use crate::breaker::Breaker;
+    use crate::config::{BackendSettings, ErrorPolicy};
+    use crate::stats::Registry;
+    use crate::testing::MemoryStore;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn calls_failed_fast_by_an_open_breaker_are_not_counted_as_pool_errors() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let settings = BackendSettings {
+            retry_attempts: 0,
+            errors: ErrorPolicy::RemountRo,
+            errors_threshold: 2,
+            ..BackendSettings::default()
+        };
+        let stats = Registry::default().register("/mnt/pool", 1);
+        let breaker = Arc::new(Breaker::new(1, 1, Duration::from_secs(60)));
+        let store = Arc::new(MemoryStore::default().dir("/docs").failing("/docs", libc::EIO));
+        let backend = {
+            let _entered = runtime.enter();
+            Backend::from_store(store, settings, Arc::clone(&stats), Arc::clone(&breaker))
+        };
+
+        // One failure the pool reports opens the breaker.
+        assert_eq!(backend.list_directory("/docs").err(), Some(libc::EIO));
+        assert_eq!(breaker.state_name(), "open");
+        for _ in 0..5 {
+            assert_eq!(backend.list_directory("/docs").err(), Some(libc::EIO));
+        }
+        assert_eq!(breaker.trips.load(Ordering::Relaxed), 1);
+        assert!(!stats.is_read_only());
+        assert_eq!(stats.render_errors().lines().count(), 1);
+    }
+
+    #[test]
+    fn a_hung_capacity_read_times_out() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let settings = BackendSettings {
+            timeout_ms: 100,
+            retry_attempts: 0,
+            ..BackendSettings::default()
+        };
+        let stats = Registry::default().register("/mnt/pool", 1);
+        let store = Arc::new(MemoryStore::default().stalling_capacity(Duration::from_secs(2)));
+        let backend = {
+            let _entered = runtime.enter();
+            Backend::from_store(store, settings, stats, Arc::new(Breaker::new(1, 5, Duration::from_secs(60))))
+        };
+        let started = Instant::now();
+        assert_eq!(backend.space().err(), Some(libc::ETIMEDOUT));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        runtime.shutdown_background();
+    }
+}
@@ -0,0 +1,203 @@
+// src/bench.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::backend::Backend;
+use crate::config::{load_settings, MountOptions, Paths};
+use crate::error::FuseError;
+use crate::mount::{find_pool, spawn_session, MountSpec};
+use crate::stats::Registry;
+use crate::unmount::unmount_all;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::io::{self, Read};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// How long each pattern runs.
+const PATTERN_TIME: Duration = Duration::from_secs(10);
+const SEQUENTIAL_CHUNK: usize = 1 << 20;
+const RANDOM_CHUNK: usize = 4096;
+
+// `rfs bench <pool-id>`: mounts a pool at a temporary directory with the
+// default mount options and runs standard access patterns through the
+// kernel and FUSE, printing throughput and latency of each. Returns the exit
+// code: 0 if every pattern ran, 1 if one failed and 2 if the pool could not
+// be mounted.
+pub async fn run(paths: &Paths, pool_id: &str) -> i32 {
+    match bench(paths, pool_id).await {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("bench: {}", e);
+            2
+        }
+    }
+}
+
+async fn bench(paths: &Paths, pool_id: &str) -> Result<bool, FuseError> {
+    let settings = load_settings(&paths.settings)?;
+    let (pool_id, pool_root) = find_pool(&paths.pool_config, pool_id).await?;
+    let mount_point = std::env::temp_dir().join(format!("rfs-bench-{}", std::process::id()));
+    fs::create_dir_all(&mount_point)?;
+    let spec = MountSpec {
+        mount_point: mount_point.to_string_lossy().into_owned(),
+        pool_id,
+        pool_root: pool_root.clone(),
+        subpath: "/".to_string(),
+        read_only: false,
+        managed: false,
+    };
+
+    let registry = Registry::default();
+    let stats = registry.register(&spec.mount_point, pool_id);
+    let breaker = registry.breaker(pool_id, &settings.backend);
+    let backend = Backend::new(pool_root, settings.backend.clone(), stats, breaker);
+    let session = match spawn_session(spec.clone(), backend, MountOptions::default(), Default::default(), true, settings.fuse.mount_method).await {
+        Ok(session) => session,
+        Err(e) => {
+            let _ = fs::remove_dir(&mount_point);
+            return Err(e);
+        }
+    };
+    println!("Benchmarking pool {} mounted at {}", pool_id, mount_point.display());
+
+    let root = mount_point.clone();
+    let results = tokio::task::spawn_blocking(move || run_patterns(&root)).await;
+
+    unmount_all(vec![(spec.mount_point, session)], &settings.shutdown).await;
+    let _ = fs::remove_dir(&mount_point);
+    let results = results.map_err(|e| FuseError::Io(io::Error::other(e)))?;
+
+    let mut all_ran = true;
+    for (pattern, result) in results {
+        match result {
+            Ok(line) => println!("{:<12} {}", pattern, line),
+            Err(e) => {
+                all_ran = false;
+                println!("{:<12} failed: {}", pattern, e);
+            }
+        }
+    }
+    println!("{:<12} skipped: the FUSE layer is read-only", "write");
+    Ok(all_ran)
+}
+
+type PatternResult = (&'static str, io::Result<String>);
+
+fn run_patterns(root: &Path) -> Vec<PatternResult> {
+    let mut results = Vec::new();
+    let metadata = metadata_storm(root);
+    let largest = metadata.as_ref().ok().and_then(|(_, largest)| largest.clone());
+    results.push(("metadata", metadata.map(|(line, _)| line)));
+    match largest {
+        Some(file) => {
+            results.push(("seq-read", sequential_read(&file)));
+            results.push(("rand-read-4k", random_read(&file)));
+        }
+        None => {
+            let none = || Err(io::Error::new(io::ErrorKind::NotFound, "no non-empty file in the pool"));
+            results.push(("seq-read", none()));
+            results.push(("rand-read-4k", none()));
+        }
+    }
+    results
+}
+
+// Lists every directory and stats every entry, starting over at the root
+// once the whole tree was visited. Also returns the largest file found, for
+// the read patterns.
+fn metadata_storm(root: &Path) -> io::Result<(String, Option<PathBuf>)> {
+    let mut latencies = Latencies::default();
+    let mut largest: Option<(u64, PathBuf)> = None;
+    let started = Instant::now();
+    let mut pending = Vec::new();
+    while started.elapsed() < PATTERN_TIME {
+        let Some(dir) = pending.pop() else {
+            pending.push(root.to_path_buf());
+            continue;
+        };
+        let op = Instant::now();
+        let entries: Vec<_> = fs::read_dir(&dir)?.collect::<Result<_, _>>()?;
+        latencies.record(op.elapsed());
+        for entry in entries {
+            let op = Instant::now();
+            let metadata = fs::symlink_metadata(entry.path())?;
+            latencies.record(op.elapsed());
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.len() > largest.as_ref().map_or(0, |(size, _)| *size) {
+                largest = Some((metadata.len(), entry.path()));
+            }
+        }
+    }
+    Ok((latencies.summary("ops", started.elapsed()), largest.map(|(_, path)| path)))
+}
+
+fn sequential_read(file: &Path) -> io::Result<String> {
+    let mut latencies = Latencies::default();
+    let mut buffer = vec![0; SEQUENTIAL_CHUNK];
+    let mut bytes = 0u64;
+    let started = Instant::now();
+    let mut reader = File::open(file)?;
+    while started.elapsed() < PATTERN_TIME {
+        let op = Instant::now();
+        let read = reader.read(&mut buffer)?;
+        latencies.record(op.elapsed());
+        if read == 0 {
+            reader = File::open(file)?;
+        }
+        bytes += read as u64;
+    }
+    Ok(format!("{}, {}", throughput(bytes, started.elapsed()), latencies.summary("reads", started.elapsed())))
+}
+
+fn random_read(file: &Path) -> io::Result<String> {
+    let mut latencies = Latencies::default();
+    let mut buffer = vec![0; RANDOM_CHUNK];
+    let mut bytes = 0u64;
+    let reader = File::open(file)?;
+    let blocks = (reader.metadata()?.len() / RANDOM_CHUNK as u64).max(1);
+    let started = Instant::now();
+    while started.elapsed() < PATTERN_TIME {
+        let offset = random() % blocks * RANDOM_CHUNK as u64;
+        let op = Instant::now();
+        bytes += reader.read_at(&mut buffer, offset)? as u64;
+        latencies.record(op.elapsed());
+    }
+    Ok(format!("{}, {}", throughput(bytes, started.elapsed()), latencies.summary("reads", started.elapsed())))
+}
+
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+fn throughput(bytes: u64, elapsed: Duration) -> String {
+    format!("{:.1} MiB/s", bytes as f64 / (1 << 20) as f64 / elapsed.as_secs_f64())
+}
+
+#[derive(Default)]
+struct Latencies(Vec<Duration>);
+
+impl Latencies {
+    fn record(&mut self, latency: Duration) {
+        self.0.push(latency);
+    }
+
+    // Operation count, rate and latency percentiles, e.g.
+    // "52000 ops, 5200/s, p50 150us, p99 900us".
+    fn summary(&mut self, unit: &str, elapsed: Duration) -> String {
+        self.0.sort();
+        let percentile = |p: usize| self.0.get((self.0.len() * p / 100).min(self.0.len().saturating_sub(1)));
+        let micros = |latency: Option<&Duration>| latency.map_or(0, |latency| latency.as_micros());
+        format!(
+            "{} {}, {:.0}/s, p50 {}us, p99 {}us",
+            self.0.len(),
+            unit,
+            self.0.len() as f64 / elapsed.as_secs_f64(),
+            micros(percentile(50)),
+            micros(percentile(99))
+        )
+    }
+}
@@ -0,0 +1,106 @@
+// src/breaker.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::logging::{log, Subsystem};
+use rfs_utils::LogLevel;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    // Calls fail fast until the deadline, then one probe is let through.
+    Open { until: Instant },
+    // The probe is in flight; everything else still fails fast.
+    HalfOpen,
+}
+
+// Circuit breaker shared by all mounts of one pool. After `threshold`
+// consecutive failed calls it opens and requests fail immediately instead of
+// tying up FUSE threads on a pool that is not answering.
+pub struct Breaker {
+    pool_id: u64,
+    threshold: u32,
+    open_for: Duration,
+    state: Mutex<State>,
+    pub trips: AtomicU64,
+}
+
+impl Breaker {
+    pub fn new(pool_id: u64, threshold: u32, open_for: Duration) -> Self {
+        Self {
+            pool_id,
+            threshold,
+            open_for,
+            state: Mutex::new(State::Closed { failures: 0 }),
+            trips: AtomicU64::new(0),
+        }
+    }
+
+    // Whether a call may go to the backend. Once the open period is over the
+    // first caller becomes the probe that decides whether the breaker closes.
+    pub fn allow(&self) -> bool {
+        if self.threshold == 0 {
+            return true;
+        }
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until } if Instant::now() >= until => {
+                *state = State::HalfOpen;
+                true
+            }
+            State::Open { .. } | State::HalfOpen => false,
+        }
+    }
+
+    // Records a call the pool answered, including with an error such as ENOENT.
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::Closed { .. }) {
+            log(Subsystem::Mount, LogLevel::Info, &format!("Pool {} recovered; closing circuit breaker", self.pool_id));
+        }
+        *state = State::Closed { failures: 0 };
+    }
+
+    // Records a call that failed even after retries.
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let trip = match *state {
+            State::Closed { failures } if failures + 1 < self.threshold => {
+                *state = State::Closed { failures: failures + 1 };
+                false
+            }
+            State::Closed { .. } | State::HalfOpen => true,
+            State::Open { .. } => false,
+        };
+        if trip {
+            *state = State::Open {
+                until: Instant::now() + self.open_for,
+            };
+            self.trips.fetch_add(1, Ordering::Relaxed);
+            log(
+                Subsystem::Mount,
+                LogLevel::Warn,
+                &format!("Pool {} keeps failing; failing requests fast for {:?}", self.pool_id, self.open_for),
+            );
+        }
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        match *self.state.lock().unwrap() {
+            State::Closed { .. } => "closed",
+            State::Open { .. } => "open",
+            State::HalfOpen => "half-open",
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(*self.state.lock().unwrap(), State::Closed { .. })
+    }
+}
@@ -0,0 +1,208 @@
+// src/bundle.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+// The support bundle written by `rfs-fusectl support-bundle`: the daemon's
+// configuration with secrets redacted, its state as reported over the
+// control socket, and version and host details, in one tar archive.
+
+use crate::user_dirs;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml::Value;
+
+// Control socket commands whose responses go into the bundle, and the file
+// each is stored as.
+const COMMANDS: [(&str, &str); 6] = [
+    ("version", "version.txt"),
+    ("status", "status.txt"),
+    ("stats --json", "stats.json"),
+    ("errors", "errors.txt"),
+    ("ops", "ops.txt"),
+    ("metrics", "metrics.txt"),
+];
+
+// Keys whose values are replaced in the bundled configuration, matched
+// case-insensitively anywhere in the key.
+const SECRET_KEYS: [&str; 5] = ["password", "secret", "token", "credential", "private_key"];
+
+// Writes the bundle to `output`, asking the daemon through `query`. A
+// command that fails, such as when the daemon is not running, is stored as
+// its error, so a bundle can still be made from a host whose daemon is down.
+pub fn write(output: &str, query: impl Fn(&str) -> io::Result<String>) -> io::Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let dir = format!("rfs-fuse-support-{}", now);
+    let mut archive = TarWriter::new(BufWriter::new(File::create(output)?), now);
+
+    archive.add(&format!("{}/host.txt", dir), host_details().as_bytes())?;
+    let mut version = String::new();
+    for (command, name) in COMMANDS {
+        let content = query(command).unwrap_or_else(|e| format!("unavailable: {}\n", e));
+        archive.add(&format!("{}/{}", dir, name), content.as_bytes())?;
+        if command == "version" {
+            version = content;
+        }
+    }
+    // The files the daemon reports having read. When it is down, those it
+    // would read if started like this command, as in config.rs.
+    for (key, var, name) in [
+        ("config", "RFS_FUSE_CONFIG", "config.toml"),
+        ("pool_config", "RFS_FUSE_POOL_CONFIG", "pool.toml"),
+        ("settings", "RFS_FUSE_SETTINGS", "fuse.toml"),
+    ] {
+        let path = reported_path(&version, key)
+            .or_else(|| env::var(var).ok().filter(|path| !path.is_empty()))
+            .unwrap_or_else(|| user_dirs::config_file(name));
+        let name = path.rsplit('/').next().unwrap_or(&path);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => redact(&content),
+            Err(e) => format!("# {}: {}\n", path, e),
+        };
+        archive.add(&format!("{}/config/{}", dir, name), content.as_bytes())?;
+    }
+    archive.finish()
+}
+
+// The path on the `<key> <path>` line of the daemon's version response.
+fn reported_path(version: &str, key: &str) -> Option<String> {
+    version.lines().find_map(|line| line.strip_prefix(key)?.strip_prefix(' ')).map(str::to_string)
+}
+
+// rfs-fusectl's version, the kernel and the rfs mounts of the host.
+fn host_details() -> String {
+    let mut out = format!("rfs-fusectl {}\n", env!("CARGO_PKG_VERSION"));
+    if let Ok(release) = fs::read_to_string("/proc/sys/kernel/osrelease") {
+        out.push_str(&format!("kernel {}", release));
+    }
+    out.push_str("\nmounts:\n");
+    if let Ok(mounts) = fs::read_to_string("/proc/self/mounts") {
+        for line in mounts.lines().filter(|line| line.split(' ').nth(2).is_some_and(|kind| kind.starts_with("fuse"))) {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// The TOML document with the values of secret-looking keys replaced. A
+// document that does not parse is left out, since its secrets cannot be found.
+fn redact(content: &str) -> String {
+    match content.parse::<toml::Table>() {
+        Ok(mut table) => {
+            redact_table(&mut table);
+            toml::to_string(&table).unwrap_or_else(|e| format!("# not included: {}\n", e))
+        }
+        Err(e) => format!("# not included, does not parse: {}\n", e),
+    }
+}
+
+fn redact_table(table: &mut toml::Table) {
+    for (key, value) in table.iter_mut() {
+        let key = key.to_ascii_lowercase();
+        if SECRET_KEYS.iter().any(|secret| key.contains(secret)) {
+            *value = Value::String("<redacted>".to_string());
+        } else {
+            redact_value(value);
+        }
+    }
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Table(table) => redact_table(table),
+        Value::Array(values) => values.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+// Writes regular files into an uncompressed ustar archive.
+struct TarWriter<W: Write> {
+    out: W,
+    mtime: u64,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn new(out: W, mtime: u64) -> Self {
+        Self { out, mtime }
+    }
+
+    fn add(&mut self, path: &str, content: &[u8]) -> io::Result<()> {
+        if path.len() > 100 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is too long for tar", path)));
+        }
+        let mut header = [0u8; 512];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], content.len() as u64);
+        octal(&mut header[136..148], self.mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        // The checksum is computed with its own field filled with spaces.
+        header[148..156].fill(b' ');
+        let checksum: u64 = header.iter().map(|byte| u64::from(*byte)).sum();
+        octal(&mut header[148..155], checksum);
+
+        self.out.write_all(&header)?;
+        self.out.write_all(content)?;
+        let padding = (512 - content.len() % 512) % 512;
+        self.out.write_all(&[0u8; 512][..padding])
+    }
+
+    // Ends the archive with two empty blocks.
+    fn finish(mut self) -> io::Result<()> {
+        self.out.write_all(&[0u8; 1024])?;
+        self.out.flush()
+    }
+}
+
+// Fills a header field with `value` in octal, zero-padded and NUL-terminated.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact, reported_path, TarWriter};
+
+    #[test]
+    fn secret_values_are_redacted() {
+        let redacted = redact("[backend]\ntimeout_secs = 5\napi_token = \"abc\"\n[[pools]]\nPassword = \"hunter2\"\n");
+        assert!(redacted.contains("timeout_secs = 5"));
+        assert!(!redacted.contains("abc"));
+        assert!(!redacted.contains("hunter2"));
+        assert!(redact("not = [toml").starts_with("# not included"));
+    }
+
+    #[test]
+    fn configuration_paths_come_from_the_daemon() {
+        let version =
+            "rfs-fuse 0.1.0\nconfig /etc/rfs/config.toml\npool_config /srv/pool.toml\nsettings /x y/fuse.toml\n";
+        assert_eq!(reported_path(version, "config").as_deref(), Some("/etc/rfs/config.toml"));
+        assert_eq!(reported_path(version, "pool_config").as_deref(), Some("/srv/pool.toml"));
+        assert_eq!(reported_path(version, "settings").as_deref(), Some("/x y/fuse.toml"));
+        assert_eq!(reported_path("unavailable: connection refused\n", "config"), None);
+    }
+
+    #[test]
+    fn archives_have_valid_headers() {
+        let mut out = Vec::new();
+        let mut archive = TarWriter::new(&mut out, 0);
+        archive.add("bundle/status.txt", b"ok\n").unwrap();
+        archive.finish().unwrap();
+
+        assert_eq!(out.len(), 512 + 512 + 1024);
+        assert_eq!(&out[..17], b"bundle/status.txt");
+        assert_eq!(&out[124..136], b"00000000003\0");
+        let mut header = out[..512].to_vec();
+        let stored = u64::from_str_radix(std::str::from_utf8(&header[148..154]).unwrap(), 8).unwrap();
+        header[148..156].fill(b' ');
+        assert_eq!(stored, header.iter().map(|byte| u64::from(*byte)).sum::<u64>());
+        assert_eq!(&out[512..515], b"ok\n");
+    }
+}
@@ -0,0 +1,90 @@
+// src/capabilities.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::config::{MountOptions, ReaddirPlus};
+use fuser::consts::{
+    FUSE_ASYNC_READ, FUSE_BIG_WRITES, FUSE_DO_READDIRPLUS, FUSE_MAX_PAGES, FUSE_PARALLEL_DIROPS, FUSE_POSIX_ACL,
+    FUSE_READDIRPLUS_AUTO, FUSE_WRITEBACK_CACHE,
+};
+use fuser::KernelConfig;
+
+// Capabilities fuser asks for on every mount.
+const DEFAULTS: u32 = FUSE_ASYNC_READ | FUSE_BIG_WRITES | FUSE_MAX_PAGES;
+
+// The capabilities negotiated at init, by the names they are reported with.
+// writeback_cache and posix_acl are never asked for: with the first the
+// kernel would buffer writes and keep size and mtime itself, while the FUSE
+// layer rejects writes; with the second it would enforce ACLs from extended
+// attributes the pool does not store.
+const CAPABILITIES: [(&str, u32); 8] = [
+    ("async_read", FUSE_ASYNC_READ),
+    ("big_writes", FUSE_BIG_WRITES),
+    ("max_pages", FUSE_MAX_PAGES),
+    ("parallel_dirops", FUSE_PARALLEL_DIROPS),
+    ("readdirplus", FUSE_DO_READDIRPLUS),
+    ("readdirplus_auto", FUSE_READDIRPLUS_AUTO),
+    ("writeback_cache", FUSE_WRITEBACK_CACHE),
+    ("posix_acl", FUSE_POSIX_ACL),
+];
+
+// How a capability came out of the negotiation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Enabled,
+    // Asked for, but the kernel does not offer it.
+    Unsupported,
+    // Not asked for.
+    Declined,
+}
+
+impl Outcome {
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Enabled => "enabled",
+            Outcome::Unsupported => "unsupported",
+            Outcome::Declined => "declined",
+        }
+    }
+}
+
+// The outcome of every capability in CAPABILITIES, in its order.
+pub struct Negotiated(pub Vec<(&'static str, Outcome)>);
+
+impl Negotiated {
+    // Names of the capabilities with `outcome`, comma separated, or "none".
+    pub fn names(&self, outcome: Outcome) -> String {
+        let names: Vec<&str> = self.0.iter().filter(|(_, o)| *o == outcome).map(|(name, _)| *name).collect();
+        if names.is_empty() { "none".to_string() } else { names.join(",") }
+    }
+}
+
+// Capabilities a mount asks for beyond fuser's defaults.
+pub fn wanted(options: &MountOptions) -> u32 {
+    let readdirplus = match options.readdirplus {
+        ReaddirPlus::Off => 0,
+        ReaddirPlus::Auto => FUSE_DO_READDIRPLUS | FUSE_READDIRPLUS_AUTO,
+        ReaddirPlus::Always => FUSE_DO_READDIRPLUS,
+    };
+    let parallel_dirops = if options.parallel_dirops { FUSE_PARALLEL_DIROPS } else { 0 };
+    readdirplus | parallel_dirops
+}
+
+// Asks the kernel for the defaults and `wanted`, one capability at a time,
+// so one it lacks does not cost the others.
+pub fn negotiate(config: &mut KernelConfig, wanted: u32) -> Negotiated {
+    let outcomes = CAPABILITIES
+        .iter()
+        .map(|&(name, bit)| {
+            let outcome = if (DEFAULTS | wanted) & bit == 0 {
+                Outcome::Declined
+            } else if config.add_capabilities(bit).is_ok() {
+                Outcome::Enabled
+            } else {
+                Outcome::Unsupported
+            };
+            (name, outcome)
+        })
+        .collect();
+    Negotiated(outcomes)
+}
@@ -0,0 +1,690 @@
+// src/config.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::dispatch::UidWeights;
+use crate::error::FuseError;
+use crate::hidden::HiddenPaths;
+use crate::logging::{log, parse_level, Subsystem};
+use crate::user_dirs;
+use crate::validate;
+use rfs_utils::LogLevel;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+// Locations of the configuration files, in /opt/rfs/rfsd or, for a user
+// other than root, ~/.config/rfs. Each can be overridden through the
+// environment so containers and CI do not need files under /opt/rfs.
+pub struct Paths {
+    pub config: String,
+    pub pool_config: String,
+    pub settings: String,
+}
+
+impl Paths {
+    pub fn from_env() -> Self {
+        Self {
+            config: env_or("RFS_FUSE_CONFIG", &user_dirs::config_file("config.toml")),
+            pool_config: env_or("RFS_FUSE_POOL_CONFIG", &user_dirs::config_file("pool.toml")),
+            settings: env_or("RFS_FUSE_SETTINGS", &user_dirs::config_file("fuse.toml")),
+        }
+    }
+}
+
+// Returns the variable's value, or `default` when it is unset or empty.
+fn env_or(var: &str, default: &str) -> String {
+    env::var(var)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+// Log level from RFS_FUSE_LOG_LEVEL, overriding the one in config.toml.
+pub fn env_log_level() -> Option<LogLevel> {
+    let value = env::var("RFS_FUSE_LOG_LEVEL").ok()?;
+    let level = parse_level(&value);
+    if level.is_none() {
+        eprintln!("Ignoring invalid RFS_FUSE_LOG_LEVEL '{}'", value);
+    }
+    level
+}
+
+// Settings specific to rfs-fuse, read from an optional TOML file next to config.toml.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub startup: StartupSettings,
+    pub shutdown: ShutdownSettings,
+    pub supervisor: SupervisorSettings,
+    pub reload: ReloadSettings,
+    pub control: ControlSettings,
+    pub log: LogSettings,
+    pub backend: BackendSettings,
+    pub scrub: ScrubSettings,
+    pub watchdog: WatchdogSettings,
+    pub fuse: FuseSettings,
+    // Per-mount options, keyed by mount point.
+    pub mounts: BTreeMap<String, MountOptions>,
+    // Read-only views of mounts, keyed by the view's mount point.
+    pub views: BTreeMap<String, ViewSettings>,
+}
+
+// A read-only mount of the pool of another mount, from a [views."<mount
+// point>"] table, e.g. for exporting a mount used internally to Samba. It
+// shares the other mount's cached entries; options for it come from its own
+// [mounts."<mount point>"] table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ViewSettings {
+    // Mount point of the mount in pool.toml whose pool is shown.
+    pub of: String,
+    // Directory of that mount shown at the view's mount point.
+    pub subpath: String,
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        Self {
+            of: String::new(),
+            subpath: "/".to_string(),
+        }
+    }
+}
+
+// Options of a single mount, from a [mounts."<mount point>"] table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MountOptions {
+    pub atime: AtimePolicy,
+    // Allocation unit of the pool in bytes, reported as st_blksize; st_blocks
+    // counts whole chunks.
+    pub chunk_size: u32,
+    // Longest file name and path, in bytes, the pool accepts. Longer ones
+    // fail with ENAMETOOLONG before reaching the pool.
+    pub name_max: u32,
+    pub path_max: u32,
+    // Match names ignoring case on lookup while readdir keeps the stored
+    // names, for re-exporting the mount to Windows clients.
+    pub case_insensitive: bool,
+    // Unicode form names are matched and listed in.
+    pub normalization: Normalization,
+    // Glob patterns of paths hidden from this mount, e.g. ["*.key", "internal/**"].
+    pub hide: HiddenPaths,
+    // Backend calls per second this mount may make; 0 means unlimited.
+    pub max_iops: u32,
+    // Threads serving metadata requests (lookup, getattr, readdir, ...) and
+    // data requests (read, write) to this mount. The two kinds are queued
+    // separately, so large transfers do not hold up listings.
+    pub metadata_workers: u32,
+    pub data_workers: u32,
+    // Requests each of the two queues holds before reading further requests
+    // from the kernel waits for a free slot.
+    pub queue_depth: u32,
+    // Serve requests from interactive processes before those from processes
+    // running with a positive nice value or in the idle I/O class.
+    pub prioritize_interactive: bool,
+    // Share of the workers each uid gets while several wait, e.g.
+    // { 0 = 4, 1000 = 2 }. Uids not listed have weight 1.
+    pub uid_weights: UidWeights,
+    // Threads of a tokio runtime of this mount's own that its backend calls
+    // run on, so a pool with pathological latency cannot tie up the runtime
+    // the other mounts share; 0 shares the daemon's runtime.
+    pub backend_threads: u32,
+    // Report 2 plus the number of subdirectories as a directory's link
+    // count, at the cost of listing it on every stat; otherwise 1.
+    pub count_subdirs: bool,
+    // Skip the health check at mount time and start the worker threads on
+    // the first request, so a mount nobody uses holds no threads but its
+    // FUSE session's.
+    pub lazy: bool,
+    // Release what a mount holds once it has had no requests for this long:
+    // its worker threads exit and the listings kept for open directories are
+    // dropped, both coming back with the next request. 0 never releases them.
+    pub idle_timeout_secs: u64,
+    // Per-user homes, e.g. "/users/%u": the mount shows the directory of
+    // the pool before %u, and each user sees and reaches only the entry in
+    // it named after their user name. Empty shows the pool as it is.
+    pub home: String,
+    // Account operations and bytes per requesting process as well as per
+    // uid, for the processes seen most recently.
+    pub account_pids: bool,
+    // Whether readdir also returns each entry's attributes, saving the
+    // lookups `ls -l` and `find` would make; "auto" leaves it to the kernel
+    // to switch between the two as it sees the directory used.
+    pub readdirplus: ReaddirPlus,
+    // Let the kernel send lookups and readdirs of one directory concurrently
+    // instead of one at a time.
+    pub parallel_dirops: bool,
+    // Let every user use the mount, not only the one running rfs-fuse and
+    // root. Without root, /etc/fuse.conf has to allow it with
+    // user_allow_other. Mounts with per-user homes always do.
+    pub allow_other: bool,
+    // Who decides whether a request may go ahead.
+    pub permissions: PermissionChecks,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    // Names are matched and listed exactly as stored.
+    #[default]
+    None,
+    // Composed form, as created by Linux and Windows tools.
+    Nfc,
+    // Decomposed form, as created by macOS.
+    Nfd,
+}
+
+impl Default for MountOptions {
+    fn default() -> Self {
+        Self {
+            atime: AtimePolicy::default(),
+            chunk_size: 4096,
+            name_max: 255,
+            path_max: 4096,
+            case_insensitive: false,
+            normalization: Normalization::None,
+            hide: HiddenPaths::default(),
+            max_iops: 0,
+            metadata_workers: 8,
+            data_workers: 4,
+            queue_depth: 256,
+            prioritize_interactive: true,
+            uid_weights: UidWeights::default(),
+            backend_threads: 0,
+            count_subdirs: false,
+            lazy: false,
+            idle_timeout_secs: 300,
+            home: String::new(),
+            account_pids: false,
+            readdirplus: ReaddirPlus::Auto,
+            parallel_dirops: true,
+            allow_other: false,
+            permissions: PermissionChecks::Daemon,
+        }
+    }
+}
+
+impl MountOptions {
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_timeout_secs > 0).then(|| Duration::from_secs(self.idle_timeout_secs))
+    }
+}
+
+// Where access to a mount's entries is checked.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionChecks {
+    // rfs-fuse itself, which enforces per-user homes and hidden paths and
+    // otherwise lets every user the mount admits read everything.
+    #[default]
+    Daemon,
+    // The kernel as well, against the owner and mode bits rfs-fuse reports
+    // (the default_permissions mount option).
+    Kernel,
+}
+
+// When readdir returns attributes along with the names.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReaddirPlus {
+    Off,
+    // The kernel asks for attributes when it expects them to be used.
+    #[default]
+    Auto,
+    Always,
+}
+
+// When access times are updated, as with the mount options of the same names.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AtimePolicy {
+    // atime is reported as the modification time and never changes.
+    #[default]
+    Noatime,
+    // atime is updated on access at most once a day.
+    Relatime,
+    // atime is updated on every access.
+    Strictatime,
+}
+
+// Controls how mount failures at startup are handled.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartupSettings {
+    // Log and skip mounts that fail instead of aborting the whole run.
+    pub continue_on_failure: bool,
+    // Keep retrying mounts whose pool is not available yet instead of failing them.
+    pub retry_unavailable_pools: bool,
+    // Probe every pool before exposing its mount; a pool failing the probe
+    // counts as unavailable.
+    pub health_check: bool,
+    pub retry_initial_backoff_ms: u64,
+    pub retry_max_backoff_secs: u64,
+}
+
+impl Default for StartupSettings {
+    fn default() -> Self {
+        Self {
+            continue_on_failure: false,
+            retry_unavailable_pools: true,
+            health_check: true,
+            retry_initial_backoff_ms: 2000,
+            retry_max_backoff_secs: 60,
+        }
+    }
+}
+
+impl StartupSettings {
+    pub fn retry_initial_backoff(&self) -> Duration {
+        Duration::from_millis(self.retry_initial_backoff_ms)
+    }
+
+    pub fn retry_max_backoff(&self) -> Duration {
+        Duration::from_secs(self.retry_max_backoff_secs)
+    }
+}
+
+// Controls how long shutdown waits for busy mounts before detaching them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownSettings {
+    // Total time to keep retrying a regular unmount while the mount is busy,
+    // after which the mount is detached lazily.
+    pub unmount_timeout_secs: u64,
+    // Pause between two unmount attempts.
+    pub retry_interval_ms: u64,
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        Self {
+            unmount_timeout_secs: 10,
+            retry_interval_ms: 500,
+        }
+    }
+}
+
+impl ShutdownSettings {
+    pub fn unmount_timeout(&self) -> Duration {
+        Duration::from_secs(self.unmount_timeout_secs)
+    }
+
+    pub fn retry_interval(&self) -> Duration {
+        Duration::from_millis(self.retry_interval_ms)
+    }
+}
+
+// Controls detection and re-establishment of FUSE sessions that died.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SupervisorSettings {
+    pub enabled: bool,
+    // How often session threads are checked.
+    pub check_interval_ms: u64,
+    // First delay between remount attempts; doubled after every failure.
+    pub initial_backoff_ms: u64,
+    pub max_backoff_secs: u64,
+}
+
+impl Default for SupervisorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_interval_ms: 1000,
+            initial_backoff_ms: 1000,
+            max_backoff_secs: 60,
+        }
+    }
+}
+
+impl SupervisorSettings {
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_millis(self.check_interval_ms)
+    }
+
+    pub fn initial_backoff(&self) -> Duration {
+        Duration::from_millis(self.initial_backoff_ms)
+    }
+
+    pub fn max_backoff(&self) -> Duration {
+        Duration::from_secs(self.max_backoff_secs)
+    }
+}
+
+// Controls how changes to pool.toml are picked up at runtime.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReloadSettings {
+    // Watch pool.toml with inotify in addition to reloading on SIGHUP.
+    pub watch_pool_config: bool,
+    // Quiet period after a change before pool.toml is read again.
+    pub debounce_ms: u64,
+}
+
+impl Default for ReloadSettings {
+    fn default() -> Self {
+        Self {
+            watch_pool_config: false,
+            debounce_ms: 500,
+        }
+    }
+}
+
+impl ReloadSettings {
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+}
+
+// Limits applied to every librfs call made while serving FUSE requests.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendSettings {
+    // Requests whose backend call takes longer fail with ETIMEDOUT; 0 waits forever.
+    pub timeout_ms: u64,
+    // Extra attempts for calls failing with a transient error; 0 disables retries.
+    pub retry_attempts: u32,
+    // First delay between attempts; doubled after every failure, with jitter.
+    pub retry_initial_backoff_ms: u64,
+    pub retry_max_backoff_ms: u64,
+    // No retry is started once this much time has passed since the first attempt.
+    pub retry_budget_ms: u64,
+    // How long calls that lost the connection to the pool, for example
+    // because rfsd restarted, keep retrying in place of the retry budget;
+    // 0 treats them like other transient errors.
+    pub reconnect_wait_ms: u64,
+    // Consecutive failed calls after which a pool's circuit breaker opens; 0 disables it.
+    pub breaker_failure_threshold: u32,
+    // How long an open breaker fails requests before letting a probe through.
+    pub breaker_open_secs: u64,
+    // Share of the pool's capacity reported as unavailable, like the blocks
+    // ext4 reserves for root.
+    pub reserve_percent: u8,
+    // What happens after `errors_threshold` consecutive failed calls, like
+    // ext4's errors= mount option.
+    pub errors: ErrorPolicy,
+    pub errors_threshold: u32,
+}
+
+impl Default for BackendSettings {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 10_000,
+            retry_attempts: 3,
+            retry_initial_backoff_ms: 50,
+            retry_max_backoff_ms: 1000,
+            retry_budget_ms: 5000,
+            reconnect_wait_ms: 30_000,
+            breaker_failure_threshold: 5,
+            breaker_open_secs: 30,
+            reserve_percent: 5,
+            errors: ErrorPolicy::Continue,
+            errors_threshold: 10,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorPolicy {
+    #[default]
+    Continue,
+    // Switch the mount to read-only, rejecting mutations with EROFS.
+    RemountRo,
+}
+
+impl BackendSettings {
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_ms > 0).then(|| Duration::from_millis(self.timeout_ms))
+    }
+
+    pub fn retry_initial_backoff(&self) -> Duration {
+        Duration::from_millis(self.retry_initial_backoff_ms)
+    }
+
+    pub fn retry_max_backoff(&self) -> Duration {
+        Duration::from_millis(self.retry_max_backoff_ms)
+    }
+
+    pub fn retry_budget(&self) -> Duration {
+        Duration::from_millis(self.retry_budget_ms)
+    }
+
+    pub fn reconnect_wait(&self) -> Duration {
+        Duration::from_millis(self.reconnect_wait_ms)
+    }
+
+    pub fn breaker_open(&self) -> Duration {
+        Duration::from_secs(self.breaker_open_secs)
+    }
+}
+
+// Background verification of the metadata of every mounted pool.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScrubSettings {
+    pub enabled: bool,
+    // Pause between the end of one run over all mounts and the start of the next.
+    pub interval_hours: u64,
+    // Upper bound on directories listed per second, to limit the load on the pool.
+    pub directories_per_sec: u32,
+}
+
+impl Default for ScrubSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: 24,
+            directories_per_sec: 20,
+        }
+    }
+}
+
+impl ScrubSettings {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_hours * 3600)
+    }
+
+    pub fn pause(&self) -> Duration {
+        Duration::from_secs(1) / self.directories_per_sec.max(1)
+    }
+}
+
+// How mounts are attached to the kernel.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FuseSettings {
+    pub mount_method: MountMethod,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MountMethod {
+    // The fusermount helper when it is installed, otherwise mount(2) if
+    // running as root.
+    #[default]
+    Auto,
+    // The setuid fusermount3 or fusermount helper, which also unmounts a
+    // mount whose daemon died.
+    Fusermount,
+    // mount(2) on /dev/fuse directly, which needs CAP_SYS_ADMIN but no
+    // helper binary.
+    Direct,
+}
+
+// Detection of FUSE operations that run far longer than they should.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogSettings {
+    pub enabled: bool,
+    // Operations running longer than this are reported as stuck.
+    pub stuck_secs: u64,
+    // Also abort the backend call a stuck operation waits on; the operation
+    // then fails with ETIMEDOUT.
+    pub abort: bool,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            stuck_secs: 60,
+            abort: false,
+        }
+    }
+}
+
+impl WatchdogSettings {
+    pub fn stuck_after(&self) -> Duration {
+        Duration::from_secs(self.stuck_secs.max(1))
+    }
+}
+
+// Location of the control socket used by administration tools. It defaults
+// to /run/rfs, or $XDG_RUNTIME_DIR/rfs for a user other than root.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlSettings {
+    pub enabled: bool,
+    pub socket_path: String,
+    // Accept mount and unmount commands, e.g. from a CSI node plugin. The
+    // daemon then keeps running even if pool.toml defines no mounts.
+    pub mounts: bool,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            socket_path: user_dirs::control_socket(),
+            mounts: false,
+        }
+    }
+}
+
+// Output format, destination and per-subsystem verbosity of the log.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    pub format: LogFormat,
+    pub target: LogTarget,
+    pub file: LogFileSettings,
+    // Subsystem name (daemon, mount, fuse, control) to level; others use the
+    // level from config.toml.
+    pub levels: BTreeMap<String, String>,
+    // Warnings and errors reporting the same error on the same mount are
+    // written at most `repeat_burst` times per `repeat_window_secs`; the rest
+    // are counted and summarized. 0 in either disables the limit.
+    pub repeat_burst: u32,
+    pub repeat_window_secs: u64,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            target: LogTarget::default(),
+            file: LogFileSettings::default(),
+            levels: BTreeMap::new(),
+            repeat_burst: 5,
+            repeat_window_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    #[default]
+    Stderr,
+    // A log file rotated according to [log.file].
+    File,
+    Syslog,
+    // The native journald protocol, keeping structured fields.
+    Journald,
+}
+
+// Location and rotation limits of the log file. A limit of 0 disables it.
+// The file defaults to /var/log/rfs, or $XDG_STATE_HOME/rfs for a user other
+// than root.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogFileSettings {
+    pub path: String,
+    pub max_size_mb: u64,
+    pub max_age_hours: u64,
+    // Number of rotated files kept next to the active one.
+    pub keep: u32,
+}
+
+impl Default for LogFileSettings {
+    fn default() -> Self {
+        Self {
+            path: match user_dirs::unprivileged() {
+                false => "/var/log/rfs/rfs-fuse.log".to_string(),
+                true => {
+                    let dir = user_dirs::base_dir("XDG_STATE_HOME", ".local/state");
+                    dir.join("rfs-fuse.log").to_string_lossy().into_owned()
+                }
+            },
+            max_size_mb: 64,
+            max_age_hours: 24,
+            keep: 5,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // One JSON object per line, for log pipelines.
+    Json,
+}
+
+// Loads settings from `path`, falling back to defaults when the file does not
+// exist, and applies overrides from the environment.
+pub fn load_settings(path: &str) -> Result<Settings, FuseError> {
+    let mut settings: Settings = match fs::read_to_string(path) {
+        Ok(contents) => validate::parse_settings(path, &contents)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => Settings::default(),
+        Err(e) => return Err(e.into()),
+    };
+    settings.apply_env()?;
+    Ok(settings)
+}
+
+impl Settings {
+    fn apply_env(&mut self) -> Result<(), FuseError> {
+        if let Some(socket_path) = env_value("RFS_FUSE_CONTROL_SOCKET") {
+            self.control.socket_path = socket_path;
+        }
+        if let Some(value) = env_value("RFS_FUSE_CONTINUE_ON_FAILURE") {
+            self.startup.continue_on_failure = parse_bool("RFS_FUSE_CONTINUE_ON_FAILURE", &value)?;
+        }
+        if let Some(value) = env_value("RFS_FUSE_WATCH_POOL_CONFIG") {
+            self.reload.watch_pool_config = parse_bool("RFS_FUSE_WATCH_POOL_CONFIG", &value)?;
+        }
+        Ok(())
+    }
+}
+
+fn env_value(var: &str) -> Option<String> {
+    let value = env::var(var).ok().filter(|value| !value.is_empty())?;
+    log(Subsystem::Daemon, LogLevel::Info, &format!("Using {}={} from the environment", var, value));
+    Some(value)
+}
+
+fn parse_bool(var: &str, value: &str) -> Result<bool, FuseError> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(FuseError::Config(format!("{} must be a boolean, got '{}'", var, value))),
+    }
+}
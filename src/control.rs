@@ -0,0 +1,302 @@
+// src/control.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::config::{ControlSettings, Paths, ShutdownSettings};
+use crate::error::FuseError;
+use crate::logging::{log, Subsystem};
+use crate::mount::{find_pool, MountManager, MountSpec};
+use crate::stats::Registry;
+use rfs_utils::LogLevel;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+
+// A running control socket. Dropping it stops the listener and removes the socket file.
+pub struct ControlServer {
+    path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// What the mount and unmount commands act on, given when they are enabled.
+pub struct MountControl {
+    pub manager: Arc<MountManager>,
+    pub shutdown: ShutdownSettings,
+}
+
+// Binds the control socket and serves one command per connection. `paths`
+// are the configuration files the daemon read, reported by `version`.
+pub fn spawn(
+    settings: &ControlSettings,
+    registry: Arc<Registry>,
+    paths: &Paths,
+    mounts: Option<MountControl>,
+) -> io::Result<ControlServer> {
+    let path = PathBuf::from(&settings.socket_path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // A socket left behind by a previous run would make bind fail.
+    remove_stale_socket(&path)?;
+
+    let listener = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o660))?;
+    log(Subsystem::Control, LogLevel::Info, &format!("Control socket listening on {}", path.display()));
+
+    let mounts = mounts.map(Arc::new);
+    let version: Arc<str> = render_version(paths).into();
+    let task = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let registry = Arc::clone(&registry);
+                    let mounts = mounts.clone();
+                    let version = Arc::clone(&version);
+                    tokio::spawn(async move {
+                        if let Err(e) = serve(stream, &registry, &version, mounts.as_deref()).await {
+                            log(Subsystem::Control, LogLevel::Warn, &format!("Control connection failed: {}", e));
+                        }
+                    });
+                }
+                Err(e) => log(Subsystem::Control, LogLevel::Warn, &format!("Control socket accept failed: {}", e)),
+            }
+        }
+    });
+    Ok(ControlServer { path, task })
+}
+
+// The version and the configuration files in use, one `<name> <path>` line
+// each, which is where support bundles read them from.
+fn render_version(paths: &Paths) -> String {
+    format!(
+        "rfs-fuse {}\nconfig {}\npool_config {}\nsettings {}\n",
+        env!("CARGO_PKG_VERSION"),
+        paths.config,
+        paths.pool_config,
+        paths.settings
+    )
+}
+
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+async fn serve(
+    stream: UnixStream,
+    registry: &Registry,
+    version: &str,
+    mounts: Option<&MountControl>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    let response = handle_command(line.trim(), registry, version, mounts).await;
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+async fn handle_command(
+    command: &str,
+    registry: &Registry,
+    version: &str,
+    mounts: Option<&MountControl>,
+) -> String {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let response = match (words.as_slice(), mounts) {
+        (["status"], _) => return registry.render_status(),
+        (["version"], _) => return version.to_string(),
+        (["metrics"], _) => return registry.render_metrics(),
+        (["ops"], _) => return registry.render_ops(),
+        (["io"], _) => return registry.render_io(None),
+        (["io", mount_point], _) => return registry.render_io(Some(mount_point.trim_end_matches('/'))),
+        (["errors"], _) => return registry.render_errors(None),
+        (["errors", mount_point], _) => return registry.render_errors(Some(mount_point.trim_end_matches('/'))),
+        (["stats", "--json"], _) => return registry.render_json(None),
+        (["stats", "--json", mount_point], _) => return registry.render_json(Some(mount_point.trim_end_matches('/'))),
+        (["stats"], _) => return registry.render_stats(None),
+        (["stats", mount_point], _) => return registry.render_stats(Some(mount_point.trim_end_matches('/'))),
+        (["reset"], _) => return registry.reset_counters(None),
+        (["reset", mount_point], _) => return registry.reset_counters(Some(mount_point.trim_end_matches('/'))),
+        (["mount", ..] | ["unmount", ..], None) => {
+            return "error: disabled: mount commands are not enabled ([control] mounts)\n".to_string();
+        }
+        (["mount", pool_id, mount_point, flags @ ..], Some(mounts)) => mount(mounts, pool_id, mount_point, flags).await,
+        (["unmount", mount_point], Some(mounts)) => {
+            // Spelled as mount spells the mount points it records.
+            let mount_point = mount_point.trim_end_matches('/');
+            let unmounted = mounts.manager.unmount_managed(mount_point, &mounts.shutdown).await;
+            unmounted.map(|unmounted| if unmounted { "unmounted" } else { "not mounted" })
+        }
+        _ => return format!("error: unknown command '{}'\n", command),
+    };
+    match response {
+        Ok(outcome) => format!("ok: {}\n", outcome),
+        Err(e) => format!("error: {}: {}\n", error_code(&e), e),
+    }
+}
+
+// `mount <pool-id> <mount point> [subpath=<dir>] [root=<dir>] [ro]`.
+// Mounting the same volume at the same mount point again succeeds without
+// doing anything. With root= the pool need not be in pool.toml.
+async fn mount(
+    mounts: &MountControl,
+    pool_id: &str,
+    mount_point: &str,
+    flags: &[&str],
+) -> Result<&'static str, FuseError> {
+    let mut subpath = "/".to_string();
+    let mut root = None;
+    let mut read_only = false;
+    for flag in flags {
+        match flag.split_once('=') {
+            Some(("subpath", dir)) => subpath = dir.to_string(),
+            Some(("root", dir)) => root = Some(dir.to_string()),
+            None if *flag == "ro" => read_only = true,
+            _ => return Err(FuseError::MountConfig(format!("unknown mount flag '{}'", flag))),
+        }
+    }
+    // Mount points are compared by their components, so one spelled with
+    // .. could slip past the overlap checks.
+    let paths = [("mount point", mount_point), ("subpath", subpath.as_str())];
+    for (what, path) in paths.into_iter().chain(root.as_deref().map(|root| ("root", root))) {
+        if !is_plain_absolute(path) {
+            return Err(FuseError::MountConfig(format!("{} '{}' must be absolute and without . or ..", what, path)));
+        }
+    }
+    let (pool_id, pool_root) = match root {
+        Some(root) => pool_at(mounts, pool_id, root.trim_end_matches('/')).await?,
+        None => find_pool(mounts.manager.pool_config_path(), pool_id).await?,
+    };
+    if !Path::new(&pool_root).is_dir() {
+        return Err(FuseError::PoolUnavailable(format!("Pool '{}' is not present at '{}'", pool_id, pool_root)));
+    }
+    let subpath = match subpath.trim_end_matches('/') {
+        "" => "/".to_string(),
+        dir => dir.to_string(),
+    };
+    let spec = MountSpec {
+        mount_point: mount_point.trim_end_matches('/').to_string(),
+        pool_id,
+        pool_root,
+        subpath,
+        read_only,
+        managed: true,
+    };
+    let mounted = mounts.manager.mount_managed(spec).await?;
+    Ok(if mounted { "mounted" } else { "already mounted" })
+}
+
+// Pool `pool_id` at the directory `root` given with the mount command. Mounts
+// of one pool share their view and lock, so the id must not stand for
+// another directory in pool.toml or in a mount already made.
+async fn pool_at(mounts: &MountControl, pool_id: &str, root: &str) -> Result<(u64, String), FuseError> {
+    let id: u64 = pool_id
+        .parse()
+        .map_err(|_| FuseError::MountConfig(format!("'{}' is not a pool id", pool_id)))?;
+    let configured = find_pool(mounts.manager.pool_config_path(), pool_id).await.ok().map(|(_, root)| root);
+    let mounted = mounts.manager.mounted().into_iter().map(|(spec, _)| spec).find(|spec| spec.pool_id == id);
+    let mounted = mounted.map(|spec| spec.pool_root);
+    match configured.or(mounted) {
+        Some(other) if other.trim_end_matches('/') != root => Err(FuseError::MountConflict(format!(
+            "pool '{}' is at '{}', not '{}'",
+            id, other, root
+        ))),
+        _ => Ok((id, if root.is_empty() { "/".to_string() } else { root.to_string() })),
+    }
+}
+
+fn is_plain_absolute(path: &str) -> bool {
+    path.starts_with('/') && !path.split('/').any(|part| part == "." || part == "..")
+}
+
+// Class of a failed command, so callers can tell retryable failures from
+// requests that will never succeed.
+fn error_code(err: &FuseError) -> &'static str {
+    match err {
+        FuseError::MountConfig(_) | FuseError::Config(_) => "invalid",
+        FuseError::PoolUnavailable(_) => "unavailable",
+        FuseError::MountConflict(_) => "conflict",
+        FuseError::Io(_) | FuseError::Pool(_) | FuseError::Metadata(_) => "failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{handle_command, is_plain_absolute, MountControl};
+    use crate::mount::{MountEntry, MountManager};
+    use crate::stats::Registry;
+    use crate::testing::skip_unmounted;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn unmount_removes_only_mounts_made_with_mount_however_spelled() {
+        let dir = std::env::temp_dir().join(format!("rfs-control-unmount-{}", std::process::id()));
+        let (pool, managed, configured) = (dir.join("pool"), dir.join("managed"), dir.join("configured"));
+        std::fs::create_dir_all(&pool).unwrap();
+        std::fs::create_dir_all(&configured).unwrap();
+        let registry = Arc::new(Registry::default());
+        let manager = Arc::new(MountManager::new(
+            Arc::clone(&registry),
+            String::new(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            BTreeMap::new(),
+            BTreeMap::new(),
+        ));
+        let mounts = MountControl {
+            manager: Arc::clone(&manager),
+            shutdown: Default::default(),
+        };
+        let runtime = Runtime::new().unwrap();
+        let command = |command: String| runtime.block_on(handle_command(&command, &registry, "", Some(&mounts)));
+
+        let mounted = command(format!("mount 7 {}/ root={} ro", managed.display(), pool.display()));
+        let from_pool_toml = MountEntry {
+            mount_point: configured.to_string_lossy().into_owned(),
+            pool_id: 7,
+            pool_root: Some(pool.to_string_lossy().into_owned()),
+            subpath: "/".to_string(),
+            read_only: true,
+        };
+        runtime.block_on(manager.mount_entries(vec![from_pool_toml]));
+        if mounted != "ok: mounted\n" || manager.mounted_count() != 2 {
+            skip_unmounted(&managed, &mounted.trim_end());
+        } else {
+            let refused = command(format!("unmount {}", configured.display()));
+            assert!(refused.starts_with("error: conflict: "), "{}", refused);
+            assert_eq!(command(format!("unmount {}/", managed.display())), "ok: unmounted\n");
+            assert_eq!(command(format!("unmount {}", managed.display())), "ok: not mounted\n");
+            assert_eq!(manager.mounted_count(), 1);
+        }
+        runtime.block_on(manager.shutdown(&Default::default()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mount_paths_must_be_absolute_without_dot_components() {
+        assert!(is_plain_absolute("/mnt/a"));
+        assert!(is_plain_absolute("/mnt/a..b/"));
+        assert!(!is_plain_absolute("mnt/a"));
+        assert!(!is_plain_absolute("/mnt/x/../a"));
+        assert!(!is_plain_absolute("/mnt/./a"));
+    }
+}
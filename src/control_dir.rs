@@ -0,0 +1,99 @@
+// src/control_dir.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::stats::MountStats;
+use fuser::{FileAttr, FileType};
+use nix::unistd::{Gid, Uid};
+use std::ffi::OsStr;
+use std::time::SystemTime;
+
+// The virtual `.rfs` directory at the root of every mount. It does not exist
+// in the pool and shadows a pool entry of the same name. Lookups find it but
+// readdir does not list it, so tools walking the mount do not descend into it.
+pub const NAME: &str = ".rfs";
+
+// Inodes handed out for pool entries count up from the root, so the virtual
+// entries take the top half of the range.
+pub const DIR_INODE: u64 = 1 << 63;
+
+// Read-only files in `.rfs`. Their content is rendered when they are opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlFile {
+    // State and counters of the mount.
+    Stats,
+    // Progress of the running scrub, or the result of the last one.
+    Scrub,
+    // The latest failed backend calls.
+    Errors,
+    // Operations and bytes by requesting uid and process.
+    Io,
+}
+
+impl ControlFile {
+    pub const ALL: [ControlFile; 4] = [ControlFile::Stats, ControlFile::Scrub, ControlFile::Errors, ControlFile::Io];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ControlFile::Stats => "stats",
+            ControlFile::Scrub => "scrub",
+            ControlFile::Errors => "errors",
+            ControlFile::Io => "io",
+        }
+    }
+
+    pub fn inode(self) -> u64 {
+        let index = ControlFile::ALL.iter().position(|f| *f == self).unwrap_or(0);
+        DIR_INODE + 1 + index as u64
+    }
+
+    pub fn from_inode(ino: u64) -> Option<Self> {
+        ControlFile::ALL.into_iter().find(|f| f.inode() == ino)
+    }
+
+    pub fn from_name(name: &OsStr) -> Option<Self> {
+        ControlFile::ALL.into_iter().find(|f| OsStr::new(f.name()) == name)
+    }
+
+    pub fn render(self, stats: &MountStats) -> String {
+        match self {
+            ControlFile::Stats => stats.render(),
+            ControlFile::Scrub => stats.scrub().render(),
+            ControlFile::Errors => stats.render_errors(),
+            ControlFile::Io => stats.render_io(),
+        }
+    }
+}
+
+pub fn is_control_inode(ino: u64) -> bool {
+    ino >= DIR_INODE
+}
+
+pub fn dir_attr() -> FileAttr {
+    attr(DIR_INODE, FileType::Directory, 0, 0o555)
+}
+
+pub fn file_attr(file: ControlFile, size: u64) -> FileAttr {
+    attr(file.inode(), FileType::RegularFile, size, 0o444)
+}
+
+fn attr(ino: u64, kind: FileType, size: u64, perm: u16) -> FileAttr {
+    let now = SystemTime::now();
+    FileAttr {
+        ino,
+        size,
+        blocks: size.div_ceil(512),
+        atime: now,
+        mtime: now,
+        ctime: now,
+        crtime: now,
+        kind,
+        perm,
+        nlink: if kind == FileType::Directory { 2 } else { 1 },
+        uid: Uid::current().as_raw(),
+        gid: Gid::current().as_raw(),
+        rdev: 0,
+        flags: 0,
+        blksize: 512,
+    }
+}
@@ -0,0 +1,111 @@
+// src/dir_snapshot.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use fuser::FileType;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// Cookies 1 and 2 belong to "." and "..".
+const FIRST_COOKIE: i64 = 3;
+// Cookies are offsets handed to the kernel, so they must stay positive.
+const COOKIE_MASK: u64 = (1 << 62) - 1;
+
+pub struct DirEntry {
+    pub cookie: i64,
+    pub ino: u64,
+    pub kind: FileType,
+    pub name: String,
+}
+
+// The entries of a directory as listed when a readdir stream started, in
+// cookie order. A cookie is derived from the entry's name rather than its
+// position, so a stream resumed at a cookie, even against a newer listing,
+// neither repeats nor skips the entries present in both.
+pub struct DirSnapshot {
+    entries: Vec<DirEntry>,
+}
+
+impl DirSnapshot {
+    pub fn new(entries: impl IntoIterator<Item = (u64, FileType, String)>) -> Self {
+        let mut entries: Vec<DirEntry> = entries
+            .into_iter()
+            .map(|(ino, kind, name)| DirEntry {
+                cookie: cookie_of(&name),
+                ino,
+                kind,
+                name,
+            })
+            .collect();
+        // Names hashing to the same cookie are ordered by name and moved to
+        // the following free cookies, so every cookie names one entry.
+        entries.sort_by(|a, b| (a.cookie, &a.name).cmp(&(b.cookie, &b.name)));
+        for i in 1..entries.len() {
+            if entries[i].cookie <= entries[i - 1].cookie {
+                entries[i].cookie = entries[i - 1].cookie + 1;
+            }
+        }
+        Self { entries }
+    }
+
+    // Entries after the one `cookie` was handed out for; 0 starts over.
+    pub fn after(&self, cookie: i64) -> &[DirEntry] {
+        let start = self.entries.partition_point(|entry| entry.cookie <= cookie);
+        &self.entries[start..]
+    }
+}
+
+fn cookie_of(name: &str) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    ((hasher.finish() & COOKIE_MASK) as i64).max(FIRST_COOKIE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DirSnapshot;
+    use fuser::FileType;
+
+    fn snapshot(names: &[&str]) -> DirSnapshot {
+        DirSnapshot::new(names.iter().enumerate().map(|(i, name)| (i as u64 + 2, FileType::RegularFile, name.to_string())))
+    }
+
+    fn names(entries: &[super::DirEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn resuming_at_a_cookie_continues_after_its_entry() {
+        let snapshot = snapshot(&["a", "b", "c", "d"]);
+        let all = snapshot.after(0);
+        assert_eq!(all.len(), 4);
+        assert_eq!(names(snapshot.after(all[1].cookie)), names(&all[2..]));
+        assert!(snapshot.after(all[3].cookie).is_empty());
+    }
+
+    #[test]
+    fn cookies_survive_a_changed_listing() {
+        let before = snapshot(&["a", "b", "c", "d", "e"]);
+        let read = &before.after(0)[..2];
+        let resume = read[1].cookie;
+        // One entry already returned and one not yet returned are gone, and
+        // a new one appeared.
+        let gone = [read[0].name.as_str(), before.after(resume)[0].name.as_str()];
+        let remaining: Vec<&str> = ["a", "b", "c", "d", "e", "f"].into_iter().filter(|name| !gone.contains(name)).collect();
+        let after = snapshot(&remaining);
+
+        let mut seen = names(read);
+        seen.extend(names(after.after(resume)));
+        for name in ["a", "b", "c", "d", "e"].iter().filter(|name| !gone.contains(name)) {
+            assert_eq!(seen.iter().filter(|seen| *seen == name).count(), 1, "{} listed once", name);
+        }
+    }
+
+    #[test]
+    fn cookies_are_unique_and_positive() {
+        let names: Vec<String> = (0..10_000).map(|i| format!("file-{}", i)).collect();
+        let snapshot = DirSnapshot::new(names.into_iter().map(|name| (2, FileType::RegularFile, name)));
+        let entries = snapshot.after(0);
+        assert!(entries[0].cookie >= 3);
+        assert!(entries.windows(2).all(|pair| pair[0].cookie < pair[1].cookie));
+    }
+}
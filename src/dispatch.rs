@@ -0,0 +1,480 @@
+// src/dispatch.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub type Job = Box<dyn FnOnce() + Send>;
+
+// Runs FUSE operations on a pool of worker threads. Pending operations are
+// queued per requesting uid and served round-robin, so one user issuing many
+// requests cannot starve the others. A uid with weight n is served up to n
+// operations per turn; uids without a weight have weight 1. Interactive
+// operations are queued ahead of batch ones. At most `capacity` operations
+// wait in the queue; further submissions block.
+pub struct Dispatcher {
+    queue: Arc<FairQueue>,
+    name: String,
+    // Most workers running at once.
+    size: usize,
+    // How long a worker waits for an operation before it exits; None keeps
+    // workers until the dispatcher is dropped.
+    idle_timeout: Option<Duration>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    // Operations are only run by `run_next`, on the caller's thread.
+    manual: bool,
+}
+
+impl Dispatcher {
+    // A dispatcher starting all its workers at once. With `idle_timeout` set,
+    // workers that find no operation for that long exit and are started
+    // again as operations arrive.
+    pub fn new(
+        name: &str,
+        workers: usize,
+        capacity: usize,
+        weights: UidWeights,
+        idle_timeout: Option<Duration>,
+    ) -> Self {
+        let dispatcher = Self::on_demand(name, workers, capacity, weights, idle_timeout);
+        for _ in 0..dispatcher.size {
+            dispatcher.start_worker();
+        }
+        dispatcher
+    }
+
+    // A dispatcher that starts no workers until operations are submitted,
+    // then up to `workers` as the queue needs them, so an unused dispatcher
+    // holds no threads.
+    pub fn on_demand(
+        name: &str,
+        workers: usize,
+        capacity: usize,
+        weights: UidWeights,
+        idle_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            queue: Arc::new(FairQueue::new(capacity.max(1), weights)),
+            name: name.to_string(),
+            size: workers.max(1),
+            idle_timeout,
+            workers: Mutex::new(Vec::new()),
+            manual: false,
+        }
+    }
+
+    // A dispatcher without workers whose operations only run when the caller
+    // steps it with `run_next`. Every interleaving is then decided by the
+    // caller, so tests can replay races deterministically.
+    #[cfg(test)]
+    pub fn manual(weights: UidWeights) -> Self {
+        Self {
+            queue: Arc::new(FairQueue::new(usize::MAX, weights)),
+            name: String::new(),
+            size: 0,
+            idle_timeout: None,
+            workers: Mutex::new(Vec::new()),
+            manual: true,
+        }
+    }
+
+    // Queues an operation on behalf of `uid`. Without workers, for example
+    // when none could be spawned, the operation runs on the calling thread.
+    pub fn submit(&self, uid: u32, priority: Priority, job: Job) {
+        if self.manual {
+            self.queue.push(uid, priority, job);
+            return;
+        }
+        self.start_worker_if_needed();
+        if let Err(job) = self.queue.push_for_workers(uid, priority, job) {
+            job();
+        }
+    }
+
+    // Starts another worker unless an idle one can take the next operation
+    // or all of them are running.
+    fn start_worker_if_needed(&self) {
+        let needed = {
+            let state = self.queue.state.lock().unwrap();
+            state.waiting <= state.queued && state.workers < self.size
+        };
+        if needed {
+            self.start_worker();
+        }
+    }
+
+    fn start_worker(&self) {
+        let mut workers = self.workers.lock().unwrap();
+        // Handles of workers that exited while idle are dropped here.
+        workers.retain(|worker| !worker.is_finished());
+        let index = workers.len();
+        self.queue.state.lock().unwrap().workers += 1;
+        let queue = Arc::clone(&self.queue);
+        let idle_timeout = self.idle_timeout;
+        let spawned = thread::Builder::new()
+            .name(format!("{}-{}", self.name, index))
+            .spawn(move || {
+                while let Some(job) = queue.pop(idle_timeout) {
+                    job();
+                }
+            });
+        match spawned {
+            Ok(worker) => workers.push(worker),
+            Err(_) => self.queue.state.lock().unwrap().workers -= 1,
+        }
+    }
+
+    // Workers currently running.
+    #[cfg(test)]
+    pub fn worker_count(&self) -> usize {
+        self.queue.state.lock().unwrap().workers
+    }
+
+    // Runs the operation a worker would take next, on the calling thread.
+    // Returns false if none is queued.
+    #[cfg(test)]
+    pub fn run_next(&self) -> bool {
+        match self.queue.try_pop() {
+            Some(job) => {
+                job();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Dispatcher {
+    // Lets the workers finish queued operations, then joins them.
+    fn drop(&mut self) {
+        self.queue.close();
+        for worker in self.workers.get_mut().unwrap().drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+// Scheduling weights of uids, from a table such as `{ 0 = 4, 1000 = 2 }`.
+// TOML keys are strings, so uids are parsed when the table is loaded.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "BTreeMap<String, u32>")]
+pub struct UidWeights(HashMap<u32, u32>);
+
+impl TryFrom<BTreeMap<String, u32>> for UidWeights {
+    type Error = String;
+
+    fn try_from(table: BTreeMap<String, u32>) -> Result<Self, Self::Error> {
+        table
+            .into_iter()
+            .map(|(uid, weight)| match uid.parse() {
+                Ok(uid) if weight > 0 => Ok((uid, weight)),
+                Ok(_) => Err(format!("weight of uid {} must be at least 1", uid)),
+                Err(_) => Err(format!("'{}' is not a uid", uid)),
+            })
+            .collect::<Result<_, _>>()
+            .map(UidWeights)
+    }
+}
+
+impl UidWeights {
+    pub fn get(&self, uid: u32) -> u32 {
+        self.0.get(&uid).copied().unwrap_or(1)
+    }
+}
+
+struct FairQueue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+    space: Condvar,
+    capacity: usize,
+    weights: UidWeights,
+}
+
+#[derive(Default)]
+struct QueueState {
+    interactive: Lane,
+    batch: Lane,
+    // Interactive jobs taken in a row while batch jobs were waiting.
+    streak: u32,
+    queued: usize,
+    // Workers running, and those of them waiting for a job.
+    workers: usize,
+    waiting: usize,
+    closed: bool,
+}
+
+// Jobs of one priority, queued per uid.
+#[derive(Default)]
+struct Lane {
+    jobs: BTreeMap<u32, VecDeque<Job>>,
+    // Uids with queued jobs, in serving order. The front uid is being served.
+    turns: VecDeque<u32>,
+    // Jobs taken from the front uid during its current turn.
+    served: u32,
+}
+
+impl Lane {
+    fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    fn push(&mut self, uid: u32, job: Job) {
+        let queue = self.jobs.entry(uid).or_default();
+        queue.push_back(job);
+        if queue.len() == 1 {
+            self.turns.push_back(uid);
+        }
+    }
+
+    fn pop(&mut self, weights: &UidWeights) -> Option<Job> {
+        let uid = *self.turns.front()?;
+        let queue = self.jobs.get_mut(&uid)?;
+        let job = queue.pop_front();
+        let remaining = queue.len();
+        if remaining == 0 {
+            self.jobs.remove(&uid);
+        }
+        self.served += 1;
+        if remaining == 0 || self.served >= weights.get(uid) {
+            self.turns.pop_front();
+            self.served = 0;
+            if remaining > 0 {
+                self.turns.push_back(uid);
+            }
+        }
+        job
+    }
+}
+
+impl FairQueue {
+    fn new(capacity: usize, weights: UidWeights) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            available: Condvar::new(),
+            space: Condvar::new(),
+            capacity,
+            weights,
+        }
+    }
+
+    fn push(&self, uid: u32, priority: Priority, job: Job) {
+        self.enqueue(self.state.lock().unwrap(), uid, priority, job);
+    }
+
+    // Queues a job unless no worker is running to take it, in which case
+    // the job is handed back. Checked under the queue's lock, so a worker
+    // exiting while idle cannot leave the job behind.
+    fn push_for_workers(&self, uid: u32, priority: Priority, job: Job) -> Result<(), Job> {
+        let state = self.state.lock().unwrap();
+        if state.workers == 0 {
+            return Err(job);
+        }
+        self.enqueue(state, uid, priority, job);
+        Ok(())
+    }
+
+    fn enqueue(&self, mut state: MutexGuard<'_, QueueState>, uid: u32, priority: Priority, job: Job) {
+        while state.queued >= self.capacity && !state.closed {
+            state = self.space.wait(state).unwrap();
+        }
+        state.queued += 1;
+        match priority {
+            Priority::Interactive => state.interactive.push(uid, job),
+            Priority::Batch => state.batch.push(uid, job),
+        }
+        self.available.notify_one();
+    }
+
+    // Blocks until a job is available. Returns None once the queue is closed
+    // and drained, or when no job came within `idle_timeout`; the calling
+    // worker then counts as exited.
+    fn pop(&self, idle_timeout: Option<Duration>) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = self.take(&mut state) {
+                return Some(job);
+            }
+            if state.closed {
+                state.workers -= 1;
+                return None;
+            }
+            state.waiting += 1;
+            let timed_out = match idle_timeout {
+                Some(timeout) => {
+                    let (guard, wait) = self.available.wait_timeout(state, timeout).unwrap();
+                    state = guard;
+                    wait.timed_out()
+                }
+                None => {
+                    state = self.available.wait(state).unwrap();
+                    false
+                }
+            };
+            state.waiting -= 1;
+            if timed_out && state.queued == 0 {
+                state.workers -= 1;
+                return None;
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn try_pop(&self) -> Option<Job> {
+        self.take(&mut self.state.lock().unwrap())
+    }
+
+    // Takes the next job in scheduling order. Interactive jobs go first, but
+    // every BATCH_SHARE-th job is a batch one so batch traffic is slowed
+    // rather than stalled.
+    fn take(&self, state: &mut QueueState) -> Option<Job> {
+        let batch_turn =
+            state.interactive.is_empty() || (!state.batch.is_empty() && state.streak + 1 >= BATCH_SHARE);
+        let job = if batch_turn {
+            state.streak = 0;
+            state.batch.pop(&self.weights)
+        } else {
+            if !state.batch.is_empty() {
+                state.streak += 1;
+            }
+            state.interactive.pop(&self.weights)
+        }?;
+        state.queued -= 1;
+        self.space.notify_one();
+        Some(job)
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.available.notify_all();
+        self.space.notify_all();
+    }
+}
+
+// How requests are ordered against other requests waiting in a queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Interactive,
+    // Background work such as backups and indexers.
+    Batch,
+}
+
+// One in this many jobs is taken from the batch lane while both lanes wait.
+const BATCH_SHARE: u32 = 8;
+
+const IOPRIO_CLASS_SHIFT: i64 = 13;
+const IOPRIO_CLASS_IDLE: i64 = 3;
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+// Classifies a request by the process that sent it: processes in the idle
+// I/O class (`ionice -c3`) or with a positive nice value are batch. Requests
+// whose process cannot be inspected, such as ones from the kernel itself or
+// from processes that already exited, are interactive.
+pub fn priority_of(pid: u32) -> Priority {
+    if pid == 0 {
+        return Priority::Interactive;
+    }
+    let ioprio = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, pid as libc::c_int) };
+    if ioprio >= 0 && ioprio >> IOPRIO_CLASS_SHIFT == IOPRIO_CLASS_IDLE {
+        return Priority::Batch;
+    }
+    // A failed lookup also returns -1, which counts as interactive either way.
+    let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, pid as libc::id_t) };
+    if nice > 0 { Priority::Batch } else { Priority::Interactive }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dispatcher, Priority, UidWeights};
+    use std::collections::BTreeMap;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Queues `count` jobs for `uid` that record their uid when run.
+    fn submit(dispatcher: &Dispatcher, log: &Arc<Mutex<Vec<u32>>>, uid: u32, priority: Priority, count: usize) {
+        for _ in 0..count {
+            let log = Arc::clone(log);
+            dispatcher.submit(uid, priority, Box::new(move || log.lock().unwrap().push(uid)));
+        }
+    }
+
+    fn run_all(dispatcher: &Dispatcher, log: &Arc<Mutex<Vec<u32>>>) -> Vec<u32> {
+        while dispatcher.run_next() {}
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    fn weights(table: &[(&str, u32)]) -> UidWeights {
+        let table: BTreeMap<String, u32> = table.iter().map(|(uid, weight)| (uid.to_string(), *weight)).collect();
+        UidWeights::try_from(table).unwrap()
+    }
+
+    #[test]
+    fn uids_are_served_round_robin() {
+        let dispatcher = Dispatcher::manual(UidWeights::default());
+        let log = Arc::new(Mutex::new(Vec::new()));
+        submit(&dispatcher, &log, 1000, Priority::Interactive, 4);
+        submit(&dispatcher, &log, 1001, Priority::Interactive, 2);
+        assert_eq!(run_all(&dispatcher, &log), [1000, 1001, 1000, 1001, 1000, 1000]);
+    }
+
+    #[test]
+    fn weights_give_uids_longer_turns() {
+        let dispatcher = Dispatcher::manual(weights(&[("0", 3)]));
+        let log = Arc::new(Mutex::new(Vec::new()));
+        submit(&dispatcher, &log, 0, Priority::Interactive, 6);
+        submit(&dispatcher, &log, 1000, Priority::Interactive, 2);
+        assert_eq!(run_all(&dispatcher, &log), [0, 0, 0, 1000, 0, 0, 0, 1000]);
+    }
+
+    #[test]
+    fn batch_jobs_yield_to_interactive_ones_without_starving() {
+        let dispatcher = Dispatcher::manual(UidWeights::default());
+        let log = Arc::new(Mutex::new(Vec::new()));
+        submit(&dispatcher, &log, 2, Priority::Batch, 2);
+        submit(&dispatcher, &log, 1, Priority::Interactive, 10);
+        let order = run_all(&dispatcher, &log);
+        assert_eq!(order[..7], [1; 7]);
+        assert_eq!(order[7], 2);
+        assert_eq!(order.iter().filter(|uid| **uid == 2).count(), 2);
+    }
+
+    #[test]
+    fn invalid_weights_are_rejected() {
+        let table = BTreeMap::from([("root".to_string(), 2)]);
+        assert!(UidWeights::try_from(table).is_err());
+        let table = BTreeMap::from([("0".to_string(), 0)]);
+        assert!(UidWeights::try_from(table).is_err());
+    }
+
+    #[test]
+    fn on_demand_workers_start_with_work_and_exit_when_idle() {
+        let dispatcher = Dispatcher::on_demand("test", 2, 16, UidWeights::default(), Some(Duration::from_millis(50)));
+        assert_eq!(dispatcher.worker_count(), 0);
+
+        let (done, finished) = mpsc::channel();
+        for _ in 0..4 {
+            let done = done.clone();
+            let report = move || done.send(thread::current().name().map(String::from)).unwrap();
+            dispatcher.submit(1000, Priority::Interactive, Box::new(report));
+        }
+        for _ in 0..4 {
+            let worker = finished.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert!(worker.unwrap().starts_with("test-"));
+        }
+        assert!((1..=2).contains(&dispatcher.worker_count()));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while dispatcher.worker_count() > 0 {
+            assert!(Instant::now() < deadline, "idle workers did not exit");
+            thread::sleep(Duration::from_millis(10));
+        }
+        // Work arriving after the workers exited starts them again.
+        dispatcher.submit(1000, Priority::Interactive, Box::new(move || done.send(None).unwrap()));
+        finished.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+}
@@ -17,4 +17,48 @@ pub enum FuseError {
 
     #[error("Mount configuration error: {0}")]
     MountConfig(String),
+
+    #[error("Pool unavailable: {0}")]
+    PoolUnavailable(String),
+
+    #[error("Mount conflict: {0}")]
+    MountConflict(String),
+
+    #[error("Settings error: {0}")]
+    Config(String),
+}
+
+// Maps a backend error to an errno by looking for the I/O error that caused
+// it. Errors without an underlying I/O error are reported as EIO.
+pub fn errno_of(err: &(dyn std::error::Error + 'static)) -> i32 {
+    let mut current = Some(err);
+    while let Some(err) = current {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            return io_errno(io);
+        }
+        current = err.source();
+    }
+    libc::EIO
+}
+
+fn io_errno(err: &std::io::Error) -> i32 {
+    use std::io::ErrorKind;
+
+    if let Some(errno) = err.raw_os_error() {
+        return errno;
+    }
+    match err.kind() {
+        ErrorKind::NotFound => libc::ENOENT,
+        ErrorKind::PermissionDenied => libc::EACCES,
+        ErrorKind::NotADirectory => libc::ENOTDIR,
+        ErrorKind::IsADirectory => libc::EISDIR,
+        ErrorKind::StorageFull => libc::ENOSPC,
+        ErrorKind::QuotaExceeded => libc::EDQUOT,
+        ErrorKind::InvalidFilename => libc::ENAMETOOLONG,
+        ErrorKind::StaleNetworkFileHandle => libc::ESTALE,
+        ErrorKind::TimedOut => libc::ETIMEDOUT,
+        ErrorKind::AlreadyExists => libc::EEXIST,
+        ErrorKind::ReadOnlyFilesystem => libc::EROFS,
+        _ => libc::EIO,
+    }
 }
@@ -3,241 +3,1822 @@
 // Copyright (c) 2025 Canmi
 
 use fuser::{
-    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+    spawn_mount2, BackgroundSession, FileAttr, FileType, Filesystem, KernelConfig, MountOption, Notifier,
+    PollHandle, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyEntry,
+    ReplyPoll, ReplyStatfs, ReplyWrite, Request, Session, SessionACL,
 };
-use librfs::{list_directory, model::Entry};
+use crate::backend::Backend;
+use crate::capabilities::{self, Outcome};
+use crate::config::{AtimePolicy, MountOptions, Normalization};
+use crate::control_dir::{self, is_control_inode, ControlFile};
+use crate::dir_snapshot::DirSnapshot;
+use crate::dispatch::{priority_of, Dispatcher, Priority};
+use crate::home::Homes;
+use crate::inflight;
+use crate::inode_locks::InodeLocks;
+use crate::inodes::{InodeTable, ROOT_INODE};
+use crate::logging::{Record, Subsystem};
+use crate::panic_guard::contain;
+use crate::store::{Entry, EntryKind};
 use nix::unistd::{Gid, Uid};
-use std::collections::HashMap;
-use std::ffi::{OsStr, OsString};
+use rfs_utils::LogLevel;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::os::fd::OwnedFd;
 use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime};
-use tokio::runtime::Handle;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use unicode_normalization::UnicodeNormalization;
 
 const TTL: Duration = Duration::from_secs(1);
-const ROOT_INODE: u64 = 1;
+// How old an access time may get before relatime updates it again.
+const RELATIME_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+// How often `.rfs` files that pollers wait on are checked for changes, and
+// how often a mount checks whether it has been idle.
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(1);
 
-// The RfsFuse struct now holds state for inode mapping.
+// The FUSE filesystem of one mount. Requests are handed to a dispatcher whose
+// workers serve them against the shared mount state; metadata and data
+// requests have a dispatcher each.
 pub struct RfsFuse {
-    pool_root: String,
-    tokio_handle: Handle,
+    fs: Arc<MountFs>,
+    metadata: Dispatcher,
+    data: Dispatcher,
+}
+
+// Entries of a pool found by lookup and readdir and when, by their path in
+// the pool, so a getattr within TTL of them is answered without listing the
+// parent directory again. Shared by every view of the pool; each mount
+// builds attributes from them with its own options.
+#[derive(Default)]
+pub struct EntryCache(Mutex<HashMap<PathBuf, (Entry, Instant)>>);
+
+// The namespace of a directory of a pool as the FUSE layer tracks it,
+// shared by every mount showing that directory: they hand out the same inode
+// numbers, and an entry one of them found is not listed again by another.
+pub struct PoolView {
+    // Directory of the pool shown, "/" for the whole pool.
+    root: PathBuf,
     // In-memory mapping to track inodes.
-    inodes: HashMap<u64, PathBuf>,
-    paths: HashMap<PathBuf, u64>,
-    next_inode: u64,
+    inodes: Mutex<InodeTable>,
+    // Held by mutating operations on the inodes they change.
+    locks: InodeLocks,
+    // Access times under the relatime and strictatime policies. librfs has
+    // no access times, so they are kept in memory while the view is in use.
+    atimes: Mutex<HashMap<u64, SystemTime>>,
+    entries: Arc<EntryCache>,
+}
+
+impl PoolView {
+    // View of the directory `root` of a pool whose entries are cached in `entries`.
+    pub fn new(root: &str, entries: Arc<EntryCache>) -> Self {
+        Self {
+            root: PathBuf::from(root),
+            inodes: Mutex::new(InodeTable::new()),
+            locks: InodeLocks::default(),
+            atimes: Mutex::new(HashMap::new()),
+            entries,
+        }
+    }
+
+    pub fn entries(&self) -> &Arc<EntryCache> {
+        &self.entries
+    }
+
+    // Path in the pool of `path` in the view.
+    fn pool_path(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
+
+impl Default for PoolView {
+    fn default() -> Self {
+        Self::new("/", Arc::default())
+    }
+}
+
+// State of a mount, shared by the dispatcher's workers.
+struct MountFs {
+    backend: Backend,
+    mount_point: String,
+    options: MountOptions,
+    view: Arc<PoolView>,
+    // Set for mounts with per-user homes.
+    homes: Option<Homes>,
+    // Open `.rfs` files, keyed by handle.
+    open_files: Mutex<HashMap<u64, ControlHandle>>,
+    // Pollers waiting for an open `.rfs` file to change, keyed by handle.
+    pollers: Mutex<HashMap<u64, PollHandle>>,
+    // Sends cache invalidations to the kernel once the session is mounted.
+    notifier: OnceLock<Notifier>,
+    // Listing of each open directory, taken when its readdir stream starts
+    // and kept until it is released, keyed by handle.
+    open_dirs: Mutex<HashMap<u64, Arc<DirSnapshot>>>,
+    next_fh: AtomicU64,
+    // When the mount was set up, and the time since then of its latest
+    // request and of the latest request before it last released its idle
+    // resources, in milliseconds.
+    created: Instant,
+    last_request_ms: AtomicU64,
+    released_after_ms: AtomicU64,
+}
+
+// An open `.rfs` file and the content its reader sees. The content is
+// rendered at open and again whenever the file is read from the start, so
+// rereading an open file shows current values.
+struct ControlHandle {
+    file: ControlFile,
+    content: Vec<u8>,
 }
 
 impl RfsFuse {
-    // Constructor to create a new FUSE instance for a specific pool.
-    pub fn new(pool_root: String) -> Self {
-        let mut inodes = HashMap::new();
-        let mut paths = HashMap::new();
-        let root_path = PathBuf::from("/");
+    // Constructor to create a new FUSE instance for a specific pool, tracking
+    // its entries in `view`.
+    pub fn new(backend: Backend, mount_point: String, options: MountOptions, view: Arc<PoolView>) -> Self {
+        let depth = options.queue_depth as usize;
+        let dispatcher = |name: &str, workers: u32| {
+            let weights = options.uid_weights.clone();
+            if options.lazy {
+                Dispatcher::on_demand(name, workers as usize, depth, weights, options.idle_timeout())
+            } else {
+                Dispatcher::new(name, workers as usize, depth, weights, options.idle_timeout())
+            }
+        };
+        let metadata = dispatcher("rfs-meta", options.metadata_workers);
+        let data = dispatcher("rfs-data", options.data_workers);
+        let homes = (!options.home.is_empty()).then(Homes::default);
+        backend.stats().io.set_per_pid(options.account_pids);
+        let fs = MountFs {
+            backend,
+            mount_point,
+            options,
+            view,
+            homes,
+            open_files: Mutex::new(HashMap::new()),
+            pollers: Mutex::new(HashMap::new()),
+            notifier: OnceLock::new(),
+            open_dirs: Mutex::new(HashMap::new()),
+            next_fh: AtomicU64::new(1),
+            created: Instant::now(),
+            last_request_ms: AtomicU64::new(0),
+            released_after_ms: AtomicU64::new(0),
+        };
+        let fs = Arc::new(fs);
+        spawn_housekeeping(Arc::downgrade(&fs));
+        Self { fs, metadata, data }
+    }
 
-        // Initialize the root directory.
-        inodes.insert(ROOT_INODE, root_path.clone());
-        paths.insert(root_path, ROOT_INODE);
+    // Mounts the filesystem at `mount_point` in a background session, which
+    // unmounts it when dropped.
+    pub fn spawn(self, mount_point: &Path, options: &[MountOption]) -> std::io::Result<BackgroundSession> {
+        let fs = Arc::clone(&self.fs);
+        let session = spawn_mount2(self, mount_point, options)?;
+        let _ = fs.notifier.set(session.notifier());
+        Ok(session)
+    }
 
-        Self {
-            pool_root,
-            tokio_handle: Handle::current(),
-            inodes,
-            paths,
-            // Start assigning new inodes from 2 onwards.
-            next_inode: ROOT_INODE + 1,
+    // Serves the filesystem on `device`, a FUSE connection mounted by the
+    // caller, in a background session. Dropping the session does not
+    // unmount it.
+    pub fn spawn_on(self, device: OwnedFd, acl: SessionACL) -> std::io::Result<BackgroundSession> {
+        let fs = Arc::clone(&self.fs);
+        let session = BackgroundSession::new(Session::from_fd(self, device, acl))?;
+        let _ = fs.notifier.set(session.notifier());
+        Ok(session)
+    }
+
+    // Queues an operation on `path` for a dispatcher's workers. It is
+    // scheduled by the priority of the requesting process and fairly against
+    // the other uids' operations, and runs under `guarded` while listed as
+    // in flight.
+    fn dispatch(
+        &self,
+        dispatcher: &Dispatcher,
+        req: &Request<'_>,
+        op: &'static str,
+        path: PathBuf,
+        f: impl FnOnce(&MountFs) + Send + 'static,
+    ) {
+        let priority = if self.fs.options.prioritize_interactive {
+            priority_of(req.pid())
+        } else {
+            Priority::Interactive
+        };
+        let (uid, requester) = (req.uid(), (req.uid(), req.gid(), req.pid()));
+        let fs = Arc::clone(&self.fs);
+        fs.last_request_ms.store(fs.created.elapsed().as_millis() as u64, Ordering::Relaxed);
+        dispatcher.submit(
+            uid,
+            priority,
+            Box::new(move || {
+                let stats = fs.backend.stats();
+                let in_flight = stats.inflight.begin(op, path, requester);
+                stats.record_io(in_flight.op(), 1, 0, 0);
+                fs.guarded(op, f);
+            }),
+        );
+    }
+
+    // Path of `ino`, or of `name` in directory `ino`, for the in-flight list.
+    fn op_path(&self, ino: u64, name: Option<&OsStr>) -> PathBuf {
+        let dir = if is_control_inode(ino) {
+            Path::new("/").join(control_dir::NAME)
+        } else {
+            self.fs.path_of(ino).unwrap_or_default()
+        };
+        match name {
+            Some(name) => dir.join(name),
+            None => dir,
+        }
+    }
+}
+
+impl MountFs {
+    fn path_of(&self, ino: u64) -> Option<PathBuf> {
+        self.view.inodes.lock().unwrap().path_of(ino)
+    }
+
+    fn child_inode(&self, parent: u64, name: &str) -> u64 {
+        self.view.inodes.lock().unwrap().child(parent, name)
+    }
+
+    // Errno for an inode whose entry is gone from the pool. The inode came
+    // from an earlier lookup, so the entry was removed behind the kernel's
+    // back: report ESTALE, which makes the kernel look the path up again,
+    // and drop the kernel's cached entry. The invalidation waits for the
+    // directory's lock, which a request queued behind this one may hold, so
+    // it is sent from a thread of its own.
+    fn stale(&self, ino: u64, path: &Path) -> i32 {
+        self.view.entries.0.lock().unwrap().remove(&self.view.pool_path(path));
+        let parent = self.view.inodes.lock().unwrap().parent_of(ino);
+        if let (Some(parent), Some(name), Some(notifier)) = (parent, path.file_name(), self.notifier.get()) {
+            let (name, notifier) = (name.to_owned(), notifier.clone());
+            std::thread::spawn(move || notifier.inval_entry(parent, &name));
+        }
+        libc::ESTALE
+    }
+
+    // Entry of a path other than the root, found in its parent's listing.
+    fn entry_at(&self, path: &Path) -> Result<Entry, i32> {
+        let parent_path = path.parent().unwrap_or_else(|| Path::new("/"));
+        let file_name = path.file_name().unwrap_or_default();
+        let (parent_str, name_str) = (utf8(parent_path.as_os_str())?, utf8(file_name)?);
+        let listing = self.backend.list_directory(parent_str)?;
+        listing.get(name_str).cloned().ok_or(libc::ENOENT)
+    }
+
+    // Writes a structured log record for a finished operation. Errors that
+    // applications routinely cause, such as ENOENT on a lookup, are debug
+    // output; everything else points at the backend and is a warning.
+    fn log_op(&self, op: &str, path: &Path, started: Instant, errno: Option<i32>) {
+        let level = match errno {
+            None | Some(
+                libc::ENOENT
+                | libc::ESTALE
+                | libc::ENOTDIR
+                | libc::EISDIR
+                | libc::EACCES
+                | libc::EROFS
+                | libc::ENAMETOOLONG
+                | libc::EILSEQ,
+            ) => LogLevel::Debug,
+            Some(_) => LogLevel::Warn,
+        };
+        let mut record = Record::new(Subsystem::Fuse, level, op)
+            .op(op)
+            .mount(&self.mount_point)
+            .path(path.to_string_lossy())
+            .duration(started.elapsed());
+        if let Some(errno) = errno {
+            record = record.error(std::io::Error::from_raw_os_error(errno).to_string());
         }
+        let requester = inflight::current();
+        if let Some(requester) = &requester {
+            record = record.requester(requester);
+        }
+        record.emit();
     }
 
-    // Helper to get or create an inode for a given path.
-    fn get_or_create_inode(&mut self, path: &Path) -> u64 {
-        if let Some(&ino) = self.paths.get(path) {
-            return ino;
+    // Runs an operation with panic containment, so a bug in one handler fails
+    // that request with EIO instead of killing the session and stranding the mount.
+    fn guarded(&self, op: &str, f: impl FnOnce(&Self)) {
+        if let Err((message, backtrace)) = contain(|| f(self)) {
+            let stats = self.backend.stats();
+            stats.count(&stats.handler_panics, 1);
+            let message = format!("Handler panicked, replied EIO: {}\n{}", message, backtrace);
+            let mut record = Record::new(Subsystem::Fuse, LogLevel::Error, &message)
+                .op(op)
+                .mount(&self.mount_point);
+            let requester = inflight::current();
+            if let Some(requester) = &requester {
+                record = record.requester(requester);
+            }
+            record.emit();
         }
-        let new_ino = self.next_inode;
-        self.next_inode += 1;
-        self.paths.insert(path.to_path_buf(), new_ino);
-        self.inodes.insert(new_ino, path.to_path_buf());
-        new_ino
     }
 
-    // Helper to build FileAttr from librfs Entry.
-    fn entry_to_attr(&self, ino: u64, entry: &Entry) -> FileAttr {
-        let (kind, size, modified_at) = match entry {
-            Entry::File(f) => (FileType::RegularFile, f.size, f.modified_at),
-            Entry::Directory(d) => (FileType::Directory, d.size, d.modified_at),
+    // Entries of the listing of `parent_path` the requesting user may see,
+    // i.e. neither hidden nor out of reach.
+    fn visible(&self, parent_path: &Path, mut listing: HashMap<String, Entry>) -> HashMap<String, Entry> {
+        listing.retain(|name, _| {
+            let path = parent_path.join(name);
+            !self.options.hide.is_hidden(&path) && self.reachable(&path)
+        });
+        listing
+    }
+
+    // Whether the requesting user may reach `path`, which only mounts with
+    // per-user homes restrict.
+    fn reachable(&self, path: &Path) -> bool {
+        let Some(homes) = &self.homes else {
+            return true;
         };
+        inflight::current().is_some_and(|op| homes.allows(op.uid, path))
+    }
+
+    // How long the kernel may cache entries and attributes. It shares them
+    // between users, so on mounts with per-user homes every access has to
+    // reach `reachable`.
+    fn ttl(&self) -> Duration {
+        if self.homes.is_some() { Duration::ZERO } else { TTL }
+    }
+
+    // Rejects names and resulting paths longer than the pool accepts.
+    fn check_name_length(&self, parent_path: &Path, name: &OsStr) -> Result<(), i32> {
+        let path_len = parent_path.as_os_str().len() + 1 + name.len();
+        if name.len() > self.options.name_max as usize || path_len > self.options.path_max as usize {
+            return Err(libc::ENAMETOOLONG);
+        }
+        Ok(())
+    }
+
+    // Path of `parent` when `name` in it can be the target of an operation:
+    // not too long, not hidden and reachable by the requester.
+    fn target_parent(&self, parent: u64, name: &OsStr) -> Result<PathBuf, i32> {
+        let parent_path = self.path_of(parent).ok_or(libc::ENOENT)?;
+        self.check_name_length(&parent_path, name)?;
+        let path = parent_path.join(name);
+        if self.options.hide.is_hidden(&path) || !self.reachable(&path) {
+            return Err(libc::EACCES);
+        }
+        Ok(parent_path)
+    }
+
+    // Errno for an operation creating `name` in `parent`. Invalid names, and
+    // names that match an existing entry only after case folding or
+    // normalization, are reported before the operation itself is refused.
+    fn mutation_errno(&self, parent: u64, name: &OsStr) -> i32 {
+        let parent_path = match self.target_parent(parent, name) {
+            Ok(parent_path) => parent_path,
+            Err(errno) => return errno,
+        };
+        if self.options.case_insensitive || self.options.normalization != Normalization::None {
+            let (parent_str, name_str) = match (utf8(parent_path.as_os_str()), utf8(name)) {
+                (Ok(parent), Ok(name)) => (parent, name),
+                (Err(errno), _) | (_, Err(errno)) => return errno,
+            };
+            match self.backend.list_directory(parent_str) {
+                Ok(listing) if find_entry(&listing, name_str, &self.options).is_some() => return libc::EEXIST,
+                Ok(_) => {}
+                Err(errno) => return errno,
+            }
+        }
+        self.backend.mutation_errno()
+    }
+
+    // Access time reported for an inode; entries never accessed under the
+    // current policy report their modification time.
+    fn atime(&self, ino: u64, modified_at: SystemTime) -> SystemTime {
+        match self.options.atime {
+            AtimePolicy::Noatime => modified_at,
+            AtimePolicy::Relatime | AtimePolicy::Strictatime => {
+                self.view.atimes.lock().unwrap().get(&ino).copied().unwrap_or(modified_at)
+            }
+        }
+    }
+
+    // Records an access to an inode according to the atime policy.
+    fn touch_atime(&self, ino: u64) {
+        let now = SystemTime::now();
+        let mut atimes = self.view.atimes.lock().unwrap();
+        let update = match self.options.atime {
+            AtimePolicy::Noatime => false,
+            AtimePolicy::Strictatime => true,
+            AtimePolicy::Relatime => atimes
+                .get(&ino)
+                .is_none_or(|atime| now.duration_since(*atime).unwrap_or_default() >= RELATIME_INTERVAL),
+        };
+        if update {
+            atimes.insert(ino, now);
+        }
+    }
+
+    // Remembers entries found at paths in the mount.
+    fn remember_entries(&self, entries: impl IntoIterator<Item = (PathBuf, Entry)>) {
+        let now = Instant::now();
+        let mut cached = self.view.entries.0.lock().unwrap();
+        cached.extend(entries.into_iter().map(|(path, entry)| (self.view.pool_path(&path), (entry, now))));
+    }
+
+    // Entry at a path in the mount as found less than TTL ago.
+    fn remembered_entry(&self, path: &Path) -> Option<Entry> {
+        let entries = self.view.entries.0.lock().unwrap();
+        let (entry, at) = entries.get(&self.view.pool_path(path))?;
+        (at.elapsed() < TTL).then(|| entry.clone())
+    }
+
+    fn remembered_attr(&self, ino: u64) -> Option<FileAttr> {
+        let path = self.path_of(ino)?;
+        let entry = self.remembered_entry(&path)?;
+        Some(self.entry_to_attr(ino, &path, &entry))
+    }
+
+    // Remembered attributes of the entry stored as `name` in directory
+    // `parent` at `parent_path`. It may have been found through another view
+    // of the pool, so its inode is handed out here if need be.
+    fn remembered_child(&self, parent: u64, parent_path: &Path, name: &str) -> Option<FileAttr> {
+        let path = parent_path.join(name);
+        let entry = self.remembered_entry(&path)?;
+        Some(self.entry_to_attr(self.child_inode(parent, name), &path, &entry))
+    }
+
+    fn expire_entries(&self) {
+        self.view.entries.0.lock().unwrap().retain(|_, (_, at)| at.elapsed() < TTL);
+    }
+
+    // Helper to build FileAttr from a pool Entry.
+    fn entry_to_attr(&self, ino: u64, path: &Path, entry: &Entry) -> FileAttr {
+        let (kind, size, modified_at) = (file_type(entry.kind), entry.size, entry.modified_at);
+        let (uid, gid) = self.homes.as_ref().and_then(|homes| homes.owner_of(path)).unwrap_or_else(|| {
+            (Uid::current().as_raw(), Gid::current().as_raw())
+        });
 
         FileAttr {
             ino,
             size,
-            blocks: (size + 511) / 512, // Calculate blocks based on size
-            atime: SystemTime::now(), // Use current time for atime for simplicity
-            mtime: modified_at.into(),
-            ctime: modified_at.into(),
-            crtime: modified_at.into(),
+            blocks: self.blocks(size),
+            atime: self.atime(ino, modified_at),
+            mtime: modified_at,
+            ctime: modified_at,
+            crtime: modified_at,
             kind,
             perm: if kind == FileType::Directory { 0o755 } else { 0o644 },
-            nlink: 1,
-            uid: Uid::current().as_raw(),
-            gid: Gid::current().as_raw(),
+            nlink: if kind == FileType::Directory { self.dir_nlink(path) } else { 1 },
+            uid,
+            gid,
             rdev: 0,
             flags: 0,
-            blksize: 512,
+            blksize: self.chunk_size(),
         }
     }
+
+    // Link count of a directory. Without count_subdirs it is 1, which tools
+    // walking trees (find, du) take as unknown, rather than 2, which older
+    // ones take as having no subdirectories and do not descend into. With
+    // it, the directory is listed to count its subdirectories.
+    fn dir_nlink(&self, path: &Path) -> u32 {
+        if !self.options.count_subdirs {
+            return 1;
+        }
+        let Ok(listing) = utf8(path.as_os_str()).and_then(|path_str| self.backend.list_directory(path_str)) else {
+            return 1;
+        };
+        let subdirs = listing
+            .iter()
+            .filter(|(name, entry)| entry.kind == EntryKind::Directory && !self.options.hide.is_hidden(&path.join(name)))
+            .count();
+        2 + u32::try_from(subdirs).unwrap_or(u32::MAX - 2)
+    }
+
+    fn chunk_size(&self) -> u32 {
+        self.options.chunk_size.max(512)
+    }
+
+    // st_blocks in 512-byte units, counting every started chunk as allocated.
+    fn blocks(&self, size: u64) -> u64 {
+        let chunk = u64::from(self.chunk_size());
+        size.div_ceil(chunk) * chunk / 512
+    }
 }
 
-impl Filesystem for RfsFuse {
-    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
-        let path = match self.inodes.get(&ino) {
+// Finds `name` in a directory listing. An exact match wins; otherwise names
+// are compared in the mount's normalization form and, in case-insensitive
+// mode, ignoring case. Among several matches the one that sorts first is used.
+fn find_entry<'a>(
+    listing: &'a HashMap<String, Entry>,
+    name: &str,
+    options: &MountOptions,
+) -> Option<(&'a str, &'a Entry)> {
+    if let Some((actual_name, entry)) = listing.get_key_value(name) {
+        return Some((actual_name.as_str(), entry));
+    }
+    if !options.case_insensitive && options.normalization == Normalization::None {
+        return None;
+    }
+    let key = match_key(name, options);
+    listing
+        .iter()
+        .filter(|(candidate, _)| match_key(candidate, options) == key)
+        .min_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(actual_name, entry)| (actual_name.as_str(), entry))
+}
+
+fn match_key(name: &str, options: &MountOptions) -> String {
+    let name = normalize(name, options.normalization);
+    if options.case_insensitive { name.to_lowercase() } else { name.into_owned() }
+}
+
+// Converts a name to the mount's normalization form.
+fn normalize(name: &str, form: Normalization) -> Cow<'_, str> {
+    match form {
+        Normalization::None => Cow::Borrowed(name),
+        Normalization::Nfc => Cow::Owned(name.nfc().collect()),
+        Normalization::Nfd => Cow::Owned(name.nfd().collect()),
+    }
+}
+
+fn file_type(kind: EntryKind) -> FileType {
+    match kind {
+        EntryKind::File => FileType::RegularFile,
+        EntryKind::Directory => FileType::Directory,
+    }
+}
+
+// Attributes handed out with "." and ".." by readdirplus, of which the
+// kernel only uses the inode number.
+fn dot_attr(ino: u64) -> FileAttr {
+    let now = SystemTime::now();
+    FileAttr {
+        ino,
+        size: 0,
+        blocks: 0,
+        atime: now,
+        mtime: now,
+        ctime: now,
+        crtime: now,
+        kind: FileType::Directory,
+        perm: 0o755,
+        nlink: 1,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+        flags: 0,
+        blksize: 0,
+    }
+}
+
+// librfs addresses entries by UTF-8 strings, so names that are not valid
+// UTF-8 cannot exist in a pool and are rejected with EILSEQ.
+fn utf8(name: &OsStr) -> Result<&str, i32> {
+    name.to_str().ok_or(libc::EILSEQ)
+}
+
+// The virtual `.rfs` directory.
+impl MountFs {
+    // Whether the user `uid` may use `.rfs`. Its files name the paths,
+    // processes and executables of every user, so on mounts with per-user
+    // homes only root may.
+    fn control_allowed(&self, uid: Option<u32>) -> bool {
+        self.homes.is_none() || uid == Some(0)
+    }
+
+    fn control_getattr(&self, ino: u64, reply: ReplyAttr) {
+        if !self.control_allowed(inflight::current().map(|op| op.uid)) {
+            reply.error(libc::EACCES);
+        } else if ino == control_dir::DIR_INODE {
+            reply.attr(&self.ttl(), &control_dir::dir_attr());
+        } else if let Some(file) = ControlFile::from_inode(ino) {
+            let size = file.render(self.backend.stats()).len() as u64;
+            reply.attr(&self.ttl(), &control_dir::file_attr(file, size));
+        } else {
+            reply.error(libc::ENOENT);
+        }
+    }
+
+    fn control_lookup(&self, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        if !self.control_allowed(inflight::current().map(|op| op.uid)) {
+            reply.error(libc::ENOENT);
+        } else if parent == ROOT_INODE {
+            reply.entry(&self.ttl(), &control_dir::dir_attr(), 0);
+        } else if let (control_dir::DIR_INODE, Some(file)) = (parent, ControlFile::from_name(name)) {
+            let size = file.render(self.backend.stats()).len() as u64;
+            reply.entry(&self.ttl(), &control_dir::file_attr(file, size), 0);
+        } else {
+            reply.error(libc::ENOENT);
+        }
+    }
+
+    fn control_readdir(&self, ino: u64, offset: i64, mut reply: ReplyDirectory) {
+        if !self.control_allowed(inflight::current().map(|op| op.uid)) {
+            reply.error(libc::EACCES);
+            return;
+        }
+        if ino != control_dir::DIR_INODE {
+            reply.error(libc::ENOTDIR);
+            return;
+        }
+        for (i, (attr, name)) in self.control_entries().into_iter().enumerate().skip(offset as usize) {
+            if reply.add(attr.ino, i as i64 + 1, attr.kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn control_readdirplus(&self, ino: u64, offset: i64, mut reply: ReplyDirectoryPlus) {
+        if !self.control_allowed(inflight::current().map(|op| op.uid)) {
+            reply.error(libc::EACCES);
+            return;
+        }
+        if ino != control_dir::DIR_INODE {
+            reply.error(libc::ENOTDIR);
+            return;
+        }
+        for (i, (attr, name)) in self.control_entries().into_iter().enumerate().skip(offset as usize) {
+            if reply.add(attr.ino, i as i64 + 1, name, &self.ttl(), &attr, 0) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    // The entries of the `.rfs` directory, starting with "." and "..".
+    fn control_entries(&self) -> Vec<(FileAttr, &'static str)> {
+        let dir = control_dir::dir_attr();
+        let mut entries = vec![(dir, "."), (FileAttr { ino: ROOT_INODE, ..dir }, "..")];
+        entries.extend(ControlFile::ALL.iter().map(|file| {
+            let size = file.render(self.backend.stats()).len() as u64;
+            (control_dir::file_attr(*file, size), file.name())
+        }));
+        entries
+    }
+
+    // Whether an open `.rfs` file changed since its reader last read it from
+    // the start. Unknown handles count as changed so their pollers return.
+    fn control_changed(&self, fh: u64) -> bool {
+        let open_files = self.open_files.lock().unwrap();
+        open_files
+            .get(&fh)
+            .is_none_or(|handle| handle.file.render(self.backend.stats()).as_bytes() != handle.content)
+    }
+
+    // A `.rfs` file is ready when it changed, so `poll` and `epoll` wait for
+    // new values rather than returning at once like on other files. Readers
+    // then read it again from offset 0.
+    fn control_poll(&self, fh: u64, ph: PollHandle, flags: u32, reply: ReplyPoll) {
+        if self.control_changed(fh) {
+            reply.poll((libc::POLLIN | libc::POLLPRI | libc::POLLRDNORM) as u32);
+            return;
+        }
+        if flags & fuser::consts::FUSE_POLL_SCHEDULE_NOTIFY != 0 {
+            self.pollers.lock().unwrap().insert(fh, ph);
+        }
+        reply.poll(0);
+    }
+}
+
+// Wakes pollers of `.rfs` files once their file changed, and releases what
+// the mount holds once it is idle. Exits when the mount's filesystem is
+// dropped.
+fn spawn_housekeeping(fs: Weak<MountFs>) {
+    let spawned = std::thread::Builder::new().name("rfs-housekeep".to_string()).spawn(move || {
+        loop {
+            std::thread::sleep(HOUSEKEEPING_INTERVAL);
+            let Some(fs) = fs.upgrade() else {
+                return;
+            };
+            let waiting: Vec<u64> = fs.pollers.lock().unwrap().keys().copied().collect();
+            for fh in waiting {
+                if fs.control_changed(fh)
+                    && let Some(ph) = fs.pollers.lock().unwrap().remove(&fh)
+                {
+                    let _ = ph.notify();
+                }
+            }
+            fs.expire_entries();
+            fs.release_if_idle();
+            fs.record_sizes();
+        }
+    });
+    if let Err(e) = spawned {
+        Record::new(Subsystem::Fuse, LogLevel::Warn, &format!("Mount housekeeping unavailable: {}", e)).emit();
+    }
+}
+
+impl MountFs {
+    // Publishes the sizes of the in-memory state in the mount's stats.
+    fn record_sizes(&self) {
+        let stats = self.backend.stats();
+        stats.inodes.store(self.view.inodes.lock().unwrap().len() as u64, Ordering::Relaxed);
+        stats.cached_attrs.store(self.view.entries.0.lock().unwrap().len() as u64, Ordering::Relaxed);
+        stats.open_dirs.store(self.open_dirs.lock().unwrap().len() as u64, Ordering::Relaxed);
+    }
+
+    // Drops the listings kept for open directories once the mount has had
+    // no request for its idle timeout, once per idle period. A readdir
+    // continuing such a stream lists the directory again; cookies derive
+    // from names, so it resumes where it stopped. The dispatchers' workers
+    // exit on the same timeout by themselves.
+    fn release_if_idle(&self) {
+        let Some(timeout) = self.options.idle_timeout() else {
+            return;
+        };
+        let last_request = self.last_request_ms.load(Ordering::Relaxed);
+        let idle = self.created.elapsed().saturating_sub(Duration::from_millis(last_request));
+        if idle < timeout || self.released_after_ms.swap(last_request, Ordering::Relaxed) == last_request {
+            return;
+        }
+        let listings = {
+            let mut open_dirs = self.open_dirs.lock().unwrap();
+            let count = open_dirs.len();
+            open_dirs.clear();
+            open_dirs.shrink_to_fit();
+            count
+        };
+        self.open_files.lock().unwrap().shrink_to_fit();
+        self.view.atimes.lock().unwrap().shrink_to_fit();
+        self.view.entries.0.lock().unwrap().shrink_to_fit();
+        Record::new(
+            Subsystem::Fuse,
+            LogLevel::Debug,
+            &format!("Idle for {:?}, released {} directory listings", idle, listings),
+        )
+        .mount(&self.mount_point)
+        .emit();
+    }
+}
+
+// Operation bodies, run by the dispatcher's workers under `guarded`.
+impl MountFs {
+    fn do_getattr(&self, ino: u64, reply: ReplyAttr) {
+        if is_control_inode(ino) {
+            self.control_getattr(ino, reply);
+            return;
+        }
+        let path = match self.path_of(ino) {
             Some(p) => p,
             None => {
                 reply.error(libc::ENOENT);
                 return;
             }
         };
+        if !self.reachable(&path) {
+            reply.error(libc::EACCES);
+            return;
+        }
 
         // Handle root directory separately.
         if ino == ROOT_INODE {
             let attr = FileAttr {
                 ino: ROOT_INODE,
                 size: 4096, // Typical size for a directory
-                blocks: 8,
+                blocks: self.blocks(4096),
                 atime: SystemTime::now(),
                 mtime: SystemTime::now(),
                 ctime: SystemTime::now(),
                 crtime: SystemTime::now(),
                 kind: FileType::Directory,
                 perm: 0o755,
-                nlink: 2, // '.' and '..'
+                nlink: self.dir_nlink(&path),
                 uid: Uid::current().as_raw(),
                 gid: Gid::current().as_raw(),
                 rdev: 0,
                 flags: 0,
-                blksize: 512,
+                blksize: self.chunk_size(),
             };
-            reply.attr(&TTL, &attr);
+            reply.attr(&self.ttl(), &attr);
             return;
         }
 
-        // For other files/dirs, find their entry in the parent listing.
-        let parent_path = path.parent().unwrap_or_else(|| Path::new("/"));
-        let file_name = path.file_name().unwrap_or_default();
-
-        let listing_result = self.tokio_handle.block_on(
-            list_directory(&self.pool_root, parent_path.to_str().unwrap_or("/"))
-        );
-
-        match listing_result {
-            Ok(listing) => {
-                if let Some(entry) = listing.get(file_name.to_str().unwrap()) {
-                    let attr = self.entry_to_attr(ino, entry);
-                    reply.attr(&TTL, &attr);
-                } else {
-                    reply.error(libc::ENOENT);
-                }
+        if let Some(attr) = self.remembered_attr(ino) {
+            reply.attr(&self.ttl(), &attr);
+            return;
+        }
+        let started = Instant::now();
+        let errno = match self.entry_at(&path) {
+            Ok(entry) => {
+                let attr = self.entry_to_attr(ino, &path, &entry);
+                reply.attr(&self.ttl(), &attr);
+                None
             }
-            Err(_) => reply.error(libc::EIO),
+            Err(libc::ENOENT) => {
+                let errno = self.stale(ino, &path);
+                reply.error(errno);
+                Some(errno)
+            }
+            Err(errno) => {
+                reply.error(errno);
+                Some(errno)
+            }
+        };
+        self.log_op("getattr", &path, started, errno);
+    }
+
+    // Checks the flags of an open against the entry and the mount. Opens
+    // that would write fail with EROFS: the kernel takes ENOSYS from open as
+    // the filesystem not implementing open and lets every later open succeed.
+    // O_DIRECTORY only opens directories.
+    fn do_open(&self, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        let Some(path) = self.path_of(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        if !self.reachable(&path) {
+            reply.error(libc::EACCES);
+            return;
         }
+        let started = Instant::now();
+        let kind = if ino == ROOT_INODE {
+            Ok(EntryKind::Directory)
+        } else {
+            match self.entry_at(&path) {
+                Ok(entry) => Ok(entry.kind),
+                Err(libc::ENOENT) => Err(self.stale(ino, &path)),
+                Err(errno) => Err(errno),
+            }
+        };
+        let writes = flags & libc::O_ACCMODE != libc::O_RDONLY || flags & libc::O_TRUNC != 0;
+        let errno = match kind {
+            Err(errno) => errno,
+            Ok(EntryKind::File) if flags & libc::O_DIRECTORY != 0 => libc::ENOTDIR,
+            Ok(EntryKind::Directory) if flags & libc::O_ACCMODE != libc::O_RDONLY => libc::EISDIR,
+            Ok(_) if writes => libc::EROFS,
+            // TODO: Implement file opening based on path.
+            Ok(_) => libc::ENOENT,
+        };
+        reply.error(errno);
+        self.log_op("open", &path, started, Some(errno));
     }
 
-    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        let parent_path = match self.inodes.get(&parent) {
-            Some(p) => p.clone(),
+    fn do_lookup(&self, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        if is_control_inode(parent) || (parent == ROOT_INODE && name == control_dir::NAME) {
+            self.control_lookup(parent, name, reply);
+            return;
+        }
+        let parent_path = match self.path_of(parent) {
+            Some(p) => p,
             None => {
                 reply.error(libc::ENOENT);
                 return;
             }
         };
 
-        let listing_result = self.tokio_handle.block_on(
-            list_directory(&self.pool_root, parent_path.to_str().unwrap_or("/"))
-        );
+        let started = Instant::now();
+        let child_path = parent_path.join(name);
+        if let Err(errno) = self.check_name_length(&parent_path, name) {
+            reply.error(errno);
+            self.log_op("lookup", &child_path, started, Some(errno));
+            return;
+        }
+        if self.options.hide.is_hidden(&child_path) || !self.reachable(&child_path) {
+            reply.error(libc::ENOENT);
+            self.log_op("lookup", &child_path, started, Some(libc::ENOENT));
+            return;
+        }
+        let (parent_str, name_str) = match (utf8(parent_path.as_os_str()), utf8(name)) {
+            (Ok(parent), Ok(name)) => (parent, name),
+            (Err(errno), _) | (_, Err(errno)) => {
+                reply.error(errno);
+                self.log_op("lookup", &child_path, started, Some(errno));
+                return;
+            }
+        };
+
+        // `ls -l` and the like look up every entry they just listed; those
+        // lookups are answered from the listing instead of listing the
+        // directory once per entry.
+        if let Some(attr) = self.remembered_child(parent, &parent_path, name_str) {
+            reply.entry(&self.ttl(), &attr, 0);
+            self.log_op("lookup", &child_path, started, None);
+            return;
+        }
+        let listing_result = self.backend.list_directory(parent_str);
 
-        match listing_result {
+        let errno = match listing_result {
             Ok(listing) => {
-                if let Some(entry) = listing.get(name.to_str().unwrap()) {
-                    let child_path = parent_path.join(name);
-                    let ino = self.get_or_create_inode(&child_path);
-                    let attr = self.entry_to_attr(ino, entry);
-                    reply.entry(&TTL, &attr, 0);
+                let listing = self.visible(&parent_path, listing);
+                let found = find_entry(&listing, name_str, &self.options);
+                if let Some((actual_name, entry)) = found {
+                    // Inodes always map to the name stored in the pool.
+                    let ino = self.child_inode(parent, actual_name);
+                    let attr = self.entry_to_attr(ino, &parent_path.join(actual_name), entry);
+                    self.remember_entries([(parent_path.join(actual_name), entry.clone())]);
+                    reply.entry(&self.ttl(), &attr, 0);
+                    None
                 } else {
                     reply.error(libc::ENOENT);
+                    Some(libc::ENOENT)
                 }
             }
-            Err(_) => reply.error(libc::EIO),
-        }
+            Err(errno) => {
+                reply.error(errno);
+                Some(errno)
+            }
+        };
+        self.log_op("lookup", &child_path, started, errno);
     }
 
-    fn readdir(
-        &mut self,
-        _req: &Request<'_>,
-        ino: u64,
-        _fh: u64,
-        offset: i64,
-        mut reply: ReplyDirectory,
-    ) {
-        let path = match self.inodes.get(&ino) {
-            Some(p) => p.clone(),
-            None => {
-                reply.error(libc::ENOENT);
+    // Entries are returned from the snapshot taken at offset 0, so a stream
+    // split across several requests, or resumed with seekdir, sees one
+    // consistent listing. Offsets are the entries' cookies.
+    fn do_readdir(&self, ino: u64, fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        if is_control_inode(ino) {
+            self.control_readdir(ino, offset, reply);
+            return;
+        }
+        let snapshot = match self.open_snapshot(ino, fh, offset) {
+            Ok((_, snapshot)) => snapshot,
+            Err(errno) => {
+                reply.error(errno);
                 return;
             }
         };
+        if offset < 1 && reply.add(ino, 1, FileType::Directory, ".") {
+            reply.ok();
+            return;
+        }
+        if offset < 2 && reply.add(self.parent_inode(ino), 2, FileType::Directory, "..") {
+            reply.ok();
+            return;
+        }
+        for entry in snapshot.after(offset) {
+            if reply.add(entry.ino, entry.cookie, entry.kind, &entry.name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
 
-        if offset == 0 {
-            let _ = reply.add(ino, 0, FileType::Directory, ".");
-            let parent_ino = if ino == ROOT_INODE {
-                ROOT_INODE
-            } else {
-                let parent_path = path.parent().unwrap_or_else(|| Path::new("/"));
-                self.get_or_create_inode(parent_path)
+    // Like readdir, with the attributes of every entry, which the kernel
+    // caches as if each had been looked up.
+    fn do_readdirplus(&self, ino: u64, fh: u64, offset: i64, mut reply: ReplyDirectoryPlus) {
+        if is_control_inode(ino) {
+            self.control_readdirplus(ino, offset, reply);
+            return;
+        }
+        let (path, snapshot) = match self.open_snapshot(ino, fh, offset) {
+            Ok(opened) => opened,
+            Err(errno) => {
+                reply.error(errno);
+                return;
+            }
+        };
+        // The kernel only takes the inode numbers of "." and "..".
+        let dot = dot_attr(ino);
+        if offset < 1 && reply.add(ino, 1, ".", &Duration::ZERO, &dot, 0) {
+            reply.ok();
+            return;
+        }
+        let parent = self.parent_inode(ino);
+        if offset < 2 && reply.add(parent, 2, "..", &Duration::ZERO, &FileAttr { ino: parent, ..dot }, 0) {
+            reply.ok();
+            return;
+        }
+        // A stream read slowly outlives the entries remembered when it
+        // started; they are listed again once. Entries gone from the pool
+        // since are left out.
+        let mut relisted = false;
+        for entry in snapshot.after(offset) {
+            let mut attr = self.remembered_attr(entry.ino);
+            if attr.is_none() && !relisted {
+                relisted = true;
+                self.relist(&path);
+                attr = self.remembered_attr(entry.ino);
+            }
+            let Some(attr) = attr else {
+                continue;
             };
-            let _ = reply.add(parent_ino, 1, FileType::Directory, "..");
-
-            let listing_result = self.tokio_handle.block_on(
-                list_directory(&self.pool_root, path.to_str().unwrap_or("/"))
-            );
-
-            match listing_result {
-                Ok(listing) => {
-                    for (i, (name, entry)) in listing.iter().enumerate() {
-                        let child_path = path.join(name);
-                        let child_ino = self.get_or_create_inode(&child_path);
-                        let kind = match entry {
-                            Entry::File(_) => FileType::RegularFile,
-                            Entry::Directory(_) => FileType::Directory,
-                        };
-                        if reply.add(child_ino, i as i64 + 2, kind, name) {
-                            break;
-                        }
-                    }
-                }
-                Err(_) => {
-                    reply.error(libc::EIO);
-                    return;
-                }
+            if reply.add(entry.ino, entry.cookie, &entry.name, &self.ttl(), &attr, 0) {
+                break;
             }
         }
         reply.ok();
     }
 
-    fn open(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
-        // TODO: Implement file opening based on path.
+    // The listing of directory `ino` a readdir stream at `offset` continues
+    // from, taken afresh when the stream starts.
+    fn open_snapshot(&self, ino: u64, fh: u64, offset: i64) -> Result<(PathBuf, Arc<DirSnapshot>), i32> {
+        let path = self.path_of(ino).ok_or(libc::ENOENT)?;
+        if !self.reachable(&path) {
+            return Err(libc::EACCES);
+        }
+        let cached = self.open_dirs.lock().unwrap().get(&fh).cloned();
+        let snapshot = match cached {
+            Some(snapshot) if offset != 0 => snapshot,
+            _ => {
+                let snapshot = Arc::new(self.snapshot(ino, &path)?);
+                self.open_dirs.lock().unwrap().insert(fh, Arc::clone(&snapshot));
+                snapshot
+            }
+        };
+        Ok((path, snapshot))
+    }
+
+    fn parent_inode(&self, ino: u64) -> u64 {
+        self.view.inodes.lock().unwrap().parent_of(ino).unwrap_or(ROOT_INODE)
+    }
+
+    // Lists directory `path` again to remember its entries.
+    fn relist(&self, path: &Path) {
+        let started = Instant::now();
+        match utf8(path.as_os_str()).and_then(|path_str| self.backend.list_directory(path_str)) {
+            Ok(listing) => self.remember_entries(listing.into_iter().map(|(name, entry)| (path.join(name), entry))),
+            Err(errno) => self.log_op("readdirplus", path, started, Some(errno)),
+        }
+    }
+
+    // Lists directory `ino` at `path` for a new readdir stream.
+    fn snapshot(&self, ino: u64, path: &Path) -> Result<DirSnapshot, i32> {
+        let started = Instant::now();
+        let listing = utf8(path.as_os_str()).and_then(|path_str| self.backend.list_directory(path_str));
+        let listing = match listing {
+            Ok(listing) => listing,
+            Err(errno) => {
+                self.log_op("readdir", path, started, Some(errno));
+                return Err(errno);
+            }
+        };
+        // Names are listed in the mount's normalization form; stored names
+        // that collide in that form are listed once, as the entry a lookup
+        // of the listed name finds.
+        let listing = self.visible(path, listing);
+        let shown: HashSet<Cow<'_, str>> =
+            listing.keys().map(|name| normalize(name, self.options.normalization)).collect();
+        let mut entries = Vec::new();
+        let mut found = Vec::new();
+        for shown in shown {
+            let Some((name, entry)) = find_entry(&listing, &shown, &self.options) else {
+                continue;
+            };
+            let child_path = path.join(name);
+            let child_ino = self.child_inode(ino, name);
+            found.push((child_path, entry.clone()));
+            entries.push((child_ino, file_type(entry.kind), shown.into_owned()));
+        }
+        self.remember_entries(found);
+        self.touch_atime(ino);
+        self.log_op("readdir", path, started, None);
+        Ok(DirSnapshot::new(entries))
+    }
+
+    fn do_read(&self, fh: u64, offset: i64, size: u32, reply: ReplyData) {
+        if let Some(handle) = self.open_files.lock().unwrap().get_mut(&fh) {
+            if offset <= 0 {
+                handle.content = handle.file.render(self.backend.stats()).into_bytes();
+            }
+            let data = &handle.content;
+            let start = (offset.max(0) as usize).min(data.len());
+            let end = start.saturating_add(size as usize).min(data.len());
+            if let Some(op) = inflight::current() {
+                self.backend.stats().record_io(&op, 0, (end - start) as u64, 0);
+            }
+            reply.data(&data[start..end]);
+            return;
+        }
+        // TODO: Implement file reading based on path.
         reply.error(libc::ENOENT);
     }
 
-    fn read(
+    fn do_statfs(&self, reply: ReplyStatfs) {
+        let started = Instant::now();
+        match self.backend.space() {
+            Ok(space) => {
+                reply.statfs(
+                    space.blocks,
+                    space.blocks_free,
+                    space.blocks_available,
+                    space.files,
+                    space.files_free,
+                    space.block_size,
+                    space.name_max.min(self.options.name_max),
+                    space.fragment_size,
+                );
+                self.log_op("statfs", Path::new("/"), started, None);
+            }
+            Err(errno) => {
+                reply.error(errno);
+                self.log_op("statfs", Path::new("/"), started, Some(errno));
+            }
+        }
+    }
+}
+
+impl Filesystem for RfsFuse {
+    // Negotiates the capabilities the mount's options ask for, and records
+    // the outcome in the mount's stats.
+    fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), libc::c_int> {
+        let negotiated = capabilities::negotiate(config, capabilities::wanted(&self.fs.options));
+        let message = format!(
+            "Negotiated FUSE capabilities: enabled {}; unsupported by the kernel {}; declined {}",
+            negotiated.names(Outcome::Enabled),
+            negotiated.names(Outcome::Unsupported),
+            negotiated.names(Outcome::Declined)
+        );
+        Record::new(Subsystem::Fuse, LogLevel::Info, &message).mount(&self.fs.mount_point).emit();
+        let outcomes = negotiated.0.iter().map(|(name, outcome)| (*name, outcome.name())).collect();
+        self.fs.backend.stats().set_capabilities(outcomes);
+        Ok(())
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        self.dispatch(&self.metadata, req, "getattr", self.op_path(ino, None), move |fs| fs.do_getattr(ino, reply));
+    }
+
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let name = name.to_owned();
+        self.dispatch(&self.metadata, req, "lookup", self.op_path(parent, Some(&name)), move |fs| fs.do_lookup(parent, &name, reply));
+    }
+
+    fn readdir(
         &mut self,
-        _req: &Request<'_>,
-        _ino: u64,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        reply: ReplyDirectory,
+    ) {
+        self.dispatch(&self.metadata, req, "readdir", self.op_path(ino, None), move |fs| fs.do_readdir(ino, fh, offset, reply));
+    }
+
+    fn readdirplus(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        reply: ReplyDirectoryPlus,
+    ) {
+        self.dispatch(&self.metadata, req, "readdirplus", self.op_path(ino, None), move |fs| {
+            fs.do_readdirplus(ino, fh, offset, reply)
+        });
+    }
+
+    fn opendir(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
+        reply.opened(self.fs.next_fh.fetch_add(1, Ordering::Relaxed), 0);
+    }
+
+    fn releasedir(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, _flags: i32, reply: ReplyEmpty) {
+        self.fs.open_dirs.lock().unwrap().remove(&fh);
+        reply.ok();
+    }
+
+    fn statfs(&mut self, req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        self.dispatch(&self.metadata, req, "statfs", self.op_path(ROOT_INODE, None), move |fs| fs.do_statfs(reply));
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = name.to_owned();
+        self.dispatch(&self.metadata, req, "mkdir", self.op_path(parent, Some(&name)), move |fs| {
+            let _locked = fs.view.locks.lock(&[parent]);
+            reply.error(fs.mutation_errno(parent, &name));
+        });
+    }
+
+    // FIFOs and sockets as well as regular files created with mknod.
+    fn mknod(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = name.to_owned();
+        self.dispatch(&self.metadata, req, "mknod", self.op_path(parent, Some(&name)), move |fs| {
+            let _locked = fs.view.locks.lock(&[parent]);
+            reply.error(fs.mutation_errno(parent, &name));
+        });
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        link_name: &OsStr,
+        _target: &Path,
+        reply: ReplyEntry,
+    ) {
+        let link_name = link_name.to_owned();
+        self.dispatch(&self.metadata, req, "symlink", self.op_path(parent, Some(&link_name)), move |fs| {
+            let _locked = fs.view.locks.lock(&[parent]);
+            reply.error(fs.mutation_errno(parent, &link_name));
+        });
+    }
+
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        self.dispatch(&self.metadata, req, "unlink", self.op_path(parent, Some(name)), move |fs| {
+            let _locked = fs.view.locks.lock(&[parent]);
+            reply.error(fs.backend.mutation_errno());
+        });
+    }
+
+    fn rmdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        self.dispatch(&self.metadata, req, "rmdir", self.op_path(parent, Some(name)), move |fs| {
+            let _locked = fs.view.locks.lock(&[parent]);
+            reply.error(fs.backend.mutation_errno());
+        });
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        _name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        let newname = newname.to_owned();
+        self.dispatch(&self.metadata, req, "rename", self.op_path(newparent, Some(&newname)), move |fs| {
+            let _locked = fs.view.locks.lock(&[parent, newparent]);
+            // The target may exist, case-folded or not, and is then replaced,
+            // so only creations look for colliding names.
+            match fs.target_parent(newparent, &newname) {
+                Ok(_) => reply.error(fs.backend.mutation_errno()),
+                Err(errno) => reply.error(errno),
+            }
+        });
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let name = name.to_owned();
+        self.dispatch(&self.metadata, req, "create", self.op_path(parent, Some(&name)), move |fs| {
+            let _locked = fs.view.locks.lock(&[parent]);
+            reply.error(fs.mutation_errno(parent, &name));
+        });
+    }
+
+    fn write(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
         _fh: u64,
         _offset: i64,
-        _size: u32,
+        _data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        self.dispatch(&self.data, req, "write", self.op_path(ino, None), move |fs| {
+            let _locked = fs.view.locks.lock(&[ino]);
+            reply.error(fs.backend.mutation_errno());
+        });
+    }
+
+    fn open(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        if let Some(file) = ControlFile::from_inode(ino) {
+            if !self.fs.control_allowed(Some(req.uid())) || flags & libc::O_ACCMODE != libc::O_RDONLY {
+                reply.error(libc::EACCES);
+                return;
+            }
+            let fh = self.fs.next_fh.fetch_add(1, Ordering::Relaxed);
+            let content = file.render(self.fs.backend.stats()).into_bytes();
+            self.fs.open_files.lock().unwrap().insert(fh, ControlHandle { file, content });
+            // The size reported by getattr may be stale by the time the file
+            // is read, so bypass the page cache and let reads see the content.
+            reply.opened(fh, fuser::consts::FOPEN_DIRECT_IO);
+            return;
+        }
+        self.dispatch(&self.metadata, req, "open", self.op_path(ino, None), move |fs| fs.do_open(ino, flags, reply));
+    }
+
+    fn read(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
         _flags: i32,
         _lock_owner: Option<u64>,
         reply: ReplyData,
     ) {
-        // TODO: Implement file reading based on path.
-        reply.error(libc::ENOENT);
+        self.dispatch(&self.data, req, "read", self.op_path(ino, None), move |fs| fs.do_read(fh, offset, size, reply));
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.fs.open_files.lock().unwrap().remove(&fh);
+        self.fs.pollers.lock().unwrap().remove(&fh);
+        reply.ok();
+    }
+
+    // Only `.rfs` files wait for changes; everything else is always ready.
+    // Replying ENOSYS here would make the kernel stop asking for the whole mount.
+    fn poll(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        ph: PollHandle,
+        _events: u32,
+        flags: u32,
+        reply: ReplyPoll,
+    ) {
+        if ControlFile::from_inode(ino).is_some() {
+            self.fs.control_poll(fh, ph, flags, reply);
+        } else {
+            reply.poll((libc::POLLIN | libc::POLLOUT | libc::POLLRDNORM | libc::POLLWRNORM) as u32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{MountOptions, Normalization, PermissionChecks, ReaddirPlus};
+    use crate::hidden::HiddenPaths;
+    use crate::testing::{can_act_as_other_users, MemoryStore, TestMount};
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::Path;
+
+    fn pool() -> MemoryStore {
+        MemoryStore::default()
+            .file("/docs/readme.txt", 42)
+            .file("/Photo.JPG", 5000)
+            .dir("/empty")
+    }
+
+    fn names(mount: &TestMount, dir: &str) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(mount.path(dir))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn readdir_lists_pool_entries() {
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        assert_eq!(names(&mount, "/"), ["Photo.JPG", "docs", "empty"]);
+        assert_eq!(names(&mount, "/docs"), ["readme.txt"]);
+        assert!(names(&mount, "/empty").is_empty());
+    }
+
+    #[test]
+    fn readdir_spanning_several_replies_lists_every_entry_once() {
+        let store = (0..2000).fold(MemoryStore::default(), |store, i| store.file(&format!("/big/file-{:04}", i), 1));
+        let Some(mount) = TestMount::new(store, MountOptions::default()) else {
+            return;
+        };
+        let listed = names(&mount, "/big");
+        let expected: Vec<String> = (0..2000).map(|i| format!("file-{:04}", i)).collect();
+        assert_eq!(listed, expected);
+    }
+
+    #[test]
+    fn stat_reports_kind_and_size() {
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let file = fs::metadata(mount.path("/docs/readme.txt")).unwrap();
+        assert!(file.is_file());
+        assert_eq!(file.len(), 42);
+        assert!(fs::metadata(mount.path("/docs")).unwrap().is_dir());
+    }
+
+    #[test]
+    fn directory_link_counts() {
+        use std::os::unix::fs::MetadataExt;
+
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        assert_eq!(fs::metadata(mount.path("/")).unwrap().nlink(), 1);
+        assert_eq!(fs::metadata(mount.path("/docs")).unwrap().nlink(), 1);
+        drop(mount);
+
+        let options = MountOptions {
+            count_subdirs: true,
+            ..MountOptions::default()
+        };
+        let Some(mount) = TestMount::new(pool(), options) else {
+            return;
+        };
+        assert_eq!(fs::metadata(mount.path("/")).unwrap().nlink(), 4);
+        assert_eq!(fs::metadata(mount.path("/docs")).unwrap().nlink(), 2);
+        assert_eq!(fs::metadata(mount.path("/docs/readme.txt")).unwrap().nlink(), 1);
+    }
+
+    #[test]
+    fn getattr_after_lookup_does_not_list_again() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let path = CString::new(mount.path("/docs/readme.txt").as_os_str().as_bytes()).unwrap();
+        // AT_STATX_FORCE_SYNC makes the kernel send a getattr even though
+        // the lookup just returned the attributes.
+        let mut statx = unsafe { std::mem::zeroed::<libc::statx>() };
+        let flags = libc::AT_STATX_FORCE_SYNC;
+        let result = unsafe { libc::statx(libc::AT_FDCWD, path.as_ptr(), flags, libc::STATX_BASIC_STATS, &mut statx) };
+        assert_eq!(result, 0);
+        assert_eq!(statx.stx_size, 42);
+        assert_eq!(mount.store.listings("/docs"), 1);
+    }
+
+    #[test]
+    fn mounts_of_one_pool_share_inodes_and_entries() {
+        use std::os::unix::fs::MetadataExt;
+
+        let Some(first) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let options = MountOptions {
+            chunk_size: 65536,
+            ..MountOptions::default()
+        };
+        let Some(second) = TestMount::sharing(&first, options) else {
+            return;
+        };
+        let listed = fs::read_dir(first.path("/docs")).unwrap().count();
+        assert_eq!(listed, 1);
+        let in_first = fs::symlink_metadata(first.path("/docs/readme.txt")).unwrap();
+        let in_second = fs::symlink_metadata(second.path("/docs/readme.txt")).unwrap();
+        assert_eq!(in_first.ino(), in_second.ino());
+        // Attributes still follow the options of the mount.
+        assert_eq!((in_first.blksize(), in_second.blksize()), (4096, 65536));
+        assert_eq!(first.store.listings("/docs"), 1);
+    }
+
+    #[test]
+    fn views_of_a_subpath_reuse_the_entries_of_their_mount() {
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let Some(view) = TestMount::view_of(&mount, "/docs", MountOptions::default()) else {
+            return;
+        };
+        assert_eq!(fs::read_dir(mount.path("/docs")).unwrap().count(), 1);
+        assert_eq!(fs::symlink_metadata(view.path("/readme.txt")).unwrap().len(), 42);
+        assert_eq!(mount.store.listings("/docs"), 1);
+    }
+
+    #[test]
+    fn users_see_and_reach_only_their_own_home() {
+        use std::os::unix::fs::MetadataExt;
+
+        if !can_act_as_other_users() {
+            return;
+        }
+        let store = MemoryStore::default().file("/users/nobody/notes.txt", 5).dir("/users/alice");
+        let options = MountOptions {
+            home: "/users/%u".to_string(),
+            ..MountOptions::default()
+        };
+        let Some(mount) = TestMount::new(store, options) else {
+            return;
+        };
+        assert_eq!(names(&mount, "/"), ["alice", "nobody"]);
+
+        // Requests carry the fsuid of the caller, so this thread acts as nobody.
+        let root = mount.root.clone();
+        let seen = std::thread::spawn(move || {
+            unsafe { libc::setfsuid(65534) };
+            let listed: Vec<_> = fs::read_dir(&root).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+            let notes = fs::metadata(root.join("nobody/notes.txt")).map(|meta| (meta.len(), meta.uid()));
+            let other = fs::metadata(root.join("alice")).map_err(|e| e.raw_os_error());
+            let control = fs::read_to_string(root.join(".rfs/errors")).map_err(|e| e.raw_os_error());
+            (listed, notes.unwrap(), other.err(), control.err())
+        })
+        .join()
+        .unwrap();
+        let missing = Some(Some(libc::ENOENT));
+        assert_eq!(seen, (vec!["nobody".into()], (5, 65534), missing, missing));
+        // `.rfs` tells about every user, so only root reads it.
+        assert!(fs::read_to_string(mount.path("/.rfs/io")).unwrap().contains("uid=65534 "));
+    }
+
+    #[test]
+    fn other_users_need_allow_other_and_the_kernel_checks_modes_on_request() {
+        // Runs `probe` on the mount as nobody, the caller's fsuid being the requester.
+        fn as_nobody<T: Send + 'static>(mount: &TestMount, probe: fn(&Path) -> T) -> T {
+            let root = mount.root.clone();
+            std::thread::spawn(move || {
+                unsafe { libc::setfsuid(65534) };
+                probe(&root)
+            })
+            .join()
+            .unwrap()
+        }
+        let create = |root: &Path| fs::File::create(root.join("docs/new.txt")).map_err(|e| e.raw_os_error()).err();
+        if !can_act_as_other_users() {
+            return;
+        }
+
+        let Some(private) = TestMount::new(pool().file("/docs/notes.txt", 5), MountOptions::default()) else {
+            return;
+        };
+        let stat = as_nobody(&private, |root| fs::metadata(root.join("docs")).map_err(|e| e.raw_os_error()).err());
+        assert_eq!(stat, Some(Some(libc::EACCES)));
+
+        let options = MountOptions {
+            allow_other: true,
+            permissions: PermissionChecks::Kernel,
+            ..MountOptions::default()
+        };
+        let Some(shared) = TestMount::new(pool().file("/docs/notes.txt", 5), options) else {
+            return;
+        };
+        assert_eq!(as_nobody(&shared, |root| fs::metadata(root.join("docs/notes.txt")).map(|meta| meta.len()).ok()), Some(5));
+        // The kernel refuses nobody a write to root's directory before rfs sees it.
+        assert_eq!(as_nobody(&shared, create), Some(Some(libc::EACCES)));
+    }
+
+    #[test]
+    fn listed_entries_are_looked_up_without_listing_again() {
+        let store = pool().file("/docs/notes.txt", 7).dir("/docs/drafts");
+        let Some(mount) = TestMount::new(store, MountOptions::default()) else {
+            return;
+        };
+        let sizes: Vec<u64> = fs::read_dir(mount.path("/docs"))
+            .unwrap()
+            .map(|entry| fs::symlink_metadata(entry.unwrap().path()).unwrap().len())
+            .collect();
+        assert_eq!(sizes.len(), 3);
+        assert_eq!(mount.store.listings("/docs"), 1);
+    }
+
+    #[test]
+    fn backend_errors_are_listed_in_the_errors_file() {
+        let Some(mount) = TestMount::new(pool().failing("/docs", libc::EACCES), MountOptions::default()) else {
+            return;
+        };
+        let err = fs::metadata(mount.path("/docs/readme.txt")).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EACCES));
+        assert!(fs::metadata(mount.path("/missing")).is_err());
+
+        let errors = fs::read_to_string(mount.path("/.rfs/errors")).unwrap();
+        let lines: Vec<&str> = errors.lines().collect();
+        assert_eq!(lines.len(), 1, "{}", errors);
+        assert!(lines[0].contains(r#"op=lookup path="/docs" errno=13"#), "{}", lines[0]);
+    }
+
+    #[test]
+    fn missing_entries_are_not_found() {
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let err = fs::metadata(mount.path("/docs/missing")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn entries_removed_behind_the_kernel_are_stale() {
+        use std::os::fd::AsRawFd;
+
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let docs = fs::File::open(mount.path("/docs")).unwrap();
+        mount.store.remove("/docs");
+        // Wait for the kernel's cached attributes to expire.
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+        assert_eq!(unsafe { libc::fstat(docs.as_raw_fd(), &mut stat) }, -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ESTALE));
+        let err = fs::metadata(mount.path("/docs")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn mutations_are_not_supported() {
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let err = fs::create_dir(mount.path("/new")).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOSYS));
+        let fifo = std::ffi::CString::new(mount.path("/fifo").into_os_string().into_encoded_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ENOSYS));
+    }
+
+    #[test]
+    fn statfs_leaves_out_the_reserve() {
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        // MemoryStore has 2^20 blocks, half of them free; the default
+        // reserve is 5% of all blocks.
+        let space = nix::sys::statvfs::statvfs(&mount.root).unwrap();
+        assert_eq!(space.blocks(), 1 << 20);
+        assert_eq!(space.blocks_free(), 1 << 19);
+        assert_eq!(space.blocks_available(), (1 << 19) - (1 << 20) * 5 / 100);
+    }
+
+    #[test]
+    fn opens_for_writing_are_refused() {
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let err = fs::OpenOptions::new().write(true).open(mount.path("/docs/readme.txt")).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+        let err = fs::OpenOptions::new().write(true).open(mount.path("/docs")).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EISDIR));
+    }
+
+    #[test]
+    fn overlong_names_are_rejected() {
+        let options = MountOptions {
+            name_max: 8,
+            ..MountOptions::default()
+        };
+        let Some(mount) = TestMount::new(pool(), options) else {
+            return;
+        };
+        let err = fs::metadata(mount.path("/a-name-longer-than-eight")).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENAMETOOLONG));
+    }
+
+    #[test]
+    fn hidden_paths_are_neither_listed_nor_found() {
+        let options = MountOptions {
+            hide: HiddenPaths::try_from(vec!["*.txt".to_string()]).unwrap(),
+            ..MountOptions::default()
+        };
+        let Some(mount) = TestMount::new(pool(), options) else {
+            return;
+        };
+        assert!(names(&mount, "/docs").is_empty());
+        let err = fs::metadata(mount.path("/docs/readme.txt")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn names_colliding_after_normalization_list_the_entry_lookups_find() {
+        use std::os::unix::fs::{DirEntryExt, MetadataExt};
+
+        // Each directory stores café.txt composed (NFC, 2 bytes) and
+        // decomposed (NFD, 1 byte); /hidden hides the composed one.
+        let (nfc, nfd) = ("caf\u{e9}.txt", "cafe\u{301}.txt");
+        let store = MemoryStore::default()
+            .file(&format!("/shown/{}", nfc), 2)
+            .file(&format!("/shown/{}", nfd), 1)
+            .file(&format!("/hidden/{}", nfc), 2)
+            .file(&format!("/hidden/{}", nfd), 1);
+        let options = MountOptions {
+            normalization: Normalization::Nfc,
+            hide: HiddenPaths::try_from(vec![format!("hidden/{}", nfc)]).unwrap(),
+            ..MountOptions::default()
+        };
+        let Some(mount) = TestMount::new(store, options) else {
+            return;
+        };
+        for (dir, len) in [("/shown", 2), ("/hidden", 1)] {
+            let listed: Vec<_> = fs::read_dir(mount.path(dir)).unwrap().map(|entry| entry.unwrap()).collect();
+            assert_eq!(listed.len(), 1, "{}", dir);
+            assert_eq!(listed[0].file_name(), nfc);
+            let found = fs::metadata(mount.path(&format!("{}/{}", dir, nfc))).unwrap();
+            assert_eq!((found.ino(), found.len()), (listed[0].ino(), len), "{}", dir);
+        }
+    }
+
+    #[test]
+    fn case_insensitive_lookup_finds_stored_name() {
+        let options = MountOptions {
+            case_insensitive: true,
+            ..MountOptions::default()
+        };
+        let Some(mount) = TestMount::new(pool(), options) else {
+            return;
+        };
+        assert_eq!(fs::metadata(mount.path("/photo.jpg")).unwrap().len(), 5000);
+        assert_eq!(names(&mount, "/"), ["Photo.JPG", "docs", "empty"]);
+        // Renaming onto an entry is refused as a mutation, not as a collision.
+        let err = fs::rename(mount.path("/docs"), mount.path("/EMPTY")).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOSYS));
+    }
+
+    #[test]
+    fn polling_a_control_file_waits_for_a_change() {
+        use std::os::fd::AsRawFd;
+        use std::sync::atomic::Ordering;
+
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let stats = fs::File::open(mount.path("/.rfs/stats")).unwrap();
+        let poll = |timeout_ms| {
+            let mut fd = libc::pollfd {
+                fd: stats.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            unsafe { libc::poll(&mut fd, 1, timeout_ms) }
+        };
+        assert_eq!(poll(0), 0);
+        mount.stats.remounts.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(poll(5000), 1);
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut &stats, &mut content).unwrap();
+        assert!(content.contains("remounts=1"));
+    }
+
+    #[test]
+    fn control_directory_serves_stats() {
+        let Some(mount) = TestMount::new(pool(), MountOptions::default()) else {
+            return;
+        };
+        let stats = fs::read_to_string(mount.path("/.rfs/stats")).unwrap();
+        assert!(stats.contains(&format!("mount_point={}", mount.stats.mount_point)));
+        assert!(!names(&mount, "/").contains(&".rfs".to_string()));
+    }
+
+    #[test]
+    fn direct_mounts_serve_the_pool_without_fusermount() {
+        let Some(mount) = TestMount::direct(pool(), MountOptions::default()) else {
+            return;
+        };
+        assert_eq!(names(&mount, "/docs"), ["readme.txt"]);
+        assert_eq!(fs::metadata(mount.path("/docs/readme.txt")).unwrap().len(), 42);
+        let mounts = fs::read_to_string("/proc/self/mounts").unwrap();
+        let line = mounts.lines().find(|line| line.split(' ').nth(1) == mount.root.to_str()).unwrap();
+        assert!(line.starts_with("rfs ") && line.contains("nosuid,nodev"), "{}", line);
+    }
+
+    #[test]
+    fn readdirplus_saves_the_lookups_of_listed_entries() {
+        // Requests for listing /docs and stating its file, with readdirplus as given.
+        let requests = |readdirplus| {
+            let options = MountOptions {
+                readdirplus,
+                ..MountOptions::default()
+            };
+            let mount = TestMount::new(pool(), options)?;
+            assert_eq!(names(&mount, "/docs"), ["readme.txt"]);
+            assert_eq!(fs::metadata(mount.path("/docs/readme.txt")).unwrap().len(), 42);
+            let negotiated = mount.stats.render().lines().find(|line| line.starts_with("capabilities=")).map(str::to_string);
+            // Requests are made as whoever runs the tests.
+            let uid = nix::unistd::geteuid().as_raw();
+            Some((mount.stats.io.uids()[&uid].ops, negotiated.unwrap()))
+        };
+        let Some((plain, negotiated)) = requests(ReaddirPlus::Off) else {
+            return;
+        };
+        assert!(!negotiated.contains("readdirplus"), "{}", negotiated);
+        let (plus, negotiated) = requests(ReaddirPlus::Always).unwrap();
+        assert!(negotiated.contains("readdirplus") && !negotiated.contains("readdirplus_auto"), "{}", negotiated);
+        assert!(plus < plain, "{} requests with readdirplus, {} without", plus, plain);
+    }
+
+    #[test]
+    fn io_is_accounted_to_the_requesting_uid_and_process() {
+        let options = MountOptions {
+            account_pids: true,
+            ..MountOptions::default()
+        };
+        let Some(mount) = TestMount::new(pool(), options) else {
+            return;
+        };
+        let stats = fs::read_to_string(mount.path("/.rfs/stats")).unwrap();
+        fs::metadata(mount.path("/docs")).unwrap();
+
+        // Whoever runs the tests, root or not, is the requester.
+        let uid = nix::unistd::geteuid().as_raw();
+        let uids = mount.stats.io.uids();
+        assert_eq!(uids[&uid].bytes_read, stats.len() as u64);
+        assert!(uids[&uid].ops >= 2);
+        let exe = std::env::current_exe().unwrap();
+        let pids = mount.stats.io.pids();
+        assert!(pids.values().all(|process| process.uid == uid));
+        assert!(pids.values().any(|process| process.exe.as_deref() == exe.to_str()));
+        let io = fs::read_to_string(mount.path("/.rfs/io")).unwrap();
+        assert!(io.starts_with(&format!("uid={} ops=", uid)), "{}", io);
     }
 }
@@ -0,0 +1,186 @@
+// src/fuse_mount.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+// Attaching a mount to the kernel, through the fusermount helper or with
+// mount(2) directly for hosts that do not ship the helper.
+
+use crate::config::{MountMethod, MountOptions, PermissionChecks};
+use crate::fs::RfsFuse;
+use crate::logging::{log, Subsystem};
+use crate::unmount::detach;
+use crate::user_dirs;
+use fuser::{BackgroundSession, MountOption, SessionACL};
+use nix::mount::MsFlags;
+use nix::unistd::{getegid, geteuid};
+use rfs_utils::LogLevel;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+const HELPERS: [&str; 2] = ["fusermount3", "fusermount"];
+const FUSE_CONF: &str = "/etc/fuse.conf";
+
+// Who may use a mount and how.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Access {
+    // Users other than the owner may use it; root always can.
+    pub allow_other: bool,
+    // The kernel checks the reported owner and mode bits.
+    pub default_permissions: bool,
+    pub read_only: bool,
+}
+
+impl Access {
+    // Access to a mount with `options`.
+    pub fn of(options: &MountOptions, read_only: bool) -> Self {
+        Self {
+            // Per-user homes are only of use when other users can reach the mount.
+            allow_other: options.allow_other || !options.home.is_empty(),
+            default_permissions: options.permissions == PermissionChecks::Kernel,
+            read_only,
+        }
+    }
+}
+
+// Mounts `fs` at `mount_point` and starts serving it.
+pub fn mount(fs: RfsFuse, mount_point: &Path, method: MountMethod, access: Access) -> io::Result<BackgroundSession> {
+    if access.allow_other && user_dirs::unprivileged() && !fuse_conf_allows_other() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "allow_other for {} needs user_allow_other in {} unless rfs-fuse runs as root",
+                mount_point.display(),
+                FUSE_CONF
+            ),
+        ));
+    }
+    let direct = match method {
+        MountMethod::Fusermount => false,
+        MountMethod::Direct => true,
+        MountMethod::Auto if helper_installed() => false,
+        MountMethod::Auto => {
+            if user_dirs::unprivileged() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no fusermount3 or fusermount helper found in PATH, and mounting without it needs root",
+                ));
+            }
+            log(
+                Subsystem::Mount,
+                LogLevel::Info,
+                &format!("No fusermount helper found; mounting {} with mount(2)", mount_point.display()),
+            );
+            true
+        }
+    };
+    if direct {
+        let device = attach(mount_point, access)?;
+        let acl = if access.allow_other { SessionACL::All } else { SessionACL::RootAndOwner };
+        return fs.spawn_on(device, acl);
+    }
+    let mut options = vec![
+        MountOption::FSName("rfs".to_string()),
+        MountOption::AutoUnmount,
+    ];
+    // Like allow_other, fusermount lets users pass allow_root only with
+    // user_allow_other, so a user's mount is theirs alone.
+    if access.allow_other {
+        options.push(MountOption::AllowOther);
+    } else if !user_dirs::unprivileged() {
+        options.push(MountOption::AllowRoot); // Often needed for system-wide mounts
+    }
+    if access.default_permissions {
+        options.push(MountOption::DefaultPermissions);
+    }
+    if access.read_only {
+        options.push(MountOption::RO);
+    }
+    // This returns the session guard which must be kept alive. fusermount
+    // reports why it refused allow_other only on its stderr.
+    fs.spawn(mount_point, &options).map_err(|e| match access.allow_other {
+        true => {
+            let message = format!("{} (mounted with allow_other, see user_allow_other in {})", e, FUSE_CONF);
+            io::Error::new(e.kind(), message)
+        }
+        false => e,
+    })
+}
+
+// Mounts a FUSE filesystem at `mount_point` with mount(2) and returns the
+// connection to serve it on. The kernel lets every user through; the
+// session keeps out those the mount is not for. Nothing unmounts it if the
+// daemon dies, so a dead mount left at the mount point is detached first.
+pub fn attach(mount_point: &Path, access: Access) -> io::Result<OwnedFd> {
+    let metadata = match fs::metadata(mount_point) {
+        Err(e) if e.raw_os_error() == Some(libc::ENOTCONN) => {
+            detach(mount_point)?;
+            fs::metadata(mount_point)?
+        }
+        metadata => metadata?,
+    };
+    let device = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/fuse")
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot open /dev/fuse: {}", e)))?;
+    let mut data = format!(
+        "fd={},rootmode={:o},user_id={},group_id={},allow_other",
+        device.as_raw_fd(),
+        metadata.mode() & libc::S_IFMT,
+        geteuid(),
+        getegid()
+    );
+    if access.default_permissions {
+        data.push_str(",default_permissions");
+    }
+    let mut flags = MsFlags::MS_NOSUID | MsFlags::MS_NODEV;
+    if access.read_only {
+        flags |= MsFlags::MS_RDONLY;
+    }
+    nix::mount::mount(Some("rfs"), mount_point, Some("fuse"), flags, Some(data.as_str())).map_err(|errno| {
+        let hint = if errno == nix::errno::Errno::EPERM {
+            "; it needs CAP_SYS_ADMIN, otherwise set [fuse] mount_method = \"fusermount\""
+        } else {
+            ""
+        };
+        io::Error::new(
+            io::Error::from(errno).kind(),
+            format!("mount(2) at {} failed: {}{}", mount_point.display(), errno.desc(), hint),
+        )
+    })?;
+    Ok(device.into())
+}
+
+// Whether fusermount3 or fusermount is in PATH.
+pub fn helper_installed() -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    env::split_paths(&path).any(|dir| HELPERS.iter().any(|helper| dir.join(helper).is_file()))
+}
+
+// Whether /etc/fuse.conf lets users other than root mount with allow_other.
+fn fuse_conf_allows_other() -> bool {
+    fs::read_to_string(FUSE_CONF).is_ok_and(|contents| allows_other(&contents))
+}
+
+fn allows_other(fuse_conf: &str) -> bool {
+    fuse_conf.lines().any(|line| line.split('#').next().unwrap_or_default().trim() == "user_allow_other")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::allows_other;
+
+    #[test]
+    fn user_allow_other_is_found_in_fuse_conf() {
+        assert!(allows_other("# mount_max = 1000\nuser_allow_other\n"));
+        assert!(allows_other("  user_allow_other  # for rfs-fuse\n"));
+        assert!(!allows_other("#user_allow_other\n"));
+        assert!(!allows_other(""));
+    }
+}
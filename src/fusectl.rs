@@ -0,0 +1,102 @@
+// src/fusectl.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+// Command-line client for the rfs-fuse control socket. It sends its
+// arguments as one command and prints the response, e.g. `rfs-fusectl ops`.
+
+mod bundle;
+mod user_dirs;
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: rfs-fusectl [--socket <path>] <command>
+
+commands:
+  status    state of every mount
+  metrics   counters in the Prometheus text format
+  ops       FUSE operations currently executing, per mount
+  io [<mount point>]
+            operations and bytes by requesting uid and, with account_pids,
+            by process, of every mount or of one
+  errors [<mount point>]
+            latest failed backend calls, of every mount or of one
+  stats [--json] [<mount point>]
+            state and counters, of every mount or of one; --json prints
+            one document with cache sizes, operations, scrub and errors
+  reset [<mount point>]
+            set the counters back to 0, printing the values they had
+  mount <pool-id> <mount point> [subpath=<dir>] [root=<dir>] [ro]
+            mount a pool, or one of its directories (needs [control] mounts);
+            root= names the pool's directory when pool.toml lacks it
+  unmount <mount point>
+            unmount a mount made with mount
+  support-bundle [<file>]
+            write configuration (secrets redacted), state, stats, errors
+            and version details to a tar archive for a bug report";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut socket_path = env::var("RFS_FUSE_CONTROL_SOCKET")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .unwrap_or_else(default_socket);
+    if args.first().map(String::as_str) == Some("--socket") {
+        if args.len() < 2 {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+        socket_path = args.remove(1);
+        args.remove(0);
+    }
+    if args.is_empty() || args[0] == "--help" || args[0] == "-h" {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    }
+
+    if args[0] == "support-bundle" && args.len() <= 2 {
+        let output = args.get(1).cloned().unwrap_or_else(|| "rfs-fuse-support.tar".to_string());
+        return match bundle::write(&output, |command| send(&socket_path, command)) {
+            Ok(()) => {
+                println!("wrote {}", output);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("rfs-fusectl: {}: {}", output, e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    match send(&socket_path, &args.join(" ")) {
+        Ok(response) => {
+            print!("{}", response);
+            if response.starts_with("error:") { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+        }
+        Err(e) => {
+            eprintln!("rfs-fusectl: {}: {}", socket_path, e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// The daemon's default in fuse.toml. A user may be talking to the system
+// daemon rather than one of their own, so its socket is tried first.
+fn default_socket() -> String {
+    if Path::new(user_dirs::SYSTEM_SOCKET_PATH).exists() {
+        return user_dirs::SYSTEM_SOCKET_PATH.to_string();
+    }
+    user_dirs::control_socket()
+}
+
+fn send(socket_path: &str, command: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.write_all(format!("{}\n", command).as_bytes())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
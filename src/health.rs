@@ -0,0 +1,30 @@
+// src/health.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::backend::Backend;
+use crate::scrub::check_entry;
+
+// Quick check that a pool can be served, run before its mount is exposed:
+// the root must list without errors, its entries must pass the scrub's
+// metadata checks and the capacity must be readable. Returns the first
+// problem found.
+pub fn probe(backend: &Backend) -> Result<(), String> {
+    let listing = backend
+        .list_directory("/")
+        .map_err(|errno| format!("listing the root failed: {}", std::io::Error::from_raw_os_error(errno)))?;
+    let mut names: Vec<&String> = listing.keys().collect();
+    names.sort();
+    for name in names {
+        if let Some(problem) = check_entry(name, &listing[name]) {
+            return Err(format!("/{:?}: {}", name, problem));
+        }
+    }
+    let space = backend
+        .space()
+        .map_err(|errno| format!("reading the capacity failed: {}", std::io::Error::from_raw_os_error(errno)))?;
+    if space.blocks == 0 {
+        return Err("the pool reports no capacity".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+// src/hidden.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::path::Path;
+
+// Paths hidden from a mount, given as glob patterns relative to the mount
+// root. Patterns without a slash match the name at any depth (`*.key`);
+// patterns with one are anchored at the root (`internal/**`). A pattern
+// ending in `/**` also hides the directory itself.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct HiddenPaths {
+    set: GlobSet,
+}
+
+impl Default for HiddenPaths {
+    fn default() -> Self {
+        Self { set: GlobSet::empty() }
+    }
+}
+
+impl TryFrom<Vec<String>> for HiddenPaths {
+    type Error = globset::Error;
+
+    fn try_from(patterns: Vec<String>) -> Result<Self, Self::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &patterns {
+            let pattern = pattern.trim_start_matches('/');
+            let anchored = if pattern.contains('/') { pattern.to_string() } else { format!("**/{}", pattern) };
+            builder.add(glob(&anchored)?);
+            if let Some(dir) = anchored.strip_suffix("/**") {
+                builder.add(glob(dir)?);
+            }
+        }
+        Ok(Self { set: builder.build()? })
+    }
+}
+
+fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+impl HiddenPaths {
+    // Whether a path inside the mount, such as `/internal/a`, is hidden.
+    pub fn is_hidden(&self, path: &Path) -> bool {
+        if self.set.is_empty() {
+            return false;
+        }
+        let relative = path.strip_prefix("/").unwrap_or(path);
+        !relative.as_os_str().is_empty() && self.set.is_match(relative)
+    }
+}
@@ -0,0 +1,98 @@
+// src/home.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use nix::unistd::{Uid, User};
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::sync::Mutex;
+
+// Placeholder for the user name in a mount's `home` option.
+pub const USER_PLACEHOLDER: &str = "%u";
+
+// Directory of the pool holding the users' homes for a `home` option such
+// as "/users/%u", or None if the option is not set or malformed.
+pub fn homes_dir(template: &str) -> Option<&str> {
+    match template.strip_suffix(USER_PLACEHOLDER)?.strip_suffix('/')? {
+        "" => Some("/"),
+        dir if dir.starts_with('/')
+            && !dir.contains(USER_PLACEHOLDER)
+            && !dir.split('/').any(|part| part == "." || part == "..") =>
+        {
+            Some(dir)
+        }
+        _ => None,
+    }
+}
+
+// Per-user homes of a mount with the `home` option: the mount shows the
+// directory holding them, and each user sees and reaches only the entry
+// named after their user name, owned by them. root reaches every home.
+#[derive(Default)]
+pub struct Homes {
+    // User names by uid, and uid and gid by user name, as resolved so far.
+    names: Mutex<HashMap<u32, Option<String>>>,
+    owners: Mutex<HashMap<String, Option<(u32, u32)>>>,
+}
+
+impl Homes {
+    // Whether `uid` may reach `path` of the mount: its root and everything
+    // in the home named after the user.
+    pub fn allows(&self, uid: u32, path: &Path) -> bool {
+        if uid == 0 {
+            return true;
+        }
+        match home_of(path) {
+            None => true,
+            Some(home) => self.name_of(uid).is_some_and(|name| name == home),
+        }
+    }
+
+    // Owner of `path` of the mount, for entries in a home whose name is a
+    // user's.
+    pub fn owner_of(&self, path: &Path) -> Option<(u32, u32)> {
+        let home = home_of(path)?;
+        let mut owners = self.owners.lock().unwrap();
+        *owners.entry(home.to_string()).or_insert_with(|| {
+            User::from_name(home).ok().flatten().map(|user| (user.uid.as_raw(), user.gid.as_raw()))
+        })
+    }
+
+    fn name_of(&self, uid: u32) -> Option<String> {
+        let mut names = self.names.lock().unwrap();
+        names
+            .entry(uid)
+            .or_insert_with(|| User::from_uid(Uid::from_raw(uid)).ok().flatten().map(|user| user.name))
+            .clone()
+    }
+}
+
+// Name of the home `path` of the mount lies in; None for the root.
+fn home_of(path: &Path) -> Option<&str> {
+    path.components().find_map(|component| match component {
+        Component::Normal(name) => name.to_str(),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{homes_dir, Homes};
+    use std::path::Path;
+
+    #[test]
+    fn users_reach_only_their_own_home() {
+        assert_eq!(homes_dir("/users/%u"), Some("/users"));
+        assert_eq!(homes_dir("/%u"), Some("/"));
+        assert_eq!(homes_dir("/users/%u/files"), None);
+        assert_eq!(homes_dir("users/%u"), None);
+
+        let homes = Homes::default();
+        assert!(homes.allows(65534, Path::new("/")));
+        assert!(homes.allows(65534, Path::new("/nobody/notes.txt")));
+        assert!(!homes.allows(65534, Path::new("/root")));
+        assert!(homes.allows(0, Path::new("/nobody")));
+        assert_eq!(homes.owner_of(Path::new("/nobody/notes.txt")), Some((65534, 65534)));
+        assert_eq!(homes.owner_of(Path::new("/")), None);
+    }
+}
@@ -0,0 +1,156 @@
+// src/inflight.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use tokio::sync::Notify;
+
+thread_local! {
+    // The operation the current worker thread is executing.
+    static CURRENT: RefCell<Option<Arc<InFlightOp>>> = const { RefCell::new(None) };
+}
+
+// FUSE operations currently executing on a mount.
+#[derive(Default)]
+pub struct InFlight {
+    ops: Mutex<BTreeMap<u64, Arc<InFlightOp>>>,
+    next_id: AtomicU64,
+}
+
+pub struct InFlightOp {
+    pub op: &'static str,
+    pub path: PathBuf,
+    // Credentials and process (thread) id of the requester.
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: u32,
+    pub started: Instant,
+    // Executable of the requesting process, resolved when first asked for.
+    exe: OnceLock<Option<String>>,
+    // Set once the watchdog has reported the operation as stuck.
+    reported: AtomicBool,
+    cancelled: AtomicBool,
+    cancel: Notify,
+}
+
+// Keeps an operation listed while it executes and marks it as the current
+// operation of the thread.
+pub struct OpGuard<'a> {
+    inflight: &'a InFlight,
+    id: u64,
+    entry: Arc<InFlightOp>,
+}
+
+impl OpGuard<'_> {
+    pub fn op(&self) -> &InFlightOp {
+        &self.entry
+    }
+}
+
+impl InFlight {
+    // Lists an operation until the returned guard is dropped. Must be called
+    // on the thread that executes the operation.
+    pub fn begin(&self, op: &'static str, path: PathBuf, (uid, gid, pid): (u32, u32, u32)) -> OpGuard<'_> {
+        let entry = Arc::new(InFlightOp {
+            op,
+            path,
+            uid,
+            gid,
+            pid,
+            started: Instant::now(),
+            exe: OnceLock::new(),
+            reported: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            cancel: Notify::new(),
+        });
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.ops.lock().unwrap().insert(id, Arc::clone(&entry));
+        CURRENT.with(|current| *current.borrow_mut() = Some(Arc::clone(&entry)));
+        OpGuard { inflight: self, id, entry }
+    }
+
+    // The executing operations, oldest first.
+    pub fn snapshot(&self) -> Vec<Arc<InFlightOp>> {
+        self.ops.lock().unwrap().values().cloned().collect()
+    }
+}
+
+impl Drop for OpGuard<'_> {
+    fn drop(&mut self) {
+        self.inflight.ops.lock().unwrap().remove(&self.id);
+        CURRENT.with(|current| *current.borrow_mut() = None);
+    }
+}
+
+impl InFlightOp {
+    // Returns true only for the first caller, so a stuck operation is
+    // reported once.
+    pub fn mark_reported(&self) -> bool {
+        !self.reported.swap(true, Ordering::Relaxed)
+    }
+
+    // Aborts the backend call the operation is waiting on, and any retry.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.cancel.notify_one();
+    }
+
+    // Path of the requester's executable, or its command name when the path
+    // cannot be read; None once the process has exited, and for requests the
+    // kernel makes on its own (pid 0). Only resolved for operations that are
+    // logged, so it costs nothing on the common path.
+    pub fn exe(&self) -> Option<&str> {
+        self.exe
+            .get_or_init(|| {
+                if self.pid == 0 {
+                    return None;
+                }
+                match std::fs::read_link(format!("/proc/{}/exe", self.pid)) {
+                    Ok(exe) => Some(exe.to_string_lossy().into_owned()),
+                    Err(_) => std::fs::read_to_string(format!("/proc/{}/comm", self.pid))
+                        .ok()
+                        .map(|comm| comm.trim_end().to_string()),
+                }
+            })
+            .as_deref()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // Completes when the operation is cancelled.
+    pub async fn cancelled(&self) {
+        if !self.is_cancelled() {
+            self.cancel.notified().await;
+        }
+    }
+}
+
+// The operation executing on the calling thread, if any.
+pub fn current() -> Option<Arc<InFlightOp>> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InFlight;
+    use std::path::PathBuf;
+
+    #[test]
+    fn requesters_are_resolved_to_their_executable() {
+        let inflight = InFlight::default();
+        let _guard = inflight.begin("getattr", PathBuf::from("/"), (0, 0, std::process::id()));
+        let op = super::current().unwrap();
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(op.exe(), Some(exe.to_string_lossy().as_ref()));
+
+        let _guard = inflight.begin("forget", PathBuf::from("/"), (0, 0, 0));
+        assert_eq!(super::current().unwrap().exe(), None);
+    }
+}
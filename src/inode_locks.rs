@@ -0,0 +1,79 @@
+// src/inode_locks.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use std::collections::BTreeSet;
+use std::sync::{Condvar, Mutex};
+
+// Serializes mutations of the same inodes across the dispatcher's workers.
+// An operation locks every inode it changes in one call, such as both
+// parents of a rename; all of them are taken together or none is, so two
+// operations locking overlapping sets in any order cannot deadlock.
+#[derive(Default)]
+pub struct InodeLocks {
+    held: Mutex<BTreeSet<u64>>,
+    released: Condvar,
+}
+
+impl InodeLocks {
+    // Blocks until none of `inodes` is held by another operation, then holds
+    // them until the guard is dropped.
+    pub fn lock(&self, inodes: &[u64]) -> InodeGuard<'_> {
+        let inodes: BTreeSet<u64> = inodes.iter().copied().collect();
+        let mut held = self.held.lock().unwrap();
+        while !held.is_disjoint(&inodes) {
+            held = self.released.wait(held).unwrap();
+        }
+        held.extend(&inodes);
+        InodeGuard { locks: self, inodes }
+    }
+}
+
+pub struct InodeGuard<'a> {
+    locks: &'a InodeLocks,
+    inodes: BTreeSet<u64>,
+}
+
+impl Drop for InodeGuard<'_> {
+    fn drop(&mut self) {
+        let mut held = self.locks.held.lock().unwrap();
+        for ino in &self.inodes {
+            held.remove(ino);
+        }
+        self.locks.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InodeLocks;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn disjoint_sets_are_held_together() {
+        let locks = InodeLocks::default();
+        let _rename = locks.lock(&[2, 3]);
+        let _write = locks.lock(&[4]);
+    }
+
+    #[test]
+    fn overlapping_sets_wait_for_each_other() {
+        let locks = Arc::new(InodeLocks::default());
+        let rename = locks.lock(&[3, 2, 3]);
+        let (locked, waiting) = mpsc::channel();
+        let other = {
+            let locks = Arc::clone(&locks);
+            thread::spawn(move || {
+                let _write = locks.lock(&[2]);
+                locked.send(()).unwrap();
+            })
+        };
+        assert!(waiting.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(rename);
+        waiting.recv_timeout(Duration::from_secs(5)).unwrap();
+        other.join().unwrap();
+    }
+}
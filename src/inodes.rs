@@ -0,0 +1,167 @@
+// src/inodes.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+pub const ROOT_INODE: u64 = 1;
+
+// Where an inode sits in the tree: its directory and its name there.
+struct Node {
+    parent: u64,
+    name: String,
+}
+
+// Inodes handed out for pool entries. Every inode records its parent and
+// name rather than its path, so renaming a directory moves one node and the
+// paths of everything below it follow.
+pub struct InodeTable {
+    nodes: HashMap<u64, Node>,
+    children: HashMap<(u64, String), u64>,
+    next_inode: u64,
+}
+
+impl InodeTable {
+    pub fn new() -> Self {
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            ROOT_INODE,
+            Node {
+                parent: ROOT_INODE,
+                name: String::new(),
+            },
+        );
+        Self {
+            nodes,
+            children: HashMap::new(),
+            // Start assigning new inodes from 2 onwards.
+            next_inode: ROOT_INODE + 1,
+        }
+    }
+
+    // Path of `ino` from the root of the pool, or None for an inode that was
+    // never handed out or whose entry was replaced.
+    pub fn path_of(&self, ino: u64) -> Option<PathBuf> {
+        let mut names = Vec::new();
+        let mut current = ino;
+        while current != ROOT_INODE {
+            let node = self.nodes.get(&current)?;
+            names.push(node.name.as_str());
+            current = node.parent;
+        }
+        let mut path = PathBuf::from("/");
+        path.extend(names.iter().rev());
+        Some(path)
+    }
+
+    // Inodes handed out that still resolve, the root included.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn parent_of(&self, ino: u64) -> Option<u64> {
+        self.nodes.get(&ino).map(|node| node.parent)
+    }
+
+    // Inode of `name` in directory `parent`, handing out a new one for an
+    // entry seen for the first time.
+    pub fn child(&mut self, parent: u64, name: &str) -> u64 {
+        let key = (parent, name.to_string());
+        if let Some(&ino) = self.children.get(&key) {
+            return ino;
+        }
+        let ino = self.next_inode;
+        self.next_inode += 1;
+        self.nodes.insert(
+            ino,
+            Node {
+                parent,
+                name: name.to_string(),
+            },
+        );
+        self.children.insert(key, ino);
+        ino
+    }
+
+    // Moves `name` in `parent` to `new_name` in `new_parent`. An entry
+    // already at the destination is replaced, and its inode and those below
+    // it stop resolving. Returns EINVAL for a move into the entry's own
+    // subtree and ENOENT if the source has no inode.
+    // The rename handler calls this once librfs can rename entries.
+    #[allow(dead_code)]
+    pub fn rename(&mut self, parent: u64, name: &str, new_parent: u64, new_name: &str) -> Result<(), i32> {
+        let ino = *self.children.get(&(parent, name.to_string())).ok_or(libc::ENOENT)?;
+        let mut ancestor = new_parent;
+        while ancestor != ROOT_INODE {
+            if ancestor == ino {
+                return Err(libc::EINVAL);
+            }
+            ancestor = self.parent_of(ancestor).ok_or(libc::ENOENT)?;
+        }
+        self.children.remove(&(parent, name.to_string()));
+        if let Some(replaced) = self.children.insert((new_parent, new_name.to_string()), ino)
+            && replaced != ino
+        {
+            self.nodes.remove(&replaced);
+        }
+        self.nodes.insert(
+            ino,
+            Node {
+                parent: new_parent,
+                name: new_name.to_string(),
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Default for InodeTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InodeTable, ROOT_INODE};
+    use std::path::PathBuf;
+
+    #[test]
+    fn renaming_a_directory_moves_its_descendants() {
+        let mut table = InodeTable::new();
+        let docs = table.child(ROOT_INODE, "docs");
+        let drafts = table.child(docs, "drafts");
+        let note = table.child(drafts, "note.txt");
+        let archive = table.child(ROOT_INODE, "archive");
+
+        table.rename(ROOT_INODE, "docs", archive, "old-docs").unwrap();
+        assert_eq!(table.path_of(note), Some(PathBuf::from("/archive/old-docs/drafts/note.txt")));
+        assert_eq!(table.child(archive, "old-docs"), docs);
+        assert_ne!(table.child(ROOT_INODE, "docs"), docs);
+    }
+
+    #[test]
+    fn replaced_entries_stop_resolving() {
+        let mut table = InodeTable::new();
+        let a = table.child(ROOT_INODE, "a");
+        let b = table.child(ROOT_INODE, "b");
+        let below_b = table.child(b, "child");
+
+        table.rename(ROOT_INODE, "a", ROOT_INODE, "b").unwrap();
+        assert_eq!(table.path_of(a), Some(PathBuf::from("/b")));
+        assert_eq!(table.path_of(b), None);
+        assert_eq!(table.path_of(below_b), None);
+    }
+
+    #[test]
+    fn moving_a_directory_into_itself_is_refused() {
+        let mut table = InodeTable::new();
+        let docs = table.child(ROOT_INODE, "docs");
+        let drafts = table.child(docs, "drafts");
+
+        assert_eq!(table.rename(ROOT_INODE, "docs", drafts, "loop"), Err(libc::EINVAL));
+        assert_eq!(table.rename(ROOT_INODE, "docs", docs, "loop"), Err(libc::EINVAL));
+        assert_eq!(table.path_of(drafts), Some(PathBuf::from("/docs/drafts")));
+    }
+}
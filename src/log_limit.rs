@@ -0,0 +1,133 @@
+// src/log_limit.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::clock::{Clock, SystemClock};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Limits how many records of one kind reach the log. Up to `burst` records
+// with the same key pass per `window`; further ones in that window are only
+// counted. The count is handed back with the next record of the key after
+// the window, or by `take_expired` if none comes, which the logger calls
+// every second, so it can be logged as a summary.
+pub struct RepeatLimiter<K> {
+    burst: u32,
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    windows: Mutex<HashMap<K, Window>>,
+}
+
+struct Window {
+    started: Instant,
+    passed: u32,
+    suppressed: u64,
+}
+
+// Whether a record may be written, and how many records of its key were
+// held back in the window before it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Admission {
+    pub write: bool,
+    pub suppressed: u64,
+}
+
+impl<K: Hash + Eq + Clone> RepeatLimiter<K> {
+    pub fn new(burst: u32, window: Duration) -> Self {
+        Self::with_clock(burst, window, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(burst: u32, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            burst: burst.max(1),
+            window,
+            clock,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn admit(&self, key: &K) -> Admission {
+        let now = self.clock.now();
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(key.clone()).or_insert(Window {
+            started: now,
+            passed: 0,
+            suppressed: 0,
+        });
+        let mut suppressed = 0;
+        if now.duration_since(window.started) >= self.window {
+            suppressed = window.suppressed;
+            *window = Window {
+                started: now,
+                passed: 0,
+                suppressed: 0,
+            };
+        }
+        if window.passed < self.burst {
+            window.passed += 1;
+            Admission { write: true, suppressed }
+        } else {
+            window.suppressed += 1;
+            Admission { write: false, suppressed }
+        }
+    }
+
+    // Keys whose window has passed with records held back, and how many;
+    // their windows are forgotten.
+    pub fn take_expired(&self) -> Vec<(K, u64)> {
+        let now = self.clock.now();
+        let mut expired = Vec::new();
+        self.windows.lock().unwrap().retain(|key, window| {
+            if now.duration_since(window.started) < self.window {
+                return true;
+            }
+            if window.suppressed > 0 {
+                expired.push((key.clone(), window.suppressed));
+            }
+            false
+        });
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Admission, RepeatLimiter};
+    use crate::clock::SimClock;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn repeats_beyond_the_burst_are_counted_until_the_window_passes() {
+        let clock = Arc::new(SimClock::new());
+        let limiter = RepeatLimiter::with_clock(2, Duration::from_secs(10), clock.clone());
+        let written = (0..5).filter(|_| limiter.admit(&"EIO").write).count();
+        assert_eq!(written, 2);
+        assert!(limiter.admit(&"ETIMEDOUT").write);
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(limiter.admit(&"EIO"), Admission { write: true, suppressed: 3 });
+        assert_eq!(limiter.admit(&"EIO"), Admission { write: true, suppressed: 0 });
+    }
+
+    #[test]
+    fn held_back_counts_are_reported_when_repeats_stop() {
+        let clock = Arc::new(SimClock::new());
+        let limiter = RepeatLimiter::with_clock(1, Duration::from_secs(10), clock.clone());
+        for _ in 0..4 {
+            limiter.admit(&"EIO");
+        }
+        limiter.admit(&"ENOSPC");
+        assert!(limiter.take_expired().is_empty());
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(limiter.take_expired(), [("EIO", 3)]);
+        assert!(limiter.take_expired().is_empty());
+    }
+}
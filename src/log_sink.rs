@@ -0,0 +1,173 @@
+// src/log_sink.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::config::LogFileSettings;
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const IDENTIFIER: &str = "rfs-fuse";
+
+// A log file that is rotated once it exceeds a size or age limit. Rotated
+// files are renamed to `<path>.1`, `<path>.2`, ... and the oldest is dropped.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_age: Duration,
+    keep: u32,
+    state: Mutex<FileState>,
+}
+
+struct FileState {
+    file: File,
+    size: u64,
+    opened_at: SystemTime,
+}
+
+impl RotatingFile {
+    pub fn open(settings: &LogFileSettings) -> io::Result<Self> {
+        let path = PathBuf::from(&settings.path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let state = open_append(&path)?;
+        Ok(Self {
+            path,
+            max_size: settings.max_size_mb * 1024 * 1024,
+            max_age: Duration::from_secs(settings.max_age_hours * 3600),
+            keep: settings.keep,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn write_line(&self, line: &str) {
+        let mut state = self.state.lock().unwrap();
+        let expired = !self.max_age.is_zero()
+            && state.opened_at.elapsed().map(|age| age >= self.max_age).unwrap_or(false);
+        let full = self.max_size > 0 && state.size + line.len() as u64 + 1 > self.max_size;
+        if (expired || full) && state.size > 0 {
+            match self.rotate() {
+                Ok(fresh) => *state = fresh,
+                Err(e) => eprintln!("Log rotation of {} failed: {}", self.path.display(), e),
+            }
+        }
+        if writeln!(state.file, "{}", line).is_ok() {
+            state.size += line.len() as u64 + 1;
+        }
+    }
+
+    fn rotate(&self) -> io::Result<FileState> {
+        let rotated = |n: u32| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = fs::rename(rotated(n), rotated(n + 1));
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        open_append(&self.path)
+    }
+}
+
+fn open_append(path: &PathBuf) -> io::Result<FileState> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok(FileState {
+        file,
+        size,
+        opened_at: SystemTime::now(),
+    })
+}
+
+// Sends lines to the local syslog daemon.
+pub struct Syslog;
+
+impl Syslog {
+    pub fn open() -> Self {
+        // openlog keeps the pointer, so the identifier must live forever.
+        static IDENT: &[u8] = b"rfs-fuse\0";
+        unsafe { libc::openlog(IDENT.as_ptr().cast(), libc::LOG_PID, libc::LOG_DAEMON) };
+        Syslog
+    }
+
+    pub fn write(&self, priority: libc::c_int, line: &str) {
+        let Ok(message) = CString::new(line.replace('\0', " ")) else {
+            return;
+        };
+        unsafe { libc::syslog(priority, c"%s".as_ptr(), message.as_ptr()) };
+    }
+}
+
+// Sends records to journald using its native protocol, so every structured
+// field becomes a journal field that can be filtered with journalctl.
+pub struct Journald {
+    socket: UnixDatagram,
+}
+
+impl Journald {
+    pub fn open() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(JOURNALD_SOCKET)?;
+        Ok(Self { socket })
+    }
+
+    pub fn write(&self, priority: libc::c_int, fields: &[(&str, String)]) {
+        let mut payload = Vec::new();
+        append_field(&mut payload, "PRIORITY", &priority.to_string());
+        append_field(&mut payload, "SYSLOG_IDENTIFIER", IDENTIFIER);
+        for (key, value) in fields {
+            append_field(&mut payload, key, value);
+        }
+        let _ = self.socket.send(&payload);
+    }
+}
+
+// Values containing newlines use the length-prefixed binary encoding.
+fn append_field(payload: &mut Vec<u8>, key: &str, value: &str) {
+    payload.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        payload.push(b'\n');
+        payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        payload.push(b'=');
+    }
+    payload.extend_from_slice(value.as_bytes());
+    payload.push(b'\n');
+}
+
+// Formats a time as an RFC 3339 UTC timestamp with millisecond precision.
+pub fn format_utc(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
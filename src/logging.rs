@@ -0,0 +1,399 @@
+// src/logging.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::config::{LogFormat, LogSettings, LogTarget};
+use crate::error::FuseError;
+use crate::inflight::InFlightOp;
+use crate::log_limit::RepeatLimiter;
+use crate::log_sink::{format_utc, Journald, RotatingFile, Syslog};
+use rfs_utils::{set_log_level, LogLevel};
+use serde_json::{Map, Value};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Parts of rfs-fuse whose verbosity can be tuned independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Subsystem {
+    Daemon,
+    Mount,
+    Fuse,
+    Control,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] = [Subsystem::Daemon, Subsystem::Mount, Subsystem::Fuse, Subsystem::Control];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Daemon => "daemon",
+            Subsystem::Mount => "mount",
+            Subsystem::Fuse => "fuse",
+            Subsystem::Control => "control",
+        }
+    }
+}
+
+struct Logger {
+    format: LogFormat,
+    sink: Sink,
+    // Per-subsystem threshold, indexed like `Subsystem::ALL`.
+    levels: [Option<u8>; 4],
+    // Limits warnings and errors repeating the same error on the same mount.
+    repeats: Option<RepeatLimiter<RepeatKey>>,
+}
+
+type RepeatKey = (Subsystem, Option<String>, String);
+
+// Where records end up once `configure` has run.
+enum Sink {
+    Stderr,
+    File(RotatingFile),
+    Syslog(Syslog),
+    Journald(Journald),
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+// How often summaries of suppressed records are written once due.
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(1);
+static DEFAULT_LEVEL: AtomicU8 = AtomicU8::new(1);
+
+// Sets the level used by every subsystem without an override.
+pub fn set_default_level(level: LogLevel) {
+    DEFAULT_LEVEL.store(rank(&level), Ordering::Relaxed);
+    set_log_level(level);
+}
+
+// Applies the [log] settings. Must be called once, before mounts are started.
+pub fn configure(settings: &LogSettings) -> Result<(), FuseError> {
+    let mut levels = [None; 4];
+    for (name, value) in &settings.levels {
+        let index = Subsystem::ALL
+            .iter()
+            .position(|s| s.name() == name)
+            .ok_or_else(|| FuseError::Config(format!("Unknown log subsystem '{}'", name)))?;
+        let level = parse_level(value)
+            .ok_or_else(|| FuseError::Config(format!("Invalid log level '{}' for '{}'", value, name)))?;
+        levels[index] = Some(rank(&level));
+    }
+
+    // rfs-utils filters by a single global level, so it has to let through
+    // everything the most verbose subsystem asks for; the rest is filtered here.
+    let default = DEFAULT_LEVEL.load(Ordering::Relaxed);
+    let most_verbose = levels.iter().flatten().copied().fold(default, u8::min);
+    set_log_level(level_from_rank(most_verbose));
+
+    let sink = match settings.target {
+        LogTarget::Stderr => Sink::Stderr,
+        LogTarget::File => Sink::File(RotatingFile::open(&settings.file).map_err(|e| {
+            FuseError::Config(format!("Cannot open log file {}: {}", settings.file.path, e))
+        })?),
+        LogTarget::Syslog => Sink::Syslog(Syslog::open()),
+        LogTarget::Journald => Sink::Journald(
+            Journald::open().map_err(|e| FuseError::Config(format!("Cannot connect to journald: {}", e)))?,
+        ),
+    };
+
+    let repeats = (settings.repeat_burst > 0 && settings.repeat_window_secs > 0)
+        .then(|| RepeatLimiter::new(settings.repeat_burst, Duration::from_secs(settings.repeat_window_secs)));
+    let limited = repeats.is_some();
+    let _ = LOGGER.set(Logger {
+        format: settings.format,
+        sink,
+        levels,
+        repeats,
+    });
+    if limited {
+        spawn_summaries();
+    }
+    Ok(())
+}
+
+// Writes the summaries of suppressed records once their window has passed,
+// so an incident that stopped is summarized without waiting for the next
+// warning to come along.
+fn spawn_summaries() {
+    let spawned = std::thread::Builder::new().name("rfs-log-summary".to_string()).spawn(|| {
+        loop {
+            std::thread::sleep(SUMMARY_INTERVAL);
+            write_expired_summaries(LOGGER.get());
+        }
+    });
+    if let Err(e) = spawned {
+        let message = format!("Suppressed log records will only be summarized by later ones: {}", e);
+        log(Subsystem::Daemon, LogLevel::Warn, &message);
+    }
+}
+
+fn write_expired_summaries(logger: Option<&Logger>) {
+    let Some(repeats) = logger.and_then(|l| l.repeats.as_ref()) else {
+        return;
+    };
+    for (key, suppressed) in repeats.take_expired() {
+        write_summary(logger, &key, suppressed, repeats.window());
+    }
+}
+
+pub fn parse_level(value: &str) -> Option<LogLevel> {
+    match value.to_ascii_lowercase().as_str() {
+        "debug" => Some(LogLevel::Debug),
+        "info" => Some(LogLevel::Info),
+        "warn" | "warning" => Some(LogLevel::Warn),
+        "error" => Some(LogLevel::Error),
+        _ => None,
+    }
+}
+
+fn rank(level: &LogLevel) -> u8 {
+    match level {
+        LogLevel::Debug => 0,
+        LogLevel::Info => 1,
+        LogLevel::Warn => 2,
+        LogLevel::Error => 3,
+    }
+}
+
+fn level_from_rank(rank: u8) -> LogLevel {
+    match rank {
+        0 => LogLevel::Debug,
+        1 => LogLevel::Info,
+        2 => LogLevel::Warn,
+        _ => LogLevel::Error,
+    }
+}
+
+fn level_name(rank: u8) -> &'static str {
+    match rank {
+        0 => "debug",
+        1 => "info",
+        2 => "warn",
+        _ => "error",
+    }
+}
+
+fn syslog_priority(rank: u8) -> libc::c_int {
+    match rank {
+        0 => libc::LOG_DEBUG,
+        1 => libc::LOG_INFO,
+        2 => libc::LOG_WARNING,
+        _ => libc::LOG_ERR,
+    }
+}
+
+// Logs a plain message for a subsystem.
+pub fn log(subsystem: Subsystem, level: LogLevel, message: &str) {
+    Record::new(subsystem, level, message).emit();
+}
+
+// A log line with optional structured fields. In JSON mode every field
+// becomes its own key; in text mode they are appended as key=value pairs.
+pub struct Record<'a> {
+    subsystem: Subsystem,
+    level: u8,
+    message: &'a str,
+    op: Option<&'a str>,
+    mount: Option<&'a str>,
+    path: Option<String>,
+    duration: Option<Duration>,
+    error: Option<String>,
+    requester: Option<&'a InFlightOp>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(subsystem: Subsystem, level: LogLevel, message: &'a str) -> Self {
+        Self {
+            subsystem,
+            level: rank(&level),
+            message,
+            op: None,
+            mount: None,
+            path: None,
+            duration: None,
+            error: None,
+            requester: None,
+        }
+    }
+
+    pub fn op(mut self, op: &'a str) -> Self {
+        self.op = Some(op);
+        self
+    }
+
+    pub fn mount(mut self, mount: &'a str) -> Self {
+        self.mount = Some(mount);
+        self
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn error(mut self, error: impl Into<String>) -> Self {
+        self.error = Some(error.into());
+        self
+    }
+
+    // The uid, gid, pid and executable of the process whose request this
+    // record is about.
+    pub fn requester(mut self, op: &'a InFlightOp) -> Self {
+        self.requester = Some(op);
+        self
+    }
+
+    pub fn emit(self) {
+        let logger = LOGGER.get();
+        let index = Subsystem::ALL.iter().position(|s| *s == self.subsystem).unwrap_or(0);
+        let threshold = logger
+            .and_then(|l| l.levels[index])
+            .unwrap_or_else(|| DEFAULT_LEVEL.load(Ordering::Relaxed));
+        if self.level < threshold {
+            return;
+        }
+        if let Some(repeats) = logger.and_then(|l| l.repeats.as_ref())
+            && self.level >= rank(&LogLevel::Warn)
+            && let Some(error) = &self.error
+        {
+            write_expired_summaries(logger);
+            let key = (self.subsystem, self.mount.map(str::to_string), error.clone());
+            let admission = repeats.admit(&key);
+            if admission.suppressed > 0 {
+                write_summary(logger, &key, admission.suppressed, repeats.window());
+            }
+            if !admission.write {
+                return;
+            }
+        }
+        self.write(logger);
+    }
+
+    fn write(&self, logger: Option<&Logger>) {
+        let format = logger.map(|l| l.format).unwrap_or_default();
+        match (logger.map(|l| &l.sink).unwrap_or(&Sink::Stderr), format) {
+            (Sink::Stderr, LogFormat::Text) => rfs_utils::log(level_from_rank(self.level), &self.to_text()),
+            (Sink::Stderr, LogFormat::Json) => eprintln!("{}", self.to_json()),
+            (Sink::File(file), LogFormat::Text) => file.write_line(&format!(
+                "{} {:<5} {}",
+                format_utc(SystemTime::now()),
+                level_name(self.level).to_ascii_uppercase(),
+                self.to_text()
+            )),
+            (Sink::File(file), LogFormat::Json) => file.write_line(&self.to_json()),
+            (Sink::Syslog(syslog), LogFormat::Text) => syslog.write(syslog_priority(self.level), &self.to_text()),
+            (Sink::Syslog(syslog), LogFormat::Json) => syslog.write(syslog_priority(self.level), &self.to_json()),
+            (Sink::Journald(journald), _) => journald.write(syslog_priority(self.level), &self.journal_fields()),
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let mut line = format!("[{}] {}", self.subsystem.name(), self.message);
+        if let Some(op) = self.op {
+            line.push_str(&format!(" op={}", op));
+        }
+        if let Some(mount) = self.mount {
+            line.push_str(&format!(" mount={}", mount));
+        }
+        if let Some(path) = &self.path {
+            line.push_str(&format!(" path={}", path));
+        }
+        if let Some(duration) = self.duration {
+            line.push_str(&format!(" duration={:?}", duration));
+        }
+        if let Some(error) = &self.error {
+            line.push_str(&format!(" error={}", error));
+        }
+        if let Some(op) = self.requester {
+            line.push_str(&format!(" uid={} gid={} pid={}", op.uid, op.gid, op.pid));
+            if let Some(exe) = op.exe() {
+                line.push_str(&format!(" exe={}", exe));
+            }
+        }
+        line
+    }
+
+    fn to_json(&self) -> String {
+        let mut object = Map::new();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        object.insert("ts".into(), Value::from(timestamp));
+        object.insert("level".into(), Value::from(level_name(self.level)));
+        object.insert("subsystem".into(), Value::from(self.subsystem.name()));
+        object.insert("msg".into(), Value::from(self.message));
+        if let Some(op) = self.op {
+            object.insert("op".into(), Value::from(op));
+        }
+        if let Some(mount) = self.mount {
+            object.insert("mount".into(), Value::from(mount));
+        }
+        if let Some(path) = &self.path {
+            object.insert("path".into(), Value::from(path.as_str()));
+        }
+        if let Some(duration) = self.duration {
+            object.insert("duration_us".into(), Value::from(duration.as_micros() as u64));
+        }
+        if let Some(error) = &self.error {
+            object.insert("error".into(), Value::from(error.as_str()));
+        }
+        if let Some(op) = self.requester {
+            object.insert("uid".into(), Value::from(op.uid));
+            object.insert("gid".into(), Value::from(op.gid));
+            object.insert("pid".into(), Value::from(op.pid));
+            if let Some(exe) = op.exe() {
+                object.insert("exe".into(), Value::from(exe));
+            }
+        }
+        Value::Object(object).to_string()
+    }
+
+    // Journal fields; the structured ones can be matched with e.g.
+    // `journalctl RFS_MOUNT=/mnt/pool`.
+    fn journal_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("MESSAGE", self.to_text()),
+            ("RFS_SUBSYSTEM", self.subsystem.name().to_string()),
+        ];
+        if let Some(op) = self.op {
+            fields.push(("RFS_OP", op.to_string()));
+        }
+        if let Some(mount) = self.mount {
+            fields.push(("RFS_MOUNT", mount.to_string()));
+        }
+        if let Some(path) = &self.path {
+            fields.push(("RFS_PATH", path.clone()));
+        }
+        if let Some(duration) = self.duration {
+            fields.push(("RFS_DURATION_US", duration.as_micros().to_string()));
+        }
+        if let Some(error) = &self.error {
+            fields.push(("RFS_ERROR", error.clone()));
+        }
+        if let Some(op) = self.requester {
+            fields.push(("RFS_UID", op.uid.to_string()));
+            fields.push(("RFS_GID", op.gid.to_string()));
+            fields.push(("RFS_PID", op.pid.to_string()));
+            if let Some(exe) = op.exe() {
+                fields.push(("RFS_EXE", exe.to_string()));
+            }
+        }
+        fields
+    }
+}
+
+// Writes how many records repeating `key`'s error were held back.
+fn write_summary(logger: Option<&Logger>, key: &RepeatKey, suppressed: u64, window: Duration) {
+    let (subsystem, mount, error) = key;
+    let message = format!("{} similar records suppressed within {:?}", suppressed, window);
+    let mut record = Record::new(*subsystem, LogLevel::Warn, &message).error(error.as_str());
+    if let Some(mount) = mount {
+        record = record.mount(mount);
+    }
+    record.write(logger);
+}
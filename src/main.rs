@@ -2,111 +2,216 @@
 // SPDX-License-Identifier: AGPL-3.0
 // Copyright (c) 2025 Canmi
 
+mod backend;
+mod bench;
+mod breaker;
+mod capabilities;
+mod clock;
+mod config;
+mod control;
+mod control_dir;
+mod dir_snapshot;
+mod dispatch;
 mod error;
 mod fs;
-
+mod fuse_mount;
+mod health;
+mod hidden;
+mod home;
+mod inflight;
+mod inode_locks;
+mod inodes;
+mod log_limit;
+mod log_sink;
+mod logging;
+mod mount;
+mod panic_guard;
+mod pool_lock;
+mod reload;
+mod scrub;
+mod self_test;
+mod stats;
+mod store;
+#[cfg(test)]
+mod testing;
+mod throttle;
+mod unmount;
+mod user_dirs;
+mod validate;
+mod verify;
+mod watchdog;
+
+use config::{env_log_level, load_settings, Paths, Settings};
 use error::FuseError;
-use fs::RfsFuse;
-use fuser::{spawn_mount2, MountOption};
+use logging::{log, Subsystem};
+use mount::{add_views, load_mount_entries, MountManager, MountOutcome};
 use rfs_ess::load_config;
-use rfs_pool::load_and_mount_pools;
-use rfs_utils::{log, set_log_level, LogLevel};
-use std::collections::HashMap;
-use std::ffi::OsStr;
+use rfs_utils::LogLevel;
+use stats::{MountStats, Registry};
+use std::env;
+use std::path::Path;
 use std::process;
 use std::sync::Arc;
-use tokio::task::JoinHandle;
 
-const CONFIG_PATH: &str = "/opt/rfs/rfsd/config.toml";
-const POOL_CONFIG_PATH: &str = "/opt/rfs/rfsd/pool.toml";
+const USAGE: &str = "usage: rfs [--force | verify <pool-id> | bench <pool-id> | self-test]
+
+Without arguments, mounts every FUSE mount in pool.toml and serves them until
+interrupted; a pool another rfs is serving read-write is refused unless
+--force is given. `verify` checks the namespace of one pool without mounting it;
+`bench` mounts one pool at a temporary directory and measures access patterns
+through FUSE; `self-test` (or --self-test) mounts every configured pool at a
+temporary directory, probes it and exits nonzero if one fails.";
 
 #[tokio::main]
 async fn main() {
+    let paths = Paths::from_env();
+
     // Load main config for logging.
-    let config = match load_config(CONFIG_PATH) {
+    let config = match load_config(&paths.config) {
         Ok(cfg) => cfg,
         Err(e) => {
             eprintln!("Failed to load configuration: {}. Exiting.", e);
             process::exit(1);
         }
     };
-    set_log_level(config.common.log_level);
-    log(LogLevel::Info, "Logger initialized for rfs-fuse.");
+    logging::set_default_level(env_log_level().unwrap_or(config.common.log_level));
+    panic_guard::install_hook();
+    log(Subsystem::Daemon, LogLevel::Info, "Logger initialized for rfs-fuse.");
+    if user_dirs::unprivileged() {
+        log(
+            Subsystem::Daemon,
+            LogLevel::Info,
+            &format!("Running without root: pool configuration from {}", paths.pool_config),
+        );
+    }
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => {}
+        [flag] if flag == "--force" => {}
+        [command, pool_id] if command == "verify" => process::exit(verify::run(&paths, pool_id).await),
+        [command, pool_id] if command == "bench" => process::exit(bench::run(&paths, pool_id).await),
+        [command] if command == "self-test" || command == "--self-test" => {
+            process::exit(self_test::run(&paths).await)
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
 
     // Run the application and handle errors.
-    if let Err(e) = run().await {
-        log(LogLevel::Error, &format!("Filesystem failed: {}", e));
+    if let Err(e) = run(&paths, args.as_slice() == ["--force"]).await {
+        log(Subsystem::Daemon, LogLevel::Error, &format!("Filesystem failed: {}", e));
         process::exit(1);
     }
 }
 
-async fn run() -> Result<(), FuseError> {
+async fn run(paths: &Paths, force: bool) -> Result<(), FuseError> {
+    let settings = load_settings(&paths.settings)?;
+    logging::configure(&settings.log)?;
+
     // Load pools and mount configurations.
-    let (pools, mounts) = load_and_mount_pools(POOL_CONFIG_PATH).await?;
-    if mounts.is_empty() {
-        log(LogLevel::Warn, "No FUSE mounts defined in pool.toml. Exiting.");
+    // Mounts may also arrive through the control socket, which then needs
+    // no pool.toml at all.
+    let control_mounts = settings.control.enabled && settings.control.mounts;
+    let mut entries = match load_mount_entries(&paths.pool_config).await {
+        Ok(entries) => entries,
+        Err(_) if control_mounts && !Path::new(&paths.pool_config).exists() => {
+            log(
+                Subsystem::Daemon,
+                LogLevel::Info,
+                &format!("{} does not exist; mounting only through the control socket", paths.pool_config),
+            );
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+    validate::report(validate::mount_problems(&paths.pool_config, &entries), FuseError::MountConfig)?;
+    add_views(&mut entries, &settings.views);
+    if entries.is_empty() && !control_mounts {
+        log(Subsystem::Daemon, LogLevel::Warn, "No FUSE mounts defined in pool.toml. Exiting.");
         return Ok(());
     }
 
-    // Create a quick lookup map from pool_id to pool_path.
-    let pool_map: HashMap<u64, String> =
-        pools.into_iter().map(|p| (p.pool_id, p.path)).collect();
-
-    let mut join_handles = Vec::new();
-    let mut session_guards = Vec::new();
-
-    for mount_config in mounts {
-        let pool_path = match pool_map.get(&mount_config.pool_id) {
-            Some(path) => path.clone(),
-            None => {
-                return Err(FuseError::MountConfig(format!(
-                    "Mount point '{}' references non-existent pool_id '{}'",
-                    mount_config.mount_point, mount_config.pool_id
-                )));
-            }
-        };
-
-        let mount_point = Arc::new(mount_config.mount_point);
-        let pool_root = Arc::new(pool_path);
-
-        log(LogLevel::Info, &format!("Preparing to mount pool '{}' at '{}'", pool_root, mount_point));
-
-        // Each FUSE instance needs to be spawned on a blocking-safe thread.
-        let mount_point_clone = Arc::clone(&mount_point);
-        let handle = tokio::task::spawn_blocking(move || {
-            let fuse_fs = RfsFuse::new(pool_root.to_string());
-            let options = vec![
-                MountOption::FSName("rfs".to_string()),
-                MountOption::AutoUnmount,
-                MountOption::AllowRoot, // Often needed for system-wide mounts
-            ];
-            // This returns the session guard which must be kept alive.
-            spawn_mount2(fuse_fs, mount_point_clone.as_str(), &options)
+    let registry = Arc::new(Registry::default());
+    let manager = Arc::new(MountManager::new(
+        Arc::clone(&registry),
+        paths.pool_config.clone(),
+        settings.startup.clone(),
+        settings.backend.clone(),
+        settings.fuse.clone(),
+        settings.mounts.clone(),
+        settings.views.clone(),
+    )
+    .forcing_pool_locks(force));
+    let _control = if settings.control.enabled {
+        let mounts = control_mounts.then(|| control::MountControl {
+            manager: Arc::clone(&manager),
+            shutdown: settings.shutdown.clone(),
         });
-        join_handles.push((mount_point, handle));
-    }
-
-    // Wait for the spawn_blocking tasks to finish setting up the mounts.
-    for (mount_point, handle) in join_handles {
-        match handle.await {
-            Ok(Ok(session)) => {
-                log(LogLevel::Info, &format!("Successfully mounted on {}", mount_point));
-                session_guards.push(session);
+        match control::spawn(&settings.control, Arc::clone(&registry), paths, mounts) {
+            Ok(server) => Some(server),
+            // Without the socket nothing could be mounted.
+            Err(e) if control_mounts => return Err(FuseError::Io(e)),
+            Err(e) => {
+                log(Subsystem::Daemon, LogLevel::Warn, &format!("Control socket unavailable: {}", e));
+                None
             }
-            Ok(Err(e)) => return Err(FuseError::Io(e)),
-            Err(e) => return Err(FuseError::Io(std::io::Error::new(std::io::ErrorKind::Other, e))),
+        }
+    } else {
+        None
+    };
+
+    for (mount_stats, outcome) in manager.mount_entries(entries).await {
+        if let MountOutcome::Failed(err) = outcome
+            && let Err(e) = mount_failed(&settings, &mount_stats, err)
+        {
+            // Mounts that already succeeded must not be left behind.
+            manager.shutdown(&settings.shutdown).await;
+            return Err(e);
         }
     }
 
-    log(LogLevel::Info, "All filesystems mounted. Press Ctrl+C to unmount all.");
+    if manager.mounted_count() == 0 && manager.pending_count() == 0 && !control_mounts {
+        return Err(FuseError::MountConfig("No filesystem could be mounted".to_string()));
+    }
+
+    if settings.supervisor.enabled {
+        tokio::spawn(Arc::clone(&manager).supervise(settings.supervisor.clone()));
+    }
+    if settings.scrub.enabled {
+        scrub::spawn(Arc::clone(&manager), settings.scrub.clone());
+    }
+    if settings.watchdog.enabled {
+        watchdog::spawn(Arc::clone(&registry), settings.watchdog.clone());
+    }
+    reload::spawn(Arc::clone(&manager), &paths.pool_config, &settings.reload)?;
+
+    log(Subsystem::Daemon, LogLevel::Info, "All filesystems mounted. Press Ctrl+C to unmount all.");
 
     // Wait for shutdown signal.
     tokio::signal::ctrl_c().await?;
     println!(); // Newline after ^C
-    log(LogLevel::Info, "Received Ctrl+C signal.");
-    log(LogLevel::Info, "Initiating graceful shutdown of all mounts.");
+    log(Subsystem::Daemon, LogLevel::Info, "Received Ctrl+C signal.");
+    log(Subsystem::Daemon, LogLevel::Info, "Initiating graceful shutdown of all mounts.");
+
+    // Busy mounts are retried until the timeout and then detached lazily,
+    // so shutdown never hangs on a process that keeps files open.
+    manager.shutdown(&settings.shutdown).await;
+    Ok(())
+}
 
-    // When this function returns, all `session_guards` will be dropped,
-    // which unmounts each filesystem gracefully.
+// Records a failed mount and decides whether startup may continue without it.
+fn mount_failed(settings: &Settings, mount_stats: &MountStats, err: FuseError) -> Result<(), FuseError> {
+    mount_stats.set_failed(&err.to_string());
+    if !settings.startup.continue_on_failure {
+        return Err(err);
+    }
+    log(
+        Subsystem::Daemon,
+        LogLevel::Error,
+        &format!("Skipping mount {}: {}", mount_stats.mount_point, err),
+    );
     Ok(())
 }
@@ -0,0 +1,909 @@
+// src/mount.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::backend::Backend;
+use crate::config::{
+    BackendSettings, FuseSettings, MountMethod, MountOptions, ShutdownSettings, StartupSettings,
+    SupervisorSettings, ViewSettings,
+};
+use crate::error::FuseError;
+use crate::fs::{PoolView, RfsFuse};
+use crate::fuse_mount::{self, Access};
+use crate::health;
+use crate::home::homes_dir;
+use crate::logging::{log, Subsystem};
+use crate::pool_lock::{self, PoolLock};
+use crate::stats::{MountStats, Registry};
+use crate::unmount::{detach, unmount_all};
+use fuser::BackgroundSession;
+use rfs_pool::load_and_mount_pools;
+use rfs_utils::LogLevel;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+// A mount entry from pool.toml, or a view of one, together with the root of
+// the pool it references.
+pub struct MountEntry {
+    pub mount_point: String,
+    pub pool_id: u64,
+    pub pool_root: Option<String>,
+    // Directory of the pool shown; "/" for the whole pool.
+    pub subpath: String,
+    pub read_only: bool,
+}
+
+impl MountEntry {
+    // Resolves the entry into a mountable spec. Pools whose path is not
+    // present yet are reported as unavailable, to be retried; a pool id
+    // pool.toml does not define is a configuration error.
+    pub fn spec(&self) -> Result<MountSpec, FuseError> {
+        match &self.pool_root {
+            Some(pool_root) if Path::new(pool_root).is_dir() => Ok(MountSpec {
+                mount_point: self.mount_point.clone(),
+                pool_id: self.pool_id,
+                pool_root: pool_root.clone(),
+                subpath: self.subpath.clone(),
+                read_only: self.read_only,
+                managed: false,
+            }),
+            Some(pool_root) => Err(FuseError::PoolUnavailable(format!(
+                "Pool '{}' for mount point '{}' is not present at '{}'",
+                self.pool_id, self.mount_point, pool_root
+            ))),
+            None => Err(FuseError::MountConfig(format!(
+                "Mount point '{}' references non-existent pool_id '{}'",
+                self.mount_point, self.pool_id
+            ))),
+        }
+    }
+}
+
+// Result of trying to establish a mount.
+pub enum MountOutcome {
+    Mounted,
+    // The pool is not available yet; the mount is retried in the background.
+    Deferred,
+    Failed(FuseError),
+}
+
+// Loads pools and mount configurations and resolves each mount against its pool.
+pub async fn load_mount_entries(pool_config_path: &str) -> Result<Vec<MountEntry>, FuseError> {
+    let (pools, mounts) = load_and_mount_pools(pool_config_path).await?;
+
+    // Create a quick lookup map from pool_id to pool_path.
+    let pool_map: HashMap<u64, String> =
+        pools.into_iter().map(|p| (p.pool_id, p.path)).collect();
+
+    Ok(mounts
+        .into_iter()
+        .map(|m| MountEntry {
+            pool_root: pool_map.get(&m.pool_id).cloned(),
+            mount_point: m.mount_point,
+            pool_id: m.pool_id,
+            subpath: "/".to_string(),
+            read_only: false,
+        })
+        .collect())
+}
+
+// Appends an entry for every view, showing the pool of the mount it is a
+// view of read-only. Views of mount points not among `entries` are left out.
+pub fn add_views(entries: &mut Vec<MountEntry>, views: &BTreeMap<String, ViewSettings>) {
+    for (mount_point, view) in views {
+        let Some(shown) = entries.iter().find(|entry| Path::new(&entry.mount_point) == Path::new(&view.of)) else {
+            log(
+                Subsystem::Mount,
+                LogLevel::Warn,
+                &format!("{} is a view of {}, which is not a mount in pool.toml; skipping it", mount_point, view.of),
+            );
+            continue;
+        };
+        let subpath = format!("{}{}", shown.subpath.trim_end_matches('/'), view.subpath.trim_end_matches('/'));
+        entries.push(MountEntry {
+            mount_point: mount_point.clone(),
+            pool_id: shown.pool_id,
+            pool_root: shown.pool_root.clone(),
+            subpath: if subpath.is_empty() { "/".to_string() } else { subpath },
+            read_only: true,
+        });
+    }
+}
+
+// Resolves a pool id given on the command line to the pool's id and root
+// directory, for commands working on a single pool.
+pub async fn find_pool(pool_config_path: &str, pool_id: &str) -> Result<(u64, String), FuseError> {
+    let pool_id: u64 = pool_id
+        .parse()
+        .map_err(|_| FuseError::Config(format!("'{}' is not a pool id", pool_id)))?;
+    let (pools, _) = load_and_mount_pools(pool_config_path).await?;
+    pools
+        .into_iter()
+        .find(|pool| pool.pool_id == pool_id)
+        .map(|pool| (pool_id, pool.path))
+        .ok_or_else(|| FuseError::PoolUnavailable(format!("Pool '{}' is not configured", pool_id)))
+}
+
+// Everything needed to (re)establish a single FUSE mount.
+#[derive(Debug, Clone)]
+pub struct MountSpec {
+    pub mount_point: String,
+    pub pool_id: u64,
+    pub pool_root: String,
+    // Directory of the pool shown at the mount point; "/" for the whole pool.
+    pub subpath: String,
+    // Mounted read-only in the kernel.
+    pub read_only: bool,
+    // Mounted by a control socket command rather than from pool.toml.
+    pub managed: bool,
+}
+
+impl MountSpec {
+    // Whether `other` mounts the same thing, so mounting it again is a no-op.
+    fn same_volume(&self, other: &MountSpec) -> bool {
+        self.pool_id == other.pool_id && self.subpath == other.subpath && self.read_only == other.read_only
+    }
+}
+
+struct ActiveMount {
+    spec: MountSpec,
+    stats: Arc<MountStats>,
+    session: BackgroundSession,
+    // Lock on the pool held for read-write mounts.
+    lock: Option<Arc<PoolLock>>,
+}
+
+// Owns all live FUSE sessions and keeps them alive until shutdown.
+pub struct MountManager {
+    registry: Arc<Registry>,
+    pool_config_path: String,
+    startup: StartupSettings,
+    backend: BackendSettings,
+    fuse: FuseSettings,
+    mount_options: BTreeMap<String, MountOptions>,
+    views: BTreeMap<String, ViewSettings>,
+    active: Mutex<BTreeMap<String, ActiveMount>>,
+    // Mount points with a mount or remount currently in progress.
+    in_flight: Mutex<BTreeSet<String>>,
+    // Set under the `active` lock so no session can be added after shutdown began.
+    shutting_down: AtomicBool,
+    // Views of pool directories by pool and subpath, alive while a mount
+    // showing them is.
+    pool_views: Mutex<HashMap<(u64, String), Weak<PoolView>>>,
+    // Locks on pools mounted read-write, by pool, alive while a mount of
+    // the pool holds them.
+    pool_locks: Mutex<HashMap<u64, Weak<PoolLock>>>,
+    // Mount pools whose lock another process holds, with a warning.
+    force: bool,
+}
+
+impl MountManager {
+    pub fn new(
+        registry: Arc<Registry>,
+        pool_config_path: String,
+        startup: StartupSettings,
+        backend: BackendSettings,
+        fuse: FuseSettings,
+        mount_options: BTreeMap<String, MountOptions>,
+        views: BTreeMap<String, ViewSettings>,
+    ) -> Self {
+        Self {
+            registry,
+            pool_config_path,
+            startup,
+            backend,
+            fuse,
+            mount_options,
+            views,
+            active: Mutex::new(BTreeMap::new()),
+            in_flight: Mutex::new(BTreeSet::new()),
+            shutting_down: AtomicBool::new(false),
+            pool_views: Mutex::new(HashMap::new()),
+            pool_locks: Mutex::new(HashMap::new()),
+            force: false,
+        }
+    }
+
+    // Mounts pools read-write even while another rfs-fuse holds their lock
+    // (the --force flag).
+    pub fn forcing_pool_locks(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn pool_config_path(&self) -> &str {
+        &self.pool_config_path
+    }
+
+    pub fn mounted_count(&self) -> usize {
+        self.active.lock().unwrap().len()
+    }
+
+    // Specs and stats of all live sessions.
+    pub fn mounted(&self) -> Vec<(MountSpec, Arc<MountStats>)> {
+        let active = self.active.lock().unwrap();
+        active.values().map(|m| (m.spec.clone(), Arc::clone(&m.stats))).collect()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    // Number of mounts that are still being established or retried.
+    pub fn pending_count(&self) -> usize {
+        self.in_flight.lock().unwrap().len()
+    }
+
+    // Mounts every entry that is neither mounted nor being mounted, concurrently.
+    // Returns the outcome for each entry that was attempted.
+    pub async fn mount_entries(self: &Arc<Self>, entries: Vec<MountEntry>) -> Vec<(Arc<MountStats>, MountOutcome)> {
+        let mut handles = Vec::new();
+        let mut refused = Vec::new();
+        for entry in entries {
+            match self.claim(&entry.mount_point) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    refused.push((self.registry.register(&entry.mount_point, entry.pool_id), MountOutcome::Failed(e)));
+                    continue;
+                }
+            }
+            let stats = self.registry.register(&entry.mount_point, entry.pool_id);
+            let manager = Arc::clone(self);
+            let handle = tokio::spawn(async move {
+                let result = match entry.spec() {
+                    Ok(spec) => manager.mount_claimed(spec).await,
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(()) => {}
+                    Err(FuseError::PoolUnavailable(reason)) if manager.startup.retry_unavailable_pools => {
+                        // The claim is kept until the background retry gives up or succeeds.
+                        let stats = manager.registry.register(&entry.mount_point, entry.pool_id);
+                        stats.set_retrying(&reason);
+                        log(Subsystem::Mount, LogLevel::Warn, &format!("{}; retrying in the background", reason));
+                        tokio::spawn(Arc::clone(&manager).wait_for_pool(entry.mount_point));
+                        return MountOutcome::Deferred;
+                    }
+                    Err(e) => {
+                        manager.release(&entry.mount_point);
+                        return MountOutcome::Failed(e);
+                    }
+                }
+                manager.release(&entry.mount_point);
+                MountOutcome::Mounted
+            });
+            handles.push((stats, handle));
+        }
+
+        let mut results = Vec::new();
+        for (stats, handle) in handles {
+            let outcome = match handle.await {
+                Ok(outcome) => outcome,
+                Err(e) => MountOutcome::Failed(FuseError::Io(io::Error::other(e))),
+            };
+            results.push((stats, outcome));
+        }
+        results.extend(refused);
+        results
+    }
+
+    // Entries of pool.toml and the views of them.
+    async fn load_entries(&self) -> Result<Vec<MountEntry>, FuseError> {
+        let mut entries = load_mount_entries(&self.pool_config_path).await?;
+        add_views(&mut entries, &self.views);
+        Ok(entries)
+    }
+
+    // Re-reads pool.toml and mounts entries that were added since the last load.
+    pub async fn reload(self: &Arc<Self>) -> Result<(), FuseError> {
+        let entries = self.load_entries().await?;
+        let configured: BTreeSet<String> = entries.iter().map(|e| e.mount_point.clone()).collect();
+        for (stats, outcome) in self.mount_entries(entries).await {
+            match outcome {
+                MountOutcome::Mounted => log(Subsystem::Mount, LogLevel::Info, &format!("Hot-mounted {}", stats.mount_point)),
+                MountOutcome::Deferred => {}
+                MountOutcome::Failed(e) => {
+                    stats.set_failed(&e.to_string());
+                    log(Subsystem::Mount, LogLevel::Error, &format!("Mounting {} failed: {}", stats.mount_point, e));
+                }
+            }
+        }
+
+        for (mount_point, mount) in self.active.lock().unwrap().iter() {
+            if !mount.spec.managed && !configured.contains(mount_point) {
+                log(
+                    Subsystem::Mount,
+                    LogLevel::Warn,
+                    &format!("{} is no longer configured; it stays mounted until restart", mount_point),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Mounts a volume requested through the control socket. Returns false if
+    // the same volume is already mounted there, so requests can be repeated
+    // safely; a different volume at the mount point is a conflict.
+    pub async fn mount_managed(&self, spec: MountSpec) -> Result<bool, FuseError> {
+        if let Some(mounted) = self.active.lock().unwrap().get(&spec.mount_point) {
+            if mounted.spec.same_volume(&spec) {
+                return Ok(false);
+            }
+            return Err(FuseError::MountConflict(format!(
+                "{} already mounts pool {} at {}",
+                spec.mount_point, mounted.spec.pool_id, mounted.spec.subpath
+            )));
+        }
+        if !self.claim(&spec.mount_point)? {
+            return Err(FuseError::MountConflict(format!("{} is being mounted or recovered", spec.mount_point)));
+        }
+        let mount_point = spec.mount_point.clone();
+        let result = match std::fs::create_dir_all(&mount_point) {
+            Ok(()) => self.mount_claimed(spec).await,
+            Err(e) => Err(FuseError::Io(e)),
+        };
+        self.release(&mount_point);
+        if result.is_err() {
+            self.registry.unregister(&mount_point);
+        }
+        result.map(|()| true)
+    }
+
+    // Unmounts a mount point on request of the control socket. Returns false
+    // if nothing is mounted there. Mounts from pool.toml are not the control
+    // socket's to remove and are a conflict.
+    pub async fn unmount_managed(&self, mount_point: &str, settings: &ShutdownSettings) -> Result<bool, FuseError> {
+        if self.in_flight.lock().unwrap().contains(mount_point) {
+            return Err(FuseError::MountConflict(format!("{} is being mounted or recovered", mount_point)));
+        }
+        let mounted = {
+            let mut active = self.active.lock().unwrap();
+            match active.get(mount_point) {
+                None => return Ok(false),
+                Some(mounted) if !mounted.spec.managed => {
+                    return Err(FuseError::MountConflict(format!(
+                        "{} is mounted from pool.toml, not with mount",
+                        mount_point
+                    )));
+                }
+                Some(_) => active.remove(mount_point).unwrap(),
+            }
+        };
+        unmount_all(vec![(mount_point.to_string(), mounted.session)], settings).await;
+        // The pool stays locked until it is no longer mounted.
+        drop(mounted.lock);
+        self.registry.unregister(mount_point);
+        log(Subsystem::Mount, LogLevel::Info, &format!("Unmounted {} on request", mount_point));
+        Ok(true)
+    }
+
+    // Marks a mount point as in progress. Returns false if it is mounted or
+    // already claimed, and fails if it lies inside another mount point or
+    // contains one, since either mount would shadow part of the other.
+    fn claim(&self, mount_point: &str) -> Result<bool, FuseError> {
+        let active = self.active.lock().unwrap();
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if active.contains_key(mount_point) || in_flight.contains(mount_point) {
+            return Ok(false);
+        }
+        let path = Path::new(mount_point);
+        let overlapping = active.keys().chain(in_flight.iter()).find(|other| {
+            let other = Path::new(other);
+            path.starts_with(other) || other.starts_with(path)
+        });
+        if let Some(other) = overlapping {
+            let relation = if path == Path::new(other) {
+                "is the same directory as"
+            } else if path.starts_with(other) {
+                "lies inside"
+            } else {
+                "contains"
+            };
+            return Err(FuseError::MountConflict(format!("{} {} mount point {}", mount_point, relation, other)));
+        }
+        in_flight.insert(mount_point.to_string());
+        Ok(true)
+    }
+
+    fn release(&self, mount_point: &str) {
+        self.in_flight.lock().unwrap().remove(mount_point);
+    }
+
+    // Re-reads pool.toml with backoff until the pool behind a claimed mount point
+    // is reported and present, then mounts it.
+    async fn wait_for_pool(self: Arc<Self>, mount_point: String) {
+        let mut backoff = self.startup.retry_initial_backoff();
+        while !self.shutting_down.load(Ordering::SeqCst) {
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.startup.retry_max_backoff());
+
+            let entries = match self.load_entries().await {
+                Ok(entries) => entries,
+                Err(e) => {
+                    log(Subsystem::Mount, LogLevel::Warn, &format!("Reading pools for {} failed: {}", mount_point, e));
+                    continue;
+                }
+            };
+            let Some(entry) = entries.into_iter().find(|e| e.mount_point == mount_point) else {
+                log(
+                    Subsystem::Mount,
+                    LogLevel::Warn,
+                    &format!("{} is no longer configured; giving up on it", mount_point),
+                );
+                break;
+            };
+
+            let stats = self.registry.register(&mount_point, entry.pool_id);
+            let result = match entry.spec() {
+                Ok(spec) => self.mount_claimed(spec).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => break,
+                Err(FuseError::PoolUnavailable(reason)) => stats.set_retrying(&reason),
+                Err(e) => {
+                    // Only the pool's availability is waited for; a bad mount
+                    // point or a pool locked by another daemon stays failed.
+                    log(Subsystem::Mount, LogLevel::Error, &format!("Mounting {} failed: {}; giving up", mount_point, e));
+                    stats.set_failed(&e.to_string());
+                    break;
+                }
+            }
+        }
+        self.release(&mount_point);
+    }
+
+    // Mounts a pool whose mount point has been claimed and starts tracking its session.
+    async fn mount_claimed(&self, spec: MountSpec) -> Result<(), FuseError> {
+        let stats = self.registry.register(&spec.mount_point, spec.pool_id);
+        let lock = self.lock_pool(&spec)?;
+        let session = spawn_session(
+            spec.clone(),
+            self.backend_for(&spec, &stats),
+            self.options_for(&spec),
+            self.view_for(&spec),
+            self.startup.health_check,
+            self.fuse.mount_method,
+        )
+        .await?;
+        log(Subsystem::Mount, LogLevel::Info, &format!("Successfully mounted on {}", spec.mount_point));
+        stats.set_mounted();
+        self.track(spec, stats, session, lock);
+        Ok(())
+    }
+
+    // Locks the pool of a read-write mount against other rfs-fuse processes.
+    // Mounts of the same pool in this process share the lock. A pool that
+    // cannot be locked at all, e.g. on a file system without locks, is
+    // mounted anyway.
+    fn lock_pool(&self, spec: &MountSpec) -> Result<Option<Arc<PoolLock>>, FuseError> {
+        if spec.read_only {
+            return Ok(None);
+        }
+        let mut locks = self.pool_locks.lock().unwrap();
+        locks.retain(|_, lock| lock.strong_count() > 0);
+        if let Some(lock) = locks.get(&spec.pool_id).and_then(Weak::upgrade) {
+            return Ok(Some(lock));
+        }
+        let pool_root = Path::new(&spec.pool_root);
+        match PoolLock::acquire(pool_root) {
+            Ok(lock) => {
+                let lock = Arc::new(lock);
+                locks.insert(spec.pool_id, Arc::downgrade(&lock));
+                Ok(Some(lock))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                let holder = match pool_lock::holder(pool_root) {
+                    Some(pid) => format!("another rfs-fuse (pid {})", pid),
+                    None => "another rfs-fuse".to_string(),
+                };
+                if !self.force {
+                    return Err(FuseError::MountConflict(format!(
+                        "pool '{}' at '{}' is mounted read-write by {}; stop it, or start rfs with --force",
+                        spec.pool_id, spec.pool_root, holder
+                    )));
+                }
+                log(
+                    Subsystem::Mount,
+                    LogLevel::Warn,
+                    &format!(
+                        "Pool '{}' is mounted read-write by {}; mounting {} anyway (--force)",
+                        spec.pool_id, holder, spec.mount_point
+                    ),
+                );
+                Ok(None)
+            }
+            Err(e) => {
+                log(
+                    Subsystem::Mount,
+                    LogLevel::Warn,
+                    &format!("Could not lock pool '{}' at '{}': {}; mounting unlocked", spec.pool_id, spec.pool_root, e),
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    // Backend for a mount, sharing the pool's circuit breaker and limited by
+    // the mount's options. Must be called from within the tokio runtime.
+    pub fn backend_for(&self, spec: &MountSpec, stats: &Arc<MountStats>) -> Backend {
+        let options = self.options_for(spec);
+        Backend::new(
+            spec.pool_root.clone(),
+            self.backend.clone(),
+            Arc::clone(stats),
+            self.registry.breaker(spec.pool_id, &self.backend),
+        )
+        .within(&self.root_of(spec))
+        .with_iops_limit(options.max_iops)
+        .with_runtime_threads(options.backend_threads)
+    }
+
+    // The view of the pool directory a mount shows. Mounts of the same pool
+    // and subpath share it, so they agree on inode numbers and reuse each
+    // other's lookups; views of other directories of the pool share its
+    // cached entries.
+    fn view_for(&self, spec: &MountSpec) -> Arc<PoolView> {
+        let mut views = self.pool_views.lock().unwrap();
+        views.retain(|_, view| view.strong_count() > 0);
+        let root = self.root_of(spec);
+        let key = (spec.pool_id, root.clone());
+        if let Some(view) = views.get(&key).and_then(Weak::upgrade) {
+            return view;
+        }
+        let entries = views
+            .iter()
+            .filter(|((pool_id, _), _)| *pool_id == spec.pool_id)
+            .find_map(|(_, view)| view.upgrade())
+            .map(|view| Arc::clone(view.entries()))
+            .unwrap_or_default();
+        let view = Arc::new(PoolView::new(&root, entries));
+        views.insert(key, Arc::downgrade(&view));
+        view
+    }
+
+    // Directory of the pool a mount shows: its subpath and, for a mount with
+    // per-user homes, the directory holding them within it.
+    fn root_of(&self, spec: &MountSpec) -> String {
+        let homes = homes_dir(&self.options_for(spec).home).unwrap_or("/").to_string();
+        match format!("{}{}", spec.subpath.trim_end_matches('/'), homes.trim_end_matches('/')) {
+            root if root.is_empty() => "/".to_string(),
+            root => root,
+        }
+    }
+
+    fn options_for(&self, spec: &MountSpec) -> MountOptions {
+        self.mount_options.get(&spec.mount_point).cloned().unwrap_or_default()
+    }
+
+    fn track(
+        &self,
+        spec: MountSpec,
+        stats: Arc<MountStats>,
+        session: BackgroundSession,
+        lock: Option<Arc<PoolLock>>,
+    ) {
+        let mut active = self.active.lock().unwrap();
+        if self.shutting_down.load(Ordering::SeqCst) {
+            // Shutdown already collected the sessions; let this one unmount on drop.
+            return;
+        }
+        active.insert(spec.mount_point.clone(), ActiveMount { spec, stats, session, lock });
+    }
+
+    // Unmounts every tracked session.
+    pub async fn shutdown(&self, settings: &ShutdownSettings) {
+        let (sessions, locks): (Vec<_>, Vec<_>) = {
+            let mut active = self.active.lock().unwrap();
+            self.shutting_down.store(true, Ordering::SeqCst);
+            std::mem::take(&mut *active)
+                .into_values()
+                .map(|m| ((m.spec.mount_point, m.session), m.lock))
+                .unzip()
+        };
+        unmount_all(sessions, settings).await;
+        drop(locks);
+    }
+
+    // Periodically checks for sessions whose thread has stopped and remounts them.
+    pub async fn supervise(self: Arc<Self>, settings: SupervisorSettings) {
+        let mut ticker = tokio::time::interval(settings.check_interval());
+        loop {
+            ticker.tick().await;
+            for dead in self.take_finished() {
+                tokio::spawn(Arc::clone(&self).recover(dead, settings.clone()));
+            }
+        }
+    }
+
+    fn take_finished(&self) -> Vec<ActiveMount> {
+        let mut active = self.active.lock().unwrap();
+        let finished: Vec<String> = active
+            .iter()
+            .filter(|(_, m)| m.session.guard.is_finished())
+            .map(|(mount_point, _)| mount_point.clone())
+            .collect();
+        // Recovering mounts stay claimed so a reload cannot mount them twice.
+        self.in_flight.lock().unwrap().extend(finished.iter().cloned());
+        finished.iter().filter_map(|mount_point| active.remove(mount_point)).collect()
+    }
+
+    async fn recover(self: Arc<Self>, dead: ActiveMount, settings: SupervisorSettings) {
+        let mount_point = dead.spec.mount_point.clone();
+        self.remount(dead, settings).await;
+        self.release(&mount_point);
+    }
+
+    async fn remount(&self, dead: ActiveMount, settings: SupervisorSettings) {
+        // The pool stays locked while the mount is recovered.
+        let ActiveMount { spec, stats, session, lock } = dead;
+        let mount_point = spec.mount_point.clone();
+        let ended = tokio::task::spawn_blocking(move || collect_session(Path::new(&mount_point), session)).await;
+        let (reason, attached) = match ended {
+            Ok(outcome) => outcome,
+            Err(e) => (Some(format!("session could not be joined: {}", e)), true),
+        };
+
+        if reason.is_none() && !attached {
+            log(
+                Subsystem::Mount,
+                LogLevel::Warn,
+                &format!("{} was unmounted externally; not remounting", spec.mount_point),
+            );
+            stats.set_failed("unmounted externally");
+            return;
+        }
+        let reason = reason.unwrap_or_else(|| "session ended".to_string());
+        log(
+            Subsystem::Mount,
+            LogLevel::Error,
+            &format!("FUSE session for {} died ({}); remounting", spec.mount_point, reason),
+        );
+        stats.set_failed(&reason);
+
+        let mut backoff = settings.initial_backoff();
+        while !self.shutting_down.load(Ordering::SeqCst) {
+            let session = spawn_session(
+                spec.clone(),
+                self.backend_for(&spec, &stats),
+                self.options_for(&spec),
+                self.view_for(&spec),
+                self.startup.health_check,
+                self.fuse.mount_method,
+            )
+            .await;
+            match session {
+                Ok(session) => {
+                    log(Subsystem::Mount, LogLevel::Info, &format!("Remounted {}", spec.mount_point));
+                    stats.record_remount();
+                    stats.set_mounted();
+                    self.track(spec, stats, session, lock);
+                    return;
+                }
+                Err(e) => {
+                    log(
+                        Subsystem::Mount,
+                        LogLevel::Warn,
+                        &format!("Remount of {} failed: {}; retrying in {:?}", spec.mount_point, e, backoff),
+                    );
+                    stats.set_failed(&e.to_string());
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(settings.max_backoff());
+                }
+            }
+        }
+    }
+}
+
+// Each FUSE instance needs to be spawned on a blocking-safe thread. With
+// `health_check` set the pool is probed first, and a pool failing the probe
+// is reported as unavailable so the mount is retried or refused.
+pub async fn spawn_session(
+    spec: MountSpec,
+    backend: Backend,
+    options: MountOptions,
+    view: Arc<PoolView>,
+    health_check: bool,
+    method: MountMethod,
+) -> Result<BackgroundSession, FuseError> {
+    let handle = tokio::task::spawn_blocking(move || {
+        log(
+            Subsystem::Mount,
+            LogLevel::Info,
+            &format!("Preparing to mount pool '{}' at '{}'", spec.pool_root, spec.mount_point),
+        );
+        // A lazy mount does not touch its pool until it is first used.
+        if health_check && !options.lazy && let Err(problem) = health::probe(&backend) {
+            return Err(FuseError::PoolUnavailable(format!(
+                "Pool '{}' for mount point '{}' failed its health check: {}",
+                spec.pool_id, spec.mount_point, problem
+            )));
+        }
+        let access = Access::of(&options, spec.read_only);
+        let fuse_fs = RfsFuse::new(backend, spec.mount_point.clone(), options, view);
+        fuse_mount::mount(fuse_fs, Path::new(&spec.mount_point), method, access).map_err(FuseError::Io)
+    });
+    match handle.await {
+        Ok(result) => result,
+        Err(e) => Err(FuseError::Io(io::Error::other(e))),
+    }
+}
+
+// Joins a stopped session and returns why it ended (None for a clean exit)
+// and whether its mount point is still attached. A dead mount that is still
+// attached is detached so it can be mounted again.
+fn collect_session(mount_point: &Path, session: BackgroundSession) -> (Option<String>, bool) {
+    let reason = match session.guard.join() {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(format!("session failed: {}", e)),
+        Err(_) => Some("session thread panicked".to_string()),
+    };
+    let attached = is_mounted(mount_point);
+    if attached && let Err(e) = detach(mount_point) {
+        log(
+            Subsystem::Mount,
+            LogLevel::Warn,
+            &format!("Could not detach dead mount {}: {}", mount_point.display(), e),
+        );
+    }
+    (reason, attached)
+}
+
+// Checks /proc/self/mounts for a mount at exactly this path.
+#[cfg(target_os = "linux")]
+pub fn is_mounted(mount_point: &Path) -> bool {
+    let Ok(mounts) = std::fs::read_to_string("/proc/self/mounts") else {
+        return false;
+    };
+    mounts
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .any(|field| Path::new(&unescape_mount_field(field)) == mount_point)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_mounted(_mount_point: &Path) -> bool {
+    true
+}
+
+// The kernel escapes space, tab, newline and backslash as octal sequences.
+#[cfg(target_os = "linux")]
+fn unescape_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'\\' && i + 3 < bytes.len())
+            .then(|| std::str::from_utf8(&bytes[i + 1..i + 4]).ok())
+            .flatten()
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_views, MountEntry, MountManager, MountSpec};
+    use crate::config::ViewSettings;
+    use crate::error::FuseError;
+    use crate::stats::Registry;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    fn manager() -> MountManager {
+        MountManager::new(
+            Arc::new(Registry::default()),
+            String::new(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            BTreeMap::new(),
+            BTreeMap::new(),
+        )
+    }
+
+    #[test]
+    fn nested_mount_points_are_refused() {
+        let manager = manager();
+        assert!(manager.claim("/mnt/a").unwrap());
+        assert!(!manager.claim("/mnt/a").unwrap());
+        assert!(manager.claim("/mnt/ab").unwrap());
+
+        let conflict = |mount_point| match manager.claim(mount_point) {
+            Err(FuseError::MountConflict(message)) => message,
+            other => panic!("{} was not refused: {:?}", mount_point, other),
+        };
+        assert_eq!(conflict("/mnt/a/b"), "/mnt/a/b lies inside mount point /mnt/a");
+        assert_eq!(conflict("/mnt"), "/mnt contains mount point /mnt/a");
+        assert_eq!(conflict("/mnt/a/"), "/mnt/a/ is the same directory as mount point /mnt/a");
+
+        manager.release("/mnt/a");
+        assert!(manager.claim("/mnt/a/b").unwrap());
+    }
+
+    #[test]
+    fn a_pool_is_mounted_read_write_by_one_daemon_unless_forced() {
+        let pool_root = std::env::temp_dir().join(format!("rfs-mount-lock-{}", std::process::id()));
+        std::fs::create_dir_all(&pool_root).unwrap();
+        let spec = |mount_point: &str, read_only| MountSpec {
+            mount_point: mount_point.to_string(),
+            pool_id: 7,
+            pool_root: pool_root.to_string_lossy().into_owned(),
+            subpath: "/".to_string(),
+            read_only,
+            managed: false,
+        };
+
+        // Locks on one directory taken through separate opens exclude each
+        // other like those of two processes.
+        let (first, second) = (manager(), manager());
+        let lock = first.lock_pool(&spec("/mnt/a", false)).unwrap().unwrap();
+        let shared = first.lock_pool(&spec("/mnt/b", false)).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&lock, &shared));
+        match second.lock_pool(&spec("/mnt/c", false)) {
+            Err(FuseError::MountConflict(message)) => assert!(message.contains("--force"), "{}", message),
+            other => panic!("a locked pool was mounted: {:?}", other.map(|lock| lock.is_some())),
+        }
+        assert!(second.lock_pool(&spec("/mnt/c", true)).unwrap().is_none());
+        let forced = manager().forcing_pool_locks(true);
+        assert!(forced.lock_pool(&spec("/mnt/c", false)).unwrap().is_none());
+
+        drop((lock, shared));
+        assert!(second.lock_pool(&spec("/mnt/c", false)).unwrap().is_some());
+        std::fs::remove_dir(&pool_root).unwrap();
+    }
+
+    #[test]
+    fn unknown_pool_ids_are_configuration_errors_and_missing_pools_unavailable() {
+        let entry = |pool_root: Option<&str>| MountEntry {
+            mount_point: "/mnt/data".to_string(),
+            pool_id: 3,
+            pool_root: pool_root.map(str::to_string),
+            subpath: "/".to_string(),
+            read_only: false,
+        };
+        assert!(matches!(entry(None).spec(), Err(FuseError::MountConfig(_))));
+        assert!(matches!(entry(Some("/nonexistent/pool3")).spec(), Err(FuseError::PoolUnavailable(_))));
+    }
+
+    #[test]
+    fn views_show_the_pool_of_their_mount_read_only() {
+        let mut entries = vec![MountEntry {
+            mount_point: "/mnt/data".to_string(),
+            pool_id: 3,
+            pool_root: Some("/srv/pool3".to_string()),
+            subpath: "/".to_string(),
+            read_only: false,
+        }];
+        let view = |of: &str, subpath: &str| ViewSettings {
+            of: of.to_string(),
+            subpath: subpath.to_string(),
+        };
+        let views = BTreeMap::from([
+            ("/export/data".to_string(), view("/mnt/data/", "/projects/")),
+            ("/export/other".to_string(), view("/mnt/other", "/")),
+        ]);
+        add_views(&mut entries, &views);
+
+        assert_eq!(entries.len(), 2);
+        let view = &entries[1];
+        assert_eq!(
+            (view.mount_point.as_str(), view.pool_id, view.subpath.as_str(), view.read_only),
+            ("/export/data", 3, "/projects", true)
+        );
+    }
+}
@@ -0,0 +1,63 @@
+// src/panic_guard.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::logging::{log, Subsystem};
+use rfs_utils::LogLevel;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+
+thread_local! {
+    // Set while a FUSE operation runs under `contain`.
+    static CONTAINED: Cell<bool> = const { Cell::new(false) };
+    // Message and backtrace of the last contained panic on this thread.
+    static LAST_PANIC: RefCell<Option<(String, Backtrace)>> = const { RefCell::new(None) };
+}
+
+// Installs a panic hook that hands panics inside FUSE operations to
+// `contain` and logs every other panic with its backtrace.
+pub fn install_hook() {
+    panic::set_hook(Box::new(|info| {
+        let message = describe(info);
+        let backtrace = Backtrace::force_capture();
+        if CONTAINED.with(Cell::get) {
+            LAST_PANIC.with(|last| *last.borrow_mut() = Some((message, backtrace)));
+        } else {
+            let thread = std::thread::current().name().unwrap_or("<unnamed>").to_string();
+            log(
+                Subsystem::Daemon,
+                LogLevel::Error,
+                &format!("Thread '{}' panicked: {}\n{}", thread, message, backtrace),
+            );
+        }
+    }));
+}
+
+// Runs `f`, catching a panic instead of letting it kill the session thread.
+// Returns the panic message and backtrace if `f` panicked. A reply that `f`
+// owned and had not sent yet is answered with EIO by fuser when it is dropped
+// during unwinding.
+pub fn contain(f: impl FnOnce()) -> Result<(), (String, Backtrace)> {
+    let outer = CONTAINED.with(|contained| contained.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CONTAINED.with(|contained| contained.set(outer));
+    result.map_err(|_| {
+        LAST_PANIC
+            .with(|last| last.borrow_mut().take())
+            .unwrap_or_else(|| ("unknown panic".to_string(), Backtrace::disabled()))
+    })
+}
+
+fn describe(info: &PanicHookInfo<'_>) -> String {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "non-string panic payload".to_string());
+    match info.location() {
+        Some(location) => format!("{} at {}", message, location),
+        None => message,
+    }
+}
@@ -0,0 +1,75 @@
+// src/pool_lock.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+// Advisory lock on the root directory of a pool, held while the daemon
+// mounts the pool read-write. Two daemons started by accident would each
+// cache the pool's entries without seeing the other's changes; the second
+// one finds the lock taken and refuses to mount. Locking the directory
+// itself leaves nothing behind in the pool, and the kernel drops the lock
+// when the daemon dies.
+
+use nix::fcntl::{Flock, FlockArg};
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+pub struct PoolLock {
+    _lock: Flock<File>,
+}
+
+impl PoolLock {
+    // Takes the lock on `pool_root` without waiting. Fails with WouldBlock
+    // when another process holds it.
+    pub fn acquire(pool_root: &Path) -> io::Result<Self> {
+        let dir = File::open(pool_root)?;
+        match Flock::lock(dir, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => Ok(Self { _lock: lock }),
+            Err((_, errno)) => Err(io::Error::from(errno)),
+        }
+    }
+}
+
+// Pid of the process holding the lock on `pool_root`, from /proc/locks.
+pub fn holder(pool_root: &Path) -> Option<u32> {
+    let metadata = fs::metadata(pool_root).ok()?;
+    let locks = fs::read_to_string("/proc/locks").ok()?;
+    holder_in(&locks, metadata.dev(), metadata.ino())
+}
+
+// Lines of /proc/locks read "1: FLOCK  ADVISORY  WRITE 1234 08:01:5678 0 EOF",
+// the file given as major:minor (hex) and inode.
+fn holder_in(locks: &str, dev: u64, ino: u64) -> Option<u32> {
+    let file = format!("{:02x}:{:02x}:{}", libc::major(dev), libc::minor(dev), ino);
+    locks.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [_, "FLOCK", _, "WRITE", pid, locked, ..] if *locked == file => pid.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{holder, holder_in, PoolLock};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn a_locked_pool_is_refused_until_its_holder_lets_go() {
+        let pool = std::env::temp_dir().join(format!("rfs-pool-lock-{}", std::process::id()));
+        std::fs::create_dir_all(&pool).unwrap();
+
+        let lock = PoolLock::acquire(&pool).unwrap();
+        let second = PoolLock::acquire(&pool).map(|_| ()).map_err(|e| e.kind());
+        assert_eq!(second, Err(ErrorKind::WouldBlock));
+        assert_eq!(holder(&pool), Some(std::process::id()));
+        drop(lock);
+        assert!(PoolLock::acquire(&pool).is_ok());
+
+        std::fs::remove_dir(&pool).unwrap();
+        let locks = "1: POSIX  ADVISORY  WRITE 77 103:02:42 0 EOF\n2: FLOCK  ADVISORY  WRITE 4242 103:02:42 0 EOF\n";
+        assert_eq!(holder_in(locks, libc::makedev(0x103, 2), 42), Some(4242));
+    }
+}
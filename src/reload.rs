@@ -0,0 +1,85 @@
+// src/reload.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::config::ReloadSettings;
+use crate::logging::{log, Subsystem};
+use crate::mount::MountManager;
+use rfs_utils::LogLevel;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+
+// Starts the SIGHUP handler and, if enabled, the pool.toml watcher. Both
+// trigger a reload that mounts pools added to pool.toml.
+pub fn spawn(manager: Arc<MountManager>, pool_config_path: &str, settings: &ReloadSettings) -> std::io::Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<&'static str>();
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    let hangup_tx = tx.clone();
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            if hangup_tx.send("SIGHUP").is_err() {
+                break;
+            }
+        }
+    });
+
+    if settings.watch_pool_config {
+        let path = PathBuf::from(pool_config_path);
+        std::thread::Builder::new()
+            .name("rfs-pool-watch".to_string())
+            .spawn(move || watch(&path, tx))?;
+    }
+
+    let pool_config_path = pool_config_path.to_string();
+    let debounce = settings.debounce();
+    tokio::spawn(async move {
+        while let Some(cause) = rx.recv().await {
+            // Editors and provisioning tools often write the file in several steps.
+            tokio::time::sleep(debounce).await;
+            while rx.try_recv().is_ok() {}
+
+            log(Subsystem::Mount, LogLevel::Info, &format!("Reloading {} ({})", pool_config_path, cause));
+            if let Err(e) = manager.reload().await {
+                log(Subsystem::Mount, LogLevel::Error, &format!("Reload failed: {}", e));
+            }
+        }
+    });
+    Ok(())
+}
+
+// Watches the directory holding the pool config, since tools commonly replace
+// the file with a rename rather than writing it in place.
+#[cfg(target_os = "linux")]
+fn watch(path: &Path, tx: mpsc::UnboundedSender<&'static str>) {
+    use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return;
+    };
+    let result = Inotify::init(InitFlags::IN_CLOEXEC).and_then(|inotify| {
+        let flags = AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_CREATE;
+        inotify.add_watch(dir, flags)?;
+        log(Subsystem::Mount, LogLevel::Info, &format!("Watching {} for changes", path.display()));
+        loop {
+            let events = inotify.read_events()?;
+            if events.iter().any(|e| e.name.as_deref() == Some(file_name)) && tx.send("file changed").is_err() {
+                return Ok(());
+            }
+        }
+    });
+    if let Err(e) = result {
+        log(Subsystem::Mount, LogLevel::Error, &format!("Watching {} failed: {}", path.display(), e));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch(path: &Path, _tx: mpsc::UnboundedSender<&'static str>) {
+    log(
+        Subsystem::Mount,
+        LogLevel::Warn,
+        &format!("Watching {} is only supported on Linux; use SIGHUP to reload", path.display()),
+    );
+}
@@ -0,0 +1,163 @@
+// src/scrub.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::backend::Backend;
+use crate::config::ScrubSettings;
+use crate::logging::{log, Record, Subsystem};
+use crate::mount::MountManager;
+use crate::stats::{MountStats, ScrubReport};
+use crate::store::{Entry, EntryKind};
+use rfs_utils::LogLevel;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+// Modification times further in the future than this are reported.
+const CLOCK_SKEW: Duration = Duration::from_secs(24 * 3600);
+
+// Periodically walks every mounted pool and checks its metadata, one mount
+// at a time and at a throttled rate. Results go to the mount's stats, where
+// the metrics and the `.rfs/scrub` file read them.
+pub fn spawn(manager: Arc<MountManager>, settings: ScrubSettings) {
+    tokio::spawn(async move {
+        loop {
+            for (spec, stats) in manager.mounted() {
+                if manager.is_shutting_down() {
+                    return;
+                }
+                let backend = manager.backend_for(&spec, &stats);
+                let walk_manager = Arc::clone(&manager);
+                let walk_settings = settings.clone();
+                let walk = tokio::task::spawn_blocking(move || {
+                    scrub_mount(&backend, &stats, &walk_settings, &walk_manager)
+                });
+                if let Err(e) = walk.await {
+                    log(Subsystem::Mount, LogLevel::Error, &format!("Scrub of {} failed: {}", spec.mount_point, e));
+                }
+            }
+            tokio::time::sleep(settings.interval()).await;
+        }
+    });
+}
+
+fn scrub_mount(backend: &Backend, stats: &MountStats, settings: &ScrubSettings, manager: &MountManager) {
+    log(Subsystem::Mount, LogLevel::Info, &format!("Scrubbing {}", stats.mount_point));
+    stats.update_scrub(|report| {
+        *report = ScrubReport {
+            running: true,
+            runs: report.runs,
+            started_at: Some(SystemTime::now()),
+            ..ScrubReport::default()
+        }
+    });
+
+    let completed = walk(
+        backend,
+        settings.pause(),
+        || manager.is_shutting_down(),
+        |entries| {
+            stats.update_scrub(|report| {
+                report.directories += 1;
+                report.entries += entries;
+            })
+        },
+        |path, problem| report_problem(stats, path, problem),
+    );
+    if !completed {
+        stats.update_scrub(|report| report.running = false);
+        return;
+    }
+
+    let report = stats.scrub();
+    stats.update_scrub(|report| {
+        report.running = false;
+        report.runs += 1;
+        report.finished_at = Some(SystemTime::now());
+    });
+    let level = if report.errors > 0 { LogLevel::Warn } else { LogLevel::Info };
+    log(
+        Subsystem::Mount,
+        level,
+        &format!(
+            "Scrub of {} finished: {} directories, {} entries, {} problems",
+            stats.mount_point, report.directories, report.entries, report.errors
+        ),
+    );
+}
+
+// Walks the namespace of a pool depth-first from the root, checking every
+// entry and pausing after each directory. `listed` is called with the entry
+// count of every listed directory and `problem` with the path and
+// description of every problem found. Returns false if `stop` ended the walk
+// early.
+pub fn walk(
+    backend: &Backend,
+    pause: Duration,
+    stop: impl Fn() -> bool,
+    mut listed: impl FnMut(u64),
+    mut problem: impl FnMut(&str, &str),
+) -> bool {
+    let mut pending = vec!["/".to_string()];
+    while let Some(dir) = pending.pop() {
+        if stop() {
+            return false;
+        }
+        match backend.list_directory(&dir) {
+            Ok(listing) => {
+                let mut problems = Vec::new();
+                for (name, entry) in &listing {
+                    let path = child_path(&dir, name);
+                    if let Some(problem) = check_entry(name, entry) {
+                        problems.push((path.clone(), problem));
+                    }
+                    if entry.kind == EntryKind::Directory && is_valid_name(name) {
+                        pending.push(path);
+                    }
+                }
+                listed(listing.len() as u64);
+                for (path, description) in problems {
+                    problem(&path, description);
+                }
+            }
+            // The directory was removed after its parent was listed.
+            Err(libc::ENOENT) => {}
+            Err(errno) => problem(&dir, &std::io::Error::from_raw_os_error(errno).to_string()),
+        }
+        if !pause.is_zero() {
+            std::thread::sleep(pause);
+        }
+    }
+    true
+}
+
+// Checks the metadata of a single entry.
+pub fn check_entry(name: &str, entry: &Entry) -> Option<&'static str> {
+    if !is_valid_name(name) {
+        return Some("invalid entry name");
+    }
+    if entry.modified_at > SystemTime::now() + CLOCK_SKEW {
+        return Some("modification time in the future");
+    }
+    None
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/') && !name.contains('\0')
+}
+
+fn child_path(dir: &str, name: &str) -> String {
+    if dir == "/" { format!("/{}", name) } else { format!("{}/{}", dir, name) }
+}
+
+fn report_problem(stats: &MountStats, path: &str, problem: &str) {
+    stats.update_scrub(|report| {
+        report.errors += 1;
+        report.last_error = Some(format!("{}: {}", path, problem));
+    });
+    Record::new(Subsystem::Mount, LogLevel::Warn, "Scrub found a problem")
+        .op("scrub")
+        .mount(&stats.mount_point)
+        .path(path)
+        .error(problem)
+        .emit();
+}
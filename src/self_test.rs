@@ -0,0 +1,106 @@
+// src/self_test.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::backend::Backend;
+use crate::config::{load_settings, MountOptions, Paths, Settings};
+use crate::control_dir;
+use crate::error::FuseError;
+use crate::home::homes_dir;
+use crate::mount::{load_mount_entries, spawn_session, MountEntry};
+use crate::stats::Registry;
+use crate::unmount::unmount_all;
+use crate::validate;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+// `rfs self-test`: mounts the pool of every mount in pool.toml at a
+// temporary directory, with that mount's options, and probes it through the
+// kernel: listing the root, stating its entries and reading `.rfs/stats`.
+// Prints one line per probe and returns the exit code: 0 if every mount
+// passed, 1 if one failed and 2 if the configuration could not be loaded.
+pub async fn run(paths: &Paths) -> i32 {
+    let loaded = match load_settings(&paths.settings) {
+        Ok(settings) => match load_mount_entries(&paths.pool_config).await {
+            Ok(entries) => validate::report(validate::mount_problems(&paths.pool_config, &entries), FuseError::MountConfig)
+                .map(|()| (settings, entries)),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    let (settings, entries) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("self-test: {}", e);
+            return 2;
+        }
+    };
+    if entries.is_empty() {
+        println!("No FUSE mounts defined in pool.toml");
+        return 0;
+    }
+
+    let mut failed = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        println!("{} (pool {})", entry.mount_point, entry.pool_id);
+        if let Err(e) = test_mount(&settings, entry, index).await {
+            failed += 1;
+            println!("  FAILED: {}", e);
+        }
+    }
+    println!("{} of {} mounts passed", entries.len() - failed, entries.len());
+    if failed == 0 { 0 } else { 1 }
+}
+
+async fn test_mount(settings: &Settings, entry: &MountEntry, index: usize) -> Result<(), FuseError> {
+    let mut spec = entry.spec()?;
+    let options = settings.mounts.get(&entry.mount_point).cloned().unwrap_or_default();
+    let temp = std::env::temp_dir().join(format!("rfs-self-test-{}-{}", std::process::id(), index));
+    fs::create_dir_all(&temp)?;
+    spec.mount_point = temp.to_string_lossy().into_owned();
+
+    let registry = Registry::default();
+    let stats = registry.register(&spec.mount_point, spec.pool_id);
+    let breaker = registry.breaker(spec.pool_id, &settings.backend);
+    let backend = Backend::new(spec.pool_root.clone(), settings.backend.clone(), stats, breaker)
+        .within(homes_dir(&options.home).unwrap_or("/"))
+        .with_iops_limit(options.max_iops);
+    // The health check runs here even for lazy mounts, which skip it when
+    // mounted by the daemon.
+    let options = MountOptions { lazy: false, ..options };
+    let session = match spawn_session(spec.clone(), backend, options, Default::default(), true, settings.fuse.mount_method).await {
+        Ok(session) => session,
+        Err(e) => {
+            let _ = fs::remove_dir(&temp);
+            return Err(e);
+        }
+    };
+    println!("  mount: ok");
+
+    let root = temp.clone();
+    let probed = tokio::task::spawn_blocking(move || probe(&root)).await;
+    unmount_all(vec![(spec.mount_point, session)], &settings.shutdown).await;
+    let _ = fs::remove_dir(&temp);
+    probed.map_err(|e| FuseError::Io(io::Error::other(e)))?.map_err(FuseError::Io)
+}
+
+// Runs the probes against a mounted pool, stopping at the first failure.
+fn probe(root: &Path) -> io::Result<()> {
+    let started = Instant::now();
+    let entries: Vec<_> = fs::read_dir(root)?.collect::<Result<_, _>>()?;
+    println!("  list: ok, {} entries in {:?}", entries.len(), started.elapsed());
+
+    let started = Instant::now();
+    for entry in &entries {
+        fs::symlink_metadata(entry.path())?;
+    }
+    println!("  stat: ok, {} entries in {:?}", entries.len(), started.elapsed());
+
+    let started = Instant::now();
+    let stats = fs::read_to_string(root.join(control_dir::NAME).join("stats"))?;
+    println!("  read: ok, {} bytes of {}/stats in {:?}", stats.len(), control_dir::NAME, started.elapsed());
+    println!("  write: skipped, the FUSE layer is read-only");
+    Ok(())
+}
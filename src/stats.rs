@@ -0,0 +1,1021 @@
+// src/stats.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::breaker::Breaker;
+use crate::config::BackendSettings;
+use crate::inflight::{InFlight, InFlightOp};
+use crate::log_sink::format_utc;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Lifecycle state of a configured mount.
+#[derive(Debug, Clone)]
+pub enum MountState {
+    Pending,
+    // Waiting for the pool to become available.
+    Retrying(String),
+    Mounted,
+    Failed(String),
+}
+
+impl MountState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MountState::Pending => "pending",
+            MountState::Retrying(_) => "retrying",
+            MountState::Mounted => "mounted",
+            MountState::Failed(_) => "failed",
+        }
+    }
+}
+
+// Progress of the current scrub of a mount, or the result of the last one.
+#[derive(Debug, Clone, Default)]
+pub struct ScrubReport {
+    pub running: bool,
+    // Completed runs since startup.
+    pub runs: u64,
+    pub started_at: Option<SystemTime>,
+    pub finished_at: Option<SystemTime>,
+    pub directories: u64,
+    pub entries: u64,
+    pub errors: u64,
+    pub last_error: Option<String>,
+}
+
+impl ScrubReport {
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "state={}", if self.running { "running" } else { "idle" });
+        let _ = writeln!(out, "runs={}", self.runs);
+        if let Some(started_at) = self.started_at {
+            let _ = writeln!(out, "started={}", format_utc(started_at));
+        }
+        if let Some(finished_at) = self.finished_at {
+            let _ = writeln!(out, "finished={}", format_utc(finished_at));
+        }
+        let _ = writeln!(out, "directories={}", self.directories);
+        let _ = writeln!(out, "entries={}", self.entries);
+        let _ = writeln!(out, "errors={}", self.errors);
+        if let Some(error) = &self.last_error {
+            let _ = writeln!(out, "last_error={:?}", error);
+        }
+        out
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "running": self.running,
+            "runs": self.runs,
+            "started": self.started_at.map(unix_secs),
+            "finished": self.finished_at.map(unix_secs),
+            "directories": self.directories,
+            "entries": self.entries,
+            "errors": self.errors,
+            "last_error": self.last_error,
+        })
+    }
+}
+
+// Failed backend calls kept per mount.
+const RECENT_ERRORS: usize = 50;
+
+// A backend call that failed, and the FUSE operation it was made for; calls
+// made outside of one, such as by the scrub, have no operation.
+#[derive(Debug, Clone)]
+pub struct BackendError {
+    pub at: SystemTime,
+    pub op: Option<&'static str>,
+    pub path: String,
+    pub errno: i32,
+    pub message: String,
+}
+
+impl BackendError {
+    fn to_json(&self) -> Value {
+        json!({
+            "at": unix_secs(self.at),
+            "op": self.op,
+            "path": self.path,
+            "errno": self.errno,
+            "error": self.message,
+        })
+    }
+
+    pub fn render(&self) -> String {
+        format!(
+            "{} op={} path={:?} errno={} error={:?}",
+            format_utc(self.at),
+            self.op.unwrap_or("-"),
+            self.path,
+            self.errno,
+            self.message
+        )
+    }
+}
+
+// Processes accounted separately per mount; beyond this, processes that
+// have exited are forgotten, at most once per PRUNE_INTERVAL, and new ones
+// only count toward their uid.
+const ACCOUNTED_PIDS: usize = 256;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+// Threads whose process is remembered; the table starts over beyond this.
+const KNOWN_THREADS: usize = 4096;
+
+// Operations and bytes moved on behalf of one uid or process.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoCounts {
+    pub ops: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl IoCounts {
+    fn add(&mut self, ops: u64, bytes_read: u64, bytes_written: u64) {
+        self.ops += ops;
+        self.bytes_read += bytes_read;
+        self.bytes_written += bytes_written;
+    }
+
+    fn render(self) -> String {
+        format!("ops={} bytes_read={} bytes_written={}", self.ops, self.bytes_read, self.bytes_written)
+    }
+
+    fn to_json(self) -> Value {
+        json!({ "ops": self.ops, "bytes_read": self.bytes_read, "bytes_written": self.bytes_written })
+    }
+}
+
+// A process accounted separately, with the uid and executable it had when
+// first seen.
+#[derive(Debug, Clone)]
+pub struct ProcessIo {
+    pub uid: u32,
+    pub exe: Option<String>,
+    pub counts: IoCounts,
+}
+
+// I/O of a mount by requesting uid, and by process when the mount's
+// account_pids option is set.
+#[derive(Default)]
+pub struct IoAccounts {
+    per_pid: AtomicBool,
+    uids: Mutex<BTreeMap<u32, IoCounts>>,
+    pids: Mutex<PidTable>,
+}
+
+// Processes by pid, and the pid of every thread seen: FUSE reports the
+// thread that made a request, and a multithreaded process is counted once.
+#[derive(Default)]
+struct PidTable {
+    processes: BTreeMap<u32, ProcessIo>,
+    threads: HashMap<u32, u32>,
+    pruned_at: Option<Instant>,
+}
+
+impl IoAccounts {
+    pub fn set_per_pid(&self, per_pid: bool) {
+        self.per_pid.store(per_pid, Ordering::Relaxed);
+        if !per_pid {
+            *self.pids.lock().unwrap() = PidTable::default();
+        }
+    }
+
+    // Counts operations and bytes for the requester of `op`.
+    pub fn record(&self, op: &InFlightOp, ops: u64, bytes_read: u64, bytes_written: u64) {
+        self.uids.lock().unwrap().entry(op.uid).or_default().add(ops, bytes_read, bytes_written);
+        if !self.per_pid.load(Ordering::Relaxed) || op.pid == 0 {
+            return;
+        }
+        let mut pids = self.pids.lock().unwrap();
+        if pids.threads.len() >= KNOWN_THREADS {
+            pids.threads.clear();
+        }
+        let pid = *pids.threads.entry(op.pid).or_insert_with(|| process_of(op.pid));
+        let PidTable { processes, pruned_at, .. } = &mut *pids;
+        if !processes.contains_key(&pid) && processes.len() >= ACCOUNTED_PIDS {
+            if pruned_at.is_none_or(|at| at.elapsed() >= PRUNE_INTERVAL) {
+                *pruned_at = Some(Instant::now());
+                processes.retain(|pid, _| Path::new(&format!("/proc/{}", pid)).exists());
+            }
+            if processes.len() >= ACCOUNTED_PIDS {
+                return;
+            }
+        }
+        let process = processes.entry(pid).or_insert_with(|| ProcessIo {
+            uid: op.uid,
+            exe: op.exe().map(str::to_string),
+            counts: IoCounts::default(),
+        });
+        process.counts.add(ops, bytes_read, bytes_written);
+    }
+
+    pub fn uids(&self) -> BTreeMap<u32, IoCounts> {
+        self.uids.lock().unwrap().clone()
+    }
+
+    pub fn pids(&self) -> BTreeMap<u32, ProcessIo> {
+        self.pids.lock().unwrap().processes.clone()
+    }
+
+    // Renders one line per uid, then one per process, each starting with
+    // `prefix`.
+    fn render(&self, prefix: &str, uids: &BTreeMap<u32, IoCounts>, pids: &BTreeMap<u32, ProcessIo>) -> String {
+        let mut out = String::new();
+        for (uid, counts) in uids {
+            let _ = writeln!(out, "{}uid={} {}", prefix, uid, counts.render());
+        }
+        for (pid, process) in pids {
+            let exe = process.exe.as_deref().unwrap_or("-");
+            let _ = writeln!(out, "{}pid={} uid={} exe={} {}", prefix, pid, process.uid, exe, process.counts.render());
+        }
+        out
+    }
+
+    fn to_json(&self) -> Value {
+        let uids: Map<String, Value> =
+            self.uids().iter().map(|(uid, counts)| (uid.to_string(), counts.to_json())).collect();
+        let pids: Vec<Value> = self
+            .pids()
+            .iter()
+            .map(|(pid, process)| {
+                let mut object = process.counts.to_json();
+                object["pid"] = Value::from(*pid);
+                object["uid"] = Value::from(process.uid);
+                object["exe"] = Value::from(process.exe.clone());
+                object
+            })
+            .collect();
+        json!({ "uids": uids, "pids": pids })
+    }
+
+    // Forgets everything counted so far, returning it as rendered lines.
+    fn reset(&self) -> String {
+        let uids = std::mem::take(&mut *self.uids.lock().unwrap());
+        let pids = std::mem::take(&mut self.pids.lock().unwrap().processes);
+        self.render("io ", &uids, &pids)
+    }
+}
+
+// Process (thread group) of the thread `tid`, from /proc/<tid>/status; the
+// thread itself if that cannot be read.
+fn process_of(tid: u32) -> u32 {
+    std::fs::read_to_string(format!("/proc/{}/status", tid))
+        .ok()
+        .and_then(|status| status.lines().find_map(|line| line.strip_prefix("Tgid:")?.trim().parse().ok()))
+        .unwrap_or(tid)
+}
+
+// State and counters for a single mount point.
+pub struct MountStats {
+    pub mount_point: String,
+    pub pool_id: u64,
+    state: Mutex<MountState>,
+    pub mount_failures: AtomicU64,
+    pub remounts: AtomicU64,
+    // Backend calls abandoned because they exceeded the configured timeout
+    // or their operation was aborted by the watchdog.
+    pub backend_timeouts: AtomicU64,
+    // Backend calls repeated after a transient failure.
+    pub backend_retries: AtomicU64,
+    // Time backend calls were held back by the mount's IOPS limit.
+    pub throttle_wait_us: AtomicU64,
+    // FUSE operations that panicked and were answered with EIO.
+    pub handler_panics: AtomicU64,
+    // Times the connection to the pool was lost, and since when it is down.
+    pub disconnects: AtomicU64,
+    disconnected_since: Mutex<Option<Instant>>,
+    // FUSE operations the watchdog found running past its threshold.
+    pub stuck_ops: AtomicU64,
+    pub inflight: InFlight,
+    // Operations and bytes by requesting uid and process.
+    pub io: IoAccounts,
+    // Outcome of each FUSE capability at the last init, by name.
+    capabilities: Mutex<Vec<(&'static str, &'static str)>>,
+    // Set by the errors=remount-ro policy; cleared when the mount is re-established.
+    read_only: AtomicBool,
+    scrub: Mutex<ScrubReport>,
+    // The latest failed backend calls, oldest first.
+    errors: Mutex<VecDeque<BackendError>>,
+    // When the counters were last reset, or the mount was first registered.
+    // Counting holds it for reading and a reset for writing, so a reset
+    // sees every event, counters and io alike, entirely before or after it.
+    counters_since: RwLock<SystemTime>,
+    // Sizes of the mount's in-memory state, refreshed by its housekeeping.
+    pub inodes: AtomicU64,
+    pub cached_attrs: AtomicU64,
+    pub open_dirs: AtomicU64,
+}
+
+impl MountStats {
+    fn new(mount_point: &str, pool_id: u64) -> Self {
+        Self {
+            mount_point: mount_point.to_string(),
+            pool_id,
+            state: Mutex::new(MountState::Pending),
+            mount_failures: AtomicU64::new(0),
+            remounts: AtomicU64::new(0),
+            backend_timeouts: AtomicU64::new(0),
+            backend_retries: AtomicU64::new(0),
+            throttle_wait_us: AtomicU64::new(0),
+            handler_panics: AtomicU64::new(0),
+            disconnects: AtomicU64::new(0),
+            disconnected_since: Mutex::new(None),
+            stuck_ops: AtomicU64::new(0),
+            inflight: InFlight::default(),
+            io: IoAccounts::default(),
+            capabilities: Mutex::new(Vec::new()),
+            read_only: AtomicBool::new(false),
+            scrub: Mutex::new(ScrubReport::default()),
+            errors: Mutex::new(VecDeque::new()),
+            counters_since: RwLock::new(SystemTime::now()),
+            inodes: AtomicU64::new(0),
+            cached_attrs: AtomicU64::new(0),
+            open_dirs: AtomicU64::new(0),
+        }
+    }
+
+    pub fn state(&self) -> MountState {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub fn set_mounted(&self) {
+        self.read_only.store(false, Ordering::Relaxed);
+        *self.state.lock().unwrap() = MountState::Mounted;
+    }
+
+    // Adds `amount` to `counter`, one of this mount's counters, in the
+    // current counting interval.
+    pub fn count(&self, counter: &AtomicU64, amount: u64) {
+        let _interval = self.counters_since.read().unwrap();
+        counter.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    // Accounts I/O to the requester of `op` in the current counting interval.
+    pub fn record_io(&self, op: &InFlightOp, ops: u64, bytes_read: u64, bytes_written: u64) {
+        let _interval = self.counters_since.read().unwrap();
+        self.io.record(op, ops, bytes_read, bytes_written);
+    }
+
+    pub fn set_failed(&self, reason: &str) {
+        self.count(&self.mount_failures, 1);
+        *self.state.lock().unwrap() = MountState::Failed(reason.to_string());
+    }
+
+    pub fn set_retrying(&self, reason: &str) {
+        *self.state.lock().unwrap() = MountState::Retrying(reason.to_string());
+    }
+
+    // Returns false if the mount already was read-only.
+    pub fn set_read_only(&self) -> bool {
+        !self.read_only.swap(true, Ordering::Relaxed)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    // Marks the pool as unreachable. Returns false if it already was.
+    pub fn set_disconnected(&self) -> bool {
+        let mut since = self.disconnected_since.lock().unwrap();
+        if since.is_some() {
+            return false;
+        }
+        *since = Some(Instant::now());
+        self.count(&self.disconnects, 1);
+        true
+    }
+
+    // Marks the pool as reachable again. Returns how long it was down.
+    pub fn set_connected(&self) -> Option<Duration> {
+        self.disconnected_since.lock().unwrap().take().map(|since| since.elapsed())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.disconnected_since.lock().unwrap().is_none()
+    }
+
+    pub fn record_remount(&self) {
+        self.count(&self.remounts, 1);
+    }
+
+    pub fn set_capabilities(&self, capabilities: Vec<(&'static str, &'static str)>) {
+        *self.capabilities.lock().unwrap() = capabilities;
+    }
+
+    // Names of the capabilities enabled at the last init.
+    fn enabled_capabilities(&self) -> Vec<&'static str> {
+        let capabilities = self.capabilities.lock().unwrap();
+        capabilities.iter().filter(|(_, outcome)| *outcome == "enabled").map(|(name, _)| *name).collect()
+    }
+
+    pub fn scrub(&self) -> ScrubReport {
+        self.scrub.lock().unwrap().clone()
+    }
+
+    pub fn update_scrub(&self, update: impl FnOnce(&mut ScrubReport)) {
+        update(&mut self.scrub.lock().unwrap());
+    }
+
+    pub fn record_error(&self, op: Option<&'static str>, path: &str, errno: i32, message: String) {
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() >= RECENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(BackendError {
+            at: SystemTime::now(),
+            op,
+            path: path.to_string(),
+            errno,
+            message,
+        });
+    }
+
+    // Renders the operations and bytes of each uid, then of each process
+    // accounted separately, one per line.
+    pub fn render_io(&self) -> String {
+        self.io.render("", &self.io.uids(), &self.io.pids())
+    }
+
+    // Renders the recent backend errors, one per line, oldest first.
+    pub fn render_errors(&self) -> String {
+        self.errors.lock().unwrap().iter().map(|error| error.render() + "\n").collect()
+    }
+
+    fn counters(&self) -> [(&'static str, &AtomicU64); 8] {
+        [
+            ("mount_failures", &self.mount_failures),
+            ("remounts", &self.remounts),
+            ("backend_timeouts", &self.backend_timeouts),
+            ("backend_retries", &self.backend_retries),
+            ("throttle_wait_us", &self.throttle_wait_us),
+            ("handler_panics", &self.handler_panics),
+            ("disconnects", &self.disconnects),
+            ("stuck_ops", &self.stuck_ops),
+        ]
+    }
+
+    // Renders the state and counters of this mount as key=value lines.
+    pub fn render(&self) -> String {
+        let state = self.state();
+        let mut out = String::new();
+        let _ = writeln!(out, "mount_point={}", self.mount_point);
+        let _ = writeln!(out, "pool={}", self.pool_id);
+        let _ = writeln!(out, "state={}", state.name());
+        if let MountState::Failed(reason) | MountState::Retrying(reason) = state {
+            let _ = writeln!(out, "error={:?}", reason);
+        }
+        let _ = writeln!(out, "read_only={}", self.is_read_only());
+        let _ = writeln!(out, "connected={}", self.is_connected());
+        let _ = writeln!(out, "capabilities={}", self.enabled_capabilities().join(","));
+        let since = *self.counters_since.read().unwrap();
+        let _ = writeln!(out, "counters_since={}", format_utc(since));
+        for (name, counter) in self.counters() {
+            let _ = writeln!(out, "{}={}", name, counter.load(Ordering::Relaxed));
+        }
+        out
+    }
+
+    // The state, counters, cache sizes, executing operations, scrub and
+    // recent errors of this mount as one JSON object.
+    pub fn to_json(&self) -> Value {
+        let state = self.state();
+        let reason = match &state {
+            MountState::Failed(reason) | MountState::Retrying(reason) => Some(reason.clone()),
+            MountState::Pending | MountState::Mounted => None,
+        };
+        let counters: Map<String, Value> = self
+            .counters()
+            .iter()
+            .map(|(name, counter)| (name.to_string(), Value::from(counter.load(Ordering::Relaxed))))
+            .collect();
+        let ops: Vec<Value> = self
+            .inflight
+            .snapshot()
+            .iter()
+            .map(|op| {
+                json!({
+                    "op": op.op,
+                    "path": op.path.to_string_lossy(),
+                    "uid": op.uid,
+                    "gid": op.gid,
+                    "pid": op.pid,
+                    "exe": op.exe(),
+                    "age_ms": op.started.elapsed().as_millis() as u64,
+                })
+            })
+            .collect();
+        let errors: Vec<Value> = self.errors.lock().unwrap().iter().map(BackendError::to_json).collect();
+        let capabilities: Map<String, Value> = self
+            .capabilities
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, outcome)| (name.to_string(), Value::from(*outcome)))
+            .collect();
+        json!({
+            "mount_point": self.mount_point,
+            "pool": self.pool_id,
+            "state": state.name(),
+            "error": reason,
+            "read_only": self.is_read_only(),
+            "connected": self.is_connected(),
+            "capabilities": capabilities,
+            "counters_since": unix_secs(*self.counters_since.read().unwrap()),
+            "counters": counters,
+            "caches": {
+                "inodes": self.inodes.load(Ordering::Relaxed),
+                "attrs": self.cached_attrs.load(Ordering::Relaxed),
+                "open_dirs": self.open_dirs.load(Ordering::Relaxed),
+            },
+            "ops": ops,
+            "io": self.io.to_json(),
+            "scrub": self.scrub().to_json(),
+            "recent_errors": errors,
+        })
+    }
+
+    // Sets every counter back to 0 and renders the values they had, with
+    // the interval they were counted over. Counting waits while the reset
+    // runs, so the values describe exactly that interval.
+    pub fn reset_counters(&self) -> String {
+        let mut interval = self.counters_since.write().unwrap();
+        let now = SystemTime::now();
+        let since = std::mem::replace(&mut *interval, now);
+        let mut out = String::new();
+        let _ = writeln!(out, "mount_point={}", self.mount_point);
+        let _ = writeln!(out, "counters_since={}", format_utc(since));
+        let _ = writeln!(out, "counters_until={}", format_utc(now));
+        for (name, counter) in self.counters() {
+            let _ = writeln!(out, "{}={}", name, counter.swap(0, Ordering::Relaxed));
+        }
+        out.push_str(&self.io.reset());
+        out
+    }
+}
+
+// Shared registry of all mounts and pool breakers, read by the control socket.
+#[derive(Default)]
+pub struct Registry {
+    mounts: Mutex<BTreeMap<String, Arc<MountStats>>>,
+    breakers: Mutex<BTreeMap<u64, Arc<Breaker>>>,
+}
+
+impl Registry {
+    // Returns the stats for a mount point, creating them on first use.
+    pub fn register(&self, mount_point: &str, pool_id: u64) -> Arc<MountStats> {
+        let mut mounts = self.mounts.lock().unwrap();
+        Arc::clone(
+            mounts
+                .entry(mount_point.to_string())
+                .or_insert_with(|| Arc::new(MountStats::new(mount_point, pool_id))),
+        )
+    }
+
+    // Forgets a mount that was removed, so it is no longer reported.
+    pub fn unregister(&self, mount_point: &str) {
+        self.mounts.lock().unwrap().remove(mount_point);
+    }
+
+    pub fn mounts(&self) -> Vec<Arc<MountStats>> {
+        self.mounts.lock().unwrap().values().cloned().collect()
+    }
+
+    // Returns the circuit breaker of a pool, shared by all of its mounts.
+    pub fn breaker(&self, pool_id: u64, settings: &BackendSettings) -> Arc<Breaker> {
+        let mut breakers = self.breakers.lock().unwrap();
+        Arc::clone(breakers.entry(pool_id).or_insert_with(|| {
+            Arc::new(Breaker::new(pool_id, settings.breaker_failure_threshold, settings.breaker_open()))
+        }))
+    }
+
+    // Returns the circuit breaker of a pool if any mount created it.
+    pub fn pool_breaker(&self, pool_id: u64) -> Option<Arc<Breaker>> {
+        self.breakers.lock().unwrap().get(&pool_id).cloned()
+    }
+
+    fn breakers(&self) -> Vec<(u64, Arc<Breaker>)> {
+        self.breakers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(pool_id, breaker)| (*pool_id, Arc::clone(breaker)))
+            .collect()
+    }
+
+    // Renders one line per mount with its current state. Mounts whose pool
+    // breaker is not closed are degraded and report the breaker state.
+    pub fn render_status(&self) -> String {
+        let breakers: BTreeMap<u64, Arc<Breaker>> = self.breakers().into_iter().collect();
+        let mut out = String::new();
+        for mount in self.mounts() {
+            let state = mount.state();
+            let _ = write!(out, "{} pool={} state={}", mount.mount_point, mount.pool_id, state.name());
+            if mount.is_read_only() {
+                out.push_str(" read_only");
+            }
+            if let Some(breaker) = breakers.get(&mount.pool_id)
+                && !breaker.is_closed()
+            {
+                let _ = write!(out, " breaker={}", breaker.state_name());
+            }
+            if let MountState::Failed(reason) | MountState::Retrying(reason) = state {
+                let _ = write!(out, " error={:?}", reason);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    // Mounts at `mount_point`, or all of them for None. A mount point with
+    // no mount, most likely mistyped, is answered with an error rather than
+    // an empty response.
+    fn selected(&self, mount_point: Option<&str>) -> Result<Vec<Arc<MountStats>>, String> {
+        let mut mounts = self.mounts();
+        mounts.retain(|mount| mount_point.is_none_or(|mount_point| mount_point == mount.mount_point));
+        match mount_point {
+            Some(mount_point) if mounts.is_empty() => Err(format!("error: unknown mount point '{}'\n", mount_point)),
+            _ => Ok(mounts),
+        }
+    }
+
+    // Renders the state and counters of every mount, or of the one at
+    // `mount_point`, separated by blank lines.
+    pub fn render_stats(&self, mount_point: Option<&str>) -> String {
+        match self.selected(mount_point) {
+            Ok(mounts) => mounts.iter().map(|mount| mount.render()).collect::<Vec<_>>().join("\n"),
+            Err(e) => e,
+        }
+    }
+
+    // Resets the counters of every mount, or of the one at `mount_point`,
+    // rendering the values they had.
+    pub fn reset_counters(&self, mount_point: Option<&str>) -> String {
+        match self.selected(mount_point) {
+            Ok(mounts) => mounts.iter().map(|mount| mount.reset_counters()).collect::<Vec<_>>().join("\n"),
+            Err(e) => e,
+        }
+    }
+
+    // Renders every mount, or the one at `mount_point`, and the pools'
+    // breakers as one JSON document.
+    pub fn render_json(&self, mount_point: Option<&str>) -> String {
+        let mounts: Vec<Value> = match self.selected(mount_point) {
+            Ok(mounts) => mounts.iter().map(|mount| mount.to_json()).collect(),
+            Err(e) => return e,
+        };
+        let pools: Vec<Value> = self
+            .breakers()
+            .iter()
+            .map(|(pool_id, breaker)| {
+                json!({
+                    "pool": pool_id,
+                    "breaker": breaker.state_name(),
+                    "breaker_trips": breaker.trips.load(Ordering::Relaxed),
+                })
+            })
+            .collect();
+        json!({ "mounts": mounts, "pools": pools }).to_string() + "\n"
+    }
+
+    // Renders the I/O by uid and process of every mount, or of the one at
+    // `mount_point`, each line starting with the mount point.
+    pub fn render_io(&self, mount_point: Option<&str>) -> String {
+        let mounts = match self.selected(mount_point) {
+            Ok(mounts) => mounts,
+            Err(e) => return e,
+        };
+        let mut out = String::new();
+        for mount in mounts {
+            let prefix = format!("{} ", mount.mount_point);
+            out.push_str(&mount.io.render(&prefix, &mount.io.uids(), &mount.io.pids()));
+        }
+        out
+    }
+
+    // Renders the recent backend errors of every mount, or of the one at
+    // `mount_point`, each line starting with the mount point.
+    pub fn render_errors(&self, mount_point: Option<&str>) -> String {
+        let mounts = match self.selected(mount_point) {
+            Ok(mounts) => mounts,
+            Err(e) => return e,
+        };
+        let mut out = String::new();
+        for mount in mounts {
+            for error in mount.errors.lock().unwrap().iter() {
+                let _ = writeln!(out, "{} {}", mount.mount_point, error.render());
+            }
+        }
+        out
+    }
+
+    // Renders one line per FUSE operation currently executing, oldest first
+    // within each mount.
+    pub fn render_ops(&self) -> String {
+        let mut out = String::new();
+        for mount in self.mounts() {
+            for op in mount.inflight.snapshot() {
+                let _ = writeln!(
+                    out,
+                    "{} op={} path={:?} uid={} gid={} pid={} exe={} age_ms={}",
+                    mount.mount_point,
+                    op.op,
+                    op.path,
+                    op.uid,
+                    op.gid,
+                    op.pid,
+                    op.exe().unwrap_or("-"),
+                    op.started.elapsed().as_millis()
+                );
+            }
+        }
+        out
+    }
+
+    // Renders all counters in the Prometheus text exposition format.
+    pub fn render_metrics(&self) -> String {
+        let mounts = self.mounts();
+        let mut out = String::new();
+
+        out.push_str("# TYPE rfs_fuse_mount_up gauge\n");
+        for mount in &mounts {
+            let up = matches!(mount.state(), MountState::Mounted) as u8;
+            let _ = writeln!(out, "rfs_fuse_mount_up{{{}}} {}", labels(mount), up);
+        }
+
+        out.push_str("# TYPE rfs_fuse_mount_read_only gauge\n");
+        for mount in &mounts {
+            let _ = writeln!(out, "rfs_fuse_mount_read_only{{{}}} {}", labels(mount), mount.is_read_only() as u8);
+        }
+
+        out.push_str("# TYPE rfs_fuse_mount_failures_total counter\n");
+        for mount in &mounts {
+            let _ = writeln!(
+                out,
+                "rfs_fuse_mount_failures_total{{{}}} {}",
+                labels(mount),
+                mount.mount_failures.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# TYPE rfs_fuse_remounts_total counter\n");
+        for mount in &mounts {
+            let _ = writeln!(
+                out,
+                "rfs_fuse_remounts_total{{{}}} {}",
+                labels(mount),
+                mount.remounts.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# TYPE rfs_fuse_backend_timeouts_total counter\n");
+        for mount in &mounts {
+            let _ = writeln!(
+                out,
+                "rfs_fuse_backend_timeouts_total{{{}}} {}",
+                labels(mount),
+                mount.backend_timeouts.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# TYPE rfs_fuse_backend_retries_total counter\n");
+        for mount in &mounts {
+            let _ = writeln!(
+                out,
+                "rfs_fuse_backend_retries_total{{{}}} {}",
+                labels(mount),
+                mount.backend_retries.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# TYPE rfs_fuse_throttle_wait_seconds_total counter\n");
+        for mount in &mounts {
+            let waited = mount.throttle_wait_us.load(Ordering::Relaxed) as f64 / 1e6;
+            let _ = writeln!(out, "rfs_fuse_throttle_wait_seconds_total{{{}}} {}", labels(mount), waited);
+        }
+
+        out.push_str("# TYPE rfs_fuse_handler_panics_total counter\n");
+        for mount in &mounts {
+            let _ = writeln!(
+                out,
+                "rfs_fuse_handler_panics_total{{{}}} {}",
+                labels(mount),
+                mount.handler_panics.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# TYPE rfs_fuse_backend_connected gauge\n");
+        for mount in &mounts {
+            let _ = writeln!(out, "rfs_fuse_backend_connected{{{}}} {}", labels(mount), mount.is_connected() as u8);
+        }
+
+        out.push_str("# TYPE rfs_fuse_backend_disconnects_total counter\n");
+        for mount in &mounts {
+            let _ = writeln!(
+                out,
+                "rfs_fuse_backend_disconnects_total{{{}}} {}",
+                labels(mount),
+                mount.disconnects.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# TYPE rfs_fuse_stuck_ops_total counter\n");
+        for mount in &mounts {
+            let _ = writeln!(
+                out,
+                "rfs_fuse_stuck_ops_total{{{}}} {}",
+                labels(mount),
+                mount.stuck_ops.load(Ordering::Relaxed)
+            );
+        }
+
+        let io: Vec<_> = mounts.iter().map(|mount| (mount, mount.io.uids())).collect();
+        let per_uid = ["rfs_fuse_uid_ops_total", "rfs_fuse_uid_read_bytes_total", "rfs_fuse_uid_written_bytes_total"];
+        for (index, name) in per_uid.iter().enumerate() {
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (mount, uids) in &io {
+                for (uid, counts) in uids {
+                    let value = [counts.ops, counts.bytes_read, counts.bytes_written][index];
+                    let _ = writeln!(out, "{}{{{},uid=\"{}\"}} {}", name, labels(mount), uid, value);
+                }
+            }
+        }
+
+        out.push_str("# TYPE rfs_fuse_scrub_runs_total counter\n");
+        for mount in &mounts {
+            let _ = writeln!(out, "rfs_fuse_scrub_runs_total{{{}}} {}", labels(mount), mount.scrub().runs);
+        }
+
+        // Problems found by the current run, or by the last one while idle.
+        out.push_str("# TYPE rfs_fuse_scrub_errors gauge\n");
+        for mount in &mounts {
+            let _ = writeln!(out, "rfs_fuse_scrub_errors{{{}}} {}", labels(mount), mount.scrub().errors);
+        }
+
+        out.push_str("# TYPE rfs_fuse_scrub_last_completion_timestamp_seconds gauge\n");
+        for mount in &mounts {
+            if let Some(finished_at) = mount.scrub().finished_at {
+                let secs = finished_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                let _ = writeln!(out, "rfs_fuse_scrub_last_completion_timestamp_seconds{{{}}} {}", labels(mount), secs);
+            }
+        }
+
+        let breakers = self.breakers();
+        out.push_str("# TYPE rfs_fuse_pool_breaker_open gauge\n");
+        for (pool_id, breaker) in &breakers {
+            let open = !breaker.is_closed() as u8;
+            let _ = writeln!(out, "rfs_fuse_pool_breaker_open{{pool=\"{}\"}} {}", pool_id, open);
+        }
+
+        out.push_str("# TYPE rfs_fuse_pool_breaker_trips_total counter\n");
+        for (pool_id, breaker) in &breakers {
+            let _ = writeln!(
+                out,
+                "rfs_fuse_pool_breaker_trips_total{{pool=\"{}\"}} {}",
+                pool_id,
+                breaker.trips.load(Ordering::Relaxed)
+            );
+        }
+        out
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+fn labels(mount: &MountStats) -> String {
+    format!("mount=\"{}\",pool=\"{}\"", label_value(&mount.mount_point), mount.pool_id)
+}
+
+// A label value escaped as the Prometheus text format requires: backslashes,
+// double quotes and line feeds only, so non-ASCII names are kept as they are.
+fn label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{label_value, IoAccounts, Registry};
+    use crate::inflight::InFlight;
+    use std::path::PathBuf;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn the_threads_of_a_process_are_accounted_to_the_process() {
+        let accounts = IoAccounts::default();
+        accounts.set_per_pid(true);
+        let inflight = InFlight::default();
+        // FUSE reports a request of this thread with its thread id.
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let running = std::thread::spawn(move || {
+            tx.send(unsafe { libc::gettid() } as u32).unwrap();
+            let _ = done_rx.recv();
+        });
+        let tid = rx.recv().unwrap();
+        for pid in [tid, tid, std::process::id()] {
+            let guard = inflight.begin("getattr", PathBuf::from("/"), (1000, 1000, pid));
+            accounts.record(guard.op(), 1, 0, 0);
+        }
+        done_tx.send(()).unwrap();
+        running.join().unwrap();
+        assert_ne!(tid, std::process::id());
+
+        let pids = accounts.pids();
+        assert_eq!(pids.keys().copied().collect::<Vec<_>>(), [std::process::id()]);
+        assert_eq!(pids[&std::process::id()].counts.ops, 3);
+    }
+
+    #[test]
+    fn metric_labels_escape_only_what_the_text_format_requires() {
+        assert_eq!(label_value("/mnt/Café \"a\"\\b\nc\t"), "/mnt/Café \\\"a\\\"\\\\b\\nc\t");
+        let registry = Registry::default();
+        registry.register("/mnt/Fotos é", 1);
+        assert!(registry.render_metrics().contains("{mount=\"/mnt/Fotos é\",pool=\"1\""));
+    }
+
+    #[test]
+    fn reset_returns_what_was_counted_since_the_last_one() {
+        let registry = Registry::default();
+        let stats = registry.register("/mnt/a", 1);
+        registry.register("/mnt/b", 2).backend_retries.fetch_add(7, Ordering::Relaxed);
+        stats.backend_retries.fetch_add(3, Ordering::Relaxed);
+
+        let reset = registry.reset_counters(Some("/mnt/a"));
+        assert!(reset.contains("backend_retries=3\n"), "{}", reset);
+        assert!(!reset.contains("/mnt/b"));
+        assert!(stats.render().contains("backend_retries=0\n"));
+        assert!(registry.render_stats(Some("/mnt/b")).contains("backend_retries=7\n"));
+        assert_eq!(registry.reset_counters(Some("/mnt/c")), "error: unknown mount point '/mnt/c'\n");
+        assert_eq!(registry.render_stats(Some("/mnt/c")), "error: unknown mount point '/mnt/c'\n");
+    }
+
+    #[test]
+    fn a_reset_splits_no_event_between_intervals() {
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Arc;
+
+        let stats = Registry::default().register("/mnt/a", 1);
+        stats.io.set_per_pid(true);
+        let done = Arc::new(AtomicBool::new(false));
+        let recorder = {
+            let (stats, done) = (Arc::clone(&stats), Arc::clone(&done));
+            std::thread::spawn(move || {
+                let inflight = InFlight::default();
+                let mut events = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let guard = inflight.begin("read", PathBuf::from("/f"), (1000, 1000, std::process::id()));
+                    stats.record_io(guard.op(), 1, 1, 0);
+                    stats.count(&stats.backend_retries, 1);
+                    events += 1;
+                }
+                events
+            })
+        };
+        // Sums the ops= of the io lines of a reset starting with `prefix`.
+        let ops = |reset: &str, prefix: &str| -> u64 {
+            reset
+                .lines()
+                .filter(|line| line.starts_with(prefix))
+                .filter_map(|line| line.split(' ').find_map(|field| field.strip_prefix("ops=")?.parse::<u64>().ok()))
+                .sum()
+        };
+        let mut counted = 0;
+        for round in 0..20_000 {
+            if round == 19_999 {
+                done.store(true, Ordering::Relaxed);
+            }
+            let reset = stats.reset_counters();
+            // Every event is in this interval or the next, with both its
+            // uid and its process.
+            assert_eq!(ops(&reset, "io uid="), ops(&reset, "io pid="), "{}", reset);
+            counted += ops(&reset, "io uid=");
+        }
+        let events = recorder.join().unwrap();
+        counted += ops(&stats.reset_counters(), "io uid=");
+        assert_eq!(counted, events);
+    }
+
+    #[test]
+    fn json_stats_nest_mounts_and_pools() {
+        let registry = Registry::default();
+        let stats = registry.register("/mnt/a", 1);
+        registry.breaker(1, &Default::default());
+        stats.record_error(Some("lookup"), "/docs", libc::EIO, "pool offline".to_string());
+
+        let document: serde_json::Value = serde_json::from_str(&registry.render_json(None)).unwrap();
+        let mount = &document["mounts"][0];
+        assert_eq!(mount["mount_point"], "/mnt/a");
+        assert_eq!(mount["counters"]["backend_retries"], 0);
+        assert_eq!(mount["caches"]["inodes"], 0);
+        assert_eq!(mount["recent_errors"][0]["error"], "pool offline");
+        assert_eq!(document["pools"][0]["breaker"], "closed");
+    }
+}
@@ -0,0 +1,149 @@
+// src/store.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::error::errno_of;
+use nix::sys::statvfs::statvfs;
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + 'a>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+// Metadata of a pool entry as the FUSE layer uses it.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub kind: EntryKind,
+    pub size: u64,
+    pub modified_at: SystemTime,
+}
+
+impl From<&librfs::model::Entry> for Entry {
+    fn from(entry: &librfs::model::Entry) -> Self {
+        match entry {
+            librfs::model::Entry::File(f) => Entry {
+                kind: EntryKind::File,
+                size: f.size,
+                modified_at: f.modified_at.into(),
+            },
+            librfs::model::Entry::Directory(d) => Entry {
+                kind: EntryKind::Directory,
+                size: d.size,
+                modified_at: d.modified_at.into(),
+            },
+        }
+    }
+}
+
+// Capacity of the filesystem holding a pool, before any reserve is applied.
+pub struct Capacity {
+    pub blocks: u64,
+    pub blocks_free: u64,
+    pub blocks_available: u64,
+    pub files: u64,
+    pub files_free: u64,
+    pub block_size: u32,
+    pub fragment_size: u32,
+    pub name_max: u32,
+}
+
+// Where a Backend reads a pool from. Backend adds timeouts, retries and the
+// circuit breaker on top, so implementations make single attempts.
+pub trait Store: Send + Sync {
+    fn list_directory<'a>(&'a self, path: &'a str) -> StoreFuture<'a, HashMap<String, Entry>>;
+
+    fn capacity(&self) -> io::Result<Capacity>;
+}
+
+// A directory of a pool presented as the root of a store.
+pub struct Subtree {
+    store: Arc<dyn Store>,
+    // Path of the directory in the pool, without a trailing slash.
+    root: String,
+}
+
+impl Subtree {
+    pub fn new(store: Arc<dyn Store>, root: &str) -> Self {
+        Self {
+            store,
+            root: root.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl Store for Subtree {
+    fn list_directory<'a>(&'a self, path: &'a str) -> StoreFuture<'a, HashMap<String, Entry>> {
+        Box::pin(async move {
+            let path = if path == "/" { self.root.clone() } else { format!("{}{}", self.root, path) };
+            self.store.list_directory(&path).await
+        })
+    }
+
+    fn capacity(&self) -> io::Result<Capacity> {
+        self.store.capacity()
+    }
+}
+
+// A pool read through librfs.
+pub struct Librfs {
+    pool_root: String,
+}
+
+impl Librfs {
+    pub fn new(pool_root: String) -> Self {
+        Self { pool_root }
+    }
+}
+
+impl Store for Librfs {
+    fn list_directory<'a>(&'a self, path: &'a str) -> StoreFuture<'a, HashMap<String, Entry>> {
+        Box::pin(async move {
+            let listing = librfs::list_directory(&self.pool_root, path)
+                .await
+                .map_err(|e| io::Error::from_raw_os_error(errno_of(&e)))?;
+            Ok(listing.iter().map(|(name, entry)| (name.clone(), Entry::from(entry))).collect())
+        })
+    }
+
+    // The statvfs counters are 32-bit on some platforms.
+    #[allow(clippy::useless_conversion)]
+    fn capacity(&self) -> io::Result<Capacity> {
+        let stat = statvfs(self.pool_root.as_str()).map_err(io::Error::from)?;
+        Ok(Capacity {
+            blocks: stat.blocks().into(),
+            blocks_free: stat.blocks_free().into(),
+            blocks_available: stat.blocks_available().into(),
+            files: stat.files().into(),
+            files_free: stat.files_free().into(),
+            block_size: u32::try_from(stat.block_size()).unwrap_or(u32::MAX),
+            fragment_size: u32::try_from(stat.fragment_size()).unwrap_or(u32::MAX),
+            name_max: u32::try_from(stat.name_max()).unwrap_or(255),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Store, Subtree};
+    use crate::testing::MemoryStore;
+    use std::sync::Arc;
+
+    #[test]
+    fn subtree_lists_its_directory_as_the_root() {
+        let pool = MemoryStore::default().file("/volumes/a/data.bin", 7).dir("/volumes/b");
+        let subtree = Subtree::new(Arc::new(pool), "/volumes/a/");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let root = runtime.block_on(subtree.list_directory("/")).unwrap();
+        assert_eq!(root.keys().collect::<Vec<_>>(), ["data.bin"]);
+        let err = runtime.block_on(subtree.list_directory("/missing")).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+    }
+}
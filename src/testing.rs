@@ -0,0 +1,271 @@
+// src/testing.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+// Test support: an in-memory pool and a harness that mounts it through the
+// real FUSE stack, so tests can exercise the handlers with std::fs calls.
+
+use crate::backend::Backend;
+use crate::config::{BackendSettings, MountMethod, MountOptions};
+use crate::fs::{PoolView, RfsFuse};
+use crate::fuse_mount::{self, Access};
+use crate::home::homes_dir;
+use crate::stats::{MountStats, Registry};
+use crate::store::{Capacity, Entry, EntryKind, Store, StoreFuture};
+use crate::unmount::detach;
+use fuser::BackgroundSession;
+use nix::unistd::geteuid;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::runtime::Runtime;
+
+// A pool held in memory, keyed by directory path.
+pub struct MemoryStore {
+    dirs: Mutex<HashMap<String, HashMap<String, Entry>>>,
+    // How often each directory was listed.
+    listings: Mutex<HashMap<String, u64>>,
+    // Directories whose listing fails, with the errno it fails with.
+    failures: HashMap<String, i32>,
+    // How long reading the capacity blocks, like statvfs on a hung share.
+    capacity_stall: Duration,
+}
+
+impl Default for MemoryStore {
+    // An empty pool with only the root directory.
+    fn default() -> Self {
+        let mut dirs = HashMap::new();
+        dirs.insert("/".to_string(), HashMap::new());
+        Self {
+            dirs: Mutex::new(dirs),
+            listings: Mutex::new(HashMap::new()),
+            failures: HashMap::new(),
+            capacity_stall: Duration::ZERO,
+        }
+    }
+}
+
+impl MemoryStore {
+    // Adds a directory, creating missing parents.
+    pub fn dir(self, path: &str) -> Self {
+        self.insert(path, EntryKind::Directory, 0);
+        self
+    }
+
+    // Adds a file of `size` bytes, creating missing parents.
+    pub fn file(self, path: &str, size: u64) -> Self {
+        self.insert(path, EntryKind::File, size);
+        self
+    }
+
+    // Makes listing the directory `path` fail with `errno`.
+    pub fn failing(mut self, path: &str, errno: i32) -> Self {
+        self.failures.insert(path.to_string(), errno);
+        self
+    }
+
+    // Makes reading the capacity block for `stall`.
+    pub fn stalling_capacity(mut self, stall: Duration) -> Self {
+        self.capacity_stall = stall;
+        self
+    }
+
+    // Removes an entry and everything below it, as another client of the
+    // pool would.
+    pub fn remove(&self, path: &str) {
+        let (parent, name) = split(path);
+        let mut dirs = self.dirs.lock().unwrap();
+        if let Some(listing) = dirs.get_mut(parent) {
+            listing.remove(name);
+        }
+        let below = format!("{}/", path);
+        dirs.retain(|dir, _| dir != path && !dir.starts_with(&below));
+    }
+
+    // Number of times the backend listed `path`.
+    pub fn listings(&self, path: &str) -> u64 {
+        self.listings.lock().unwrap().get(path).copied().unwrap_or(0)
+    }
+
+    fn insert(&self, path: &str, kind: EntryKind, size: u64) {
+        let (parent, name) = split(path);
+        if parent != "/" && !self.dirs.lock().unwrap().contains_key(parent) {
+            self.insert(parent, EntryKind::Directory, 0);
+        }
+        let mut dirs = self.dirs.lock().unwrap();
+        let entry = Entry {
+            kind,
+            size,
+            modified_at: SystemTime::now(),
+        };
+        dirs.entry(parent.to_string()).or_default().insert(name.to_string(), entry);
+        if kind == EntryKind::Directory {
+            dirs.entry(path.to_string()).or_default();
+        }
+    }
+}
+
+fn split(path: &str) -> (&str, &str) {
+    match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+        None => ("/", path),
+    }
+}
+
+impl Store for MemoryStore {
+    fn list_directory<'a>(&'a self, path: &'a str) -> StoreFuture<'a, HashMap<String, Entry>> {
+        let listing = self.dirs.lock().unwrap().get(path).cloned();
+        *self.listings.lock().unwrap().entry(path.to_string()).or_default() += 1;
+        let failure = self.failures.get(path).copied();
+        Box::pin(async move {
+            if let Some(errno) = failure {
+                return Err(io::Error::from_raw_os_error(errno));
+            }
+            listing.ok_or_else(|| io::Error::from_raw_os_error(libc::ENOENT))
+        })
+    }
+
+    fn capacity(&self) -> io::Result<Capacity> {
+        std::thread::sleep(self.capacity_stall);
+        Ok(Capacity {
+            blocks: 1 << 20,
+            blocks_free: 1 << 19,
+            blocks_available: 1 << 19,
+            files: 1 << 16,
+            files_free: 1 << 15,
+            block_size: 4096,
+            fragment_size: 4096,
+            name_max: 255,
+        })
+    }
+}
+
+// Notes that a test cannot mount FUSE at `mount_point` and skips it. CI sets
+// RFS_REQUIRE_FUSE so that mounted tests cannot pass without running.
+pub fn skip_unmounted(mount_point: &Path, error: &dyn std::fmt::Display) {
+    if std::env::var_os("RFS_REQUIRE_FUSE").is_some_and(|value| !value.is_empty()) {
+        panic!("cannot mount FUSE at {} with RFS_REQUIRE_FUSE set: {}", mount_point.display(), error);
+    }
+    eprintln!("skipping: cannot mount FUSE at {}: {}", mount_point.display(), error);
+}
+
+// Whether tests can make requests as another user, which takes root's
+// setfsuid. Prints a note when they cannot.
+pub fn can_act_as_other_users() -> bool {
+    let root = geteuid().is_root();
+    if !root {
+        eprintln!("skipping: acting as another user needs root");
+    }
+    root
+}
+
+// A MemoryStore mounted at a temporary directory. Dropping it unmounts the
+// store and removes the directory.
+pub struct TestMount {
+    pub root: PathBuf,
+    pub stats: Arc<MountStats>,
+    // The mounted store, for changing the pool behind the kernel's back.
+    pub store: Arc<MemoryStore>,
+    view: Arc<PoolView>,
+    session: Option<BackgroundSession>,
+    // Mounted with mount(2) rather than through fusermount, so it has to be
+    // unmounted before the session is dropped.
+    direct: bool,
+    // Backend calls block on this runtime, so it must outlive the session.
+    _runtime: Runtime,
+}
+
+impl TestMount {
+    // Returns None, after saying so on stderr, where FUSE is not available
+    // (no /dev/fuse or no permission to mount), so tests can skip.
+    pub fn new(store: MemoryStore, options: MountOptions) -> Option<Self> {
+        // Like the daemon, a mount with per-user homes shows the directory holding them.
+        let root = homes_dir(&options.home).unwrap_or("/").to_string();
+        Self::mount(Arc::new(store), &root, options, Arc::default(), false)
+    }
+
+    // Like `new`, mounted with mount(2) as with [fuse] mount_method = "direct".
+    pub fn direct(store: MemoryStore, options: MountOptions) -> Option<Self> {
+        Self::mount(Arc::new(store), "/", options, Arc::default(), true)
+    }
+
+    // A second mount of the store of `other`, sharing its view of the pool
+    // like mounts of the same pool in the daemon.
+    pub fn sharing(other: &TestMount, options: MountOptions) -> Option<Self> {
+        Self::mount(Arc::clone(&other.store), "/", options, Arc::clone(&other.view), false)
+    }
+
+    // A mount of the directory `subpath` of the store of `other`, sharing
+    // its cached entries like a view in the daemon.
+    pub fn view_of(other: &TestMount, subpath: &str, options: MountOptions) -> Option<Self> {
+        let view = PoolView::new(subpath, Arc::clone(other.view.entries()));
+        Self::mount(Arc::clone(&other.store), subpath, options, Arc::new(view), false)
+    }
+
+    fn mount(
+        store: Arc<MemoryStore>,
+        subpath: &str,
+        options: MountOptions,
+        view: Arc<PoolView>,
+        direct: bool,
+    ) -> Option<Self> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "rfs-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&root).expect("creating the mount point");
+
+        let runtime = Runtime::new().expect("starting the tokio runtime");
+        let _entered = runtime.enter();
+        let registry = Registry::default();
+        let stats = registry.register(&root.to_string_lossy(), 0);
+        let settings = BackendSettings::default();
+        let breaker = registry.breaker(0, &settings);
+        let backend = Backend::from_store(Arc::clone(&store) as Arc<dyn Store>, settings, Arc::clone(&stats), breaker)
+            .within(subpath);
+        // Mounted as the daemon mounts, with the same options and session.
+        let access = Access::of(&options, false);
+        // Without a fusermount helper the daemon mounts with mount(2) too.
+        let direct = direct || !fuse_mount::helper_installed();
+        let method = if direct { MountMethod::Direct } else { MountMethod::Fusermount };
+        let fs = RfsFuse::new(backend, root.to_string_lossy().into_owned(), options, Arc::clone(&view));
+        let session = fuse_mount::mount(fs, &root, method, access);
+        match session {
+            Ok(session) => Some(Self {
+                root,
+                stats,
+                store,
+                view,
+                session: Some(session),
+                direct,
+                _runtime: runtime,
+            }),
+            Err(e) => {
+                skip_unmounted(&root, &e);
+                let _ = std::fs::remove_dir(&root);
+                None
+            }
+        }
+    }
+
+    // Path of `relative`, such as "/docs/readme.txt", inside the mount.
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(Path::new(relative.trim_start_matches('/')))
+    }
+}
+
+impl Drop for TestMount {
+    fn drop(&mut self) {
+        if self.direct {
+            let _ = detach(&self.root);
+        }
+        drop(self.session.take());
+        let _ = std::fs::remove_dir(&self.root);
+    }
+}
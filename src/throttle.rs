@@ -0,0 +1,96 @@
+// src/throttle.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::clock::{Clock, SystemClock};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Token bucket refilled at `rate` tokens per second, holding at most one
+// second worth of tokens. Callers that find it empty reserve their tokens
+// anyway and sleep until the reservation is covered, so waiters are served in
+// arrival order.
+pub struct TokenBucket {
+    rate: f64,
+    clock: Arc<dyn Clock>,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    // Negative while callers are waiting for reserved tokens.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32) -> Self {
+        Self::with_clock(rate, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(rate: u32, clock: Arc<dyn Clock>) -> Self {
+        let rate = f64::from(rate.max(1));
+        Self {
+            rate,
+            state: Mutex::new(BucketState {
+                tokens: rate,
+                refilled_at: clock.now(),
+            }),
+            clock,
+        }
+    }
+
+    // Takes `cost` tokens, blocking the calling thread until they are
+    // available. Returns how long the caller was held back.
+    pub fn acquire(&self, cost: f64) -> Duration {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = self.clock.now();
+            let refill = now.duration_since(state.refilled_at).as_secs_f64() * self.rate;
+            state.tokens = (state.tokens + refill).min(self.rate);
+            state.refilled_at = now;
+            state.tokens -= cost;
+            if state.tokens >= 0.0 {
+                Duration::ZERO
+            } else {
+                Duration::from_secs_f64(-state.tokens / self.rate)
+            }
+        };
+        if !wait.is_zero() {
+            self.clock.sleep(wait);
+        }
+        wait
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenBucket;
+    use crate::clock::SimClock;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn bursts_up_to_the_rate_then_waits() {
+        let clock = Arc::new(SimClock::new());
+        let bucket = TokenBucket::with_clock(10, clock.clone());
+        for _ in 0..10 {
+            assert_eq!(bucket.acquire(1.0), Duration::ZERO);
+        }
+        assert_eq!(bucket.acquire(1.0), Duration::from_millis(100));
+        assert_eq!(bucket.acquire(1.0), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn refills_while_idle_up_to_one_second() {
+        let clock = Arc::new(SimClock::new());
+        let bucket = TokenBucket::with_clock(10, clock.clone());
+        for _ in 0..10 {
+            bucket.acquire(1.0);
+        }
+        clock.advance(Duration::from_secs(5));
+        for _ in 0..10 {
+            assert_eq!(bucket.acquire(1.0), Duration::ZERO);
+        }
+        assert!(!bucket.acquire(1.0).is_zero());
+    }
+}
@@ -0,0 +1,194 @@
+// src/unmount.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::config::ShutdownSettings;
+use crate::logging::{log, Subsystem};
+use fuser::BackgroundSession;
+use rfs_utils::LogLevel;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Instant;
+
+// A process that still references something below a mount point.
+pub struct BusyHolder {
+    pub pid: u32,
+    pub command: String,
+    pub path: PathBuf,
+}
+
+// Unmounts every session concurrently, so the whole shutdown is bounded by a
+// single unmount timeout rather than one timeout per mount.
+pub async fn unmount_all(sessions: Vec<(String, BackgroundSession)>, settings: &ShutdownSettings) {
+    let handles: Vec<_> = sessions
+        .into_iter()
+        .map(|(mount_point, session)| {
+            let settings = settings.clone();
+            tokio::task::spawn_blocking(move || {
+                unmount_session(Path::new(&mount_point), session, &settings)
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        if let Err(e) = handle.await {
+            log(Subsystem::Mount, LogLevel::Error, &format!("Unmount task failed: {}", e));
+        }
+    }
+}
+
+// Unmounts a single session, retrying while the mount point is busy and
+// falling back to a lazy unmount once the configured timeout has expired.
+pub fn unmount_session(mount_point: &Path, session: BackgroundSession, settings: &ShutdownSettings) {
+    let deadline = Instant::now() + settings.unmount_timeout();
+    loop {
+        match unmount(mount_point, false) {
+            Ok(()) => {
+                log(Subsystem::Mount, LogLevel::Info, &format!("Unmounted {}", mount_point.display()));
+                break;
+            }
+            // The mount is already gone, e.g. removed by an administrator.
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EINVAL) | Some(libc::ENOENT)) => break,
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) && Instant::now() < deadline => {
+                thread::sleep(settings.retry_interval());
+            }
+            Err(e) => {
+                log(
+                    Subsystem::Mount,
+                    LogLevel::Warn,
+                    &format!("Unmount of {} did not complete: {}", mount_point.display(), e),
+                );
+                report_busy_holders(mount_point);
+                match unmount(mount_point, true) {
+                    Ok(()) => log(
+                        Subsystem::Mount,
+                        LogLevel::Warn,
+                        &format!("Lazily detached {}; open files keep it alive until closed", mount_point.display()),
+                    ),
+                    Err(e) => log(
+                        Subsystem::Mount,
+                        LogLevel::Error,
+                        &format!("Lazy unmount of {} failed: {}", mount_point.display(), e),
+                    ),
+                }
+                break;
+            }
+        }
+    }
+    // Dropping the session releases fuser's own mount handle. The session
+    // thread stops by itself once the kernel closes the connection.
+    drop(session);
+}
+
+// Lazily detaches a mount whose FUSE connection is already gone.
+pub fn detach(mount_point: &Path) -> io::Result<()> {
+    unmount(mount_point, true)
+}
+
+// Logs every process that still keeps the mount point busy.
+fn report_busy_holders(mount_point: &Path) {
+    let holders = busy_holders(mount_point);
+    if holders.is_empty() {
+        log(
+            Subsystem::Mount,
+            LogLevel::Warn,
+            &format!("No process holding {} could be identified", mount_point.display()),
+        );
+    }
+    for holder in holders {
+        log(
+            Subsystem::Mount,
+            LogLevel::Warn,
+            &format!(
+                "{} is busy: pid {} ({}) holds {}",
+                mount_point.display(),
+                holder.pid,
+                holder.command,
+                holder.path.display()
+            ),
+        );
+    }
+}
+
+// Scans /proc for processes whose working directory, root, executable or
+// open file descriptors point below the mount point.
+pub fn busy_holders(mount_point: &Path) -> Vec<BusyHolder> {
+    let mut holders = Vec::new();
+    let Ok(procs) = fs::read_dir("/proc") else {
+        return holders;
+    };
+
+    for proc_entry in procs.flatten() {
+        let Some(pid) = proc_entry.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) else {
+            continue;
+        };
+        let proc_dir = proc_entry.path();
+        let mut links: Vec<PathBuf> = ["cwd", "root", "exe"].iter().map(|l| proc_dir.join(l)).collect();
+        if let Ok(fds) = fs::read_dir(proc_dir.join("fd")) {
+            links.extend(fds.flatten().map(|fd| fd.path()));
+        }
+
+        for link in links {
+            if let Ok(target) = fs::read_link(&link)
+                && target.starts_with(mount_point)
+            {
+                holders.push(BusyHolder {
+                    pid,
+                    command: command_name(&proc_dir),
+                    path: target,
+                });
+            }
+        }
+    }
+    holders
+}
+
+fn command_name(proc_dir: &Path) -> String {
+    fs::read_to_string(proc_dir.join("comm"))
+        .map(|comm| comm.trim_end().to_string())
+        .unwrap_or_else(|_| "?".to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn unmount(mount_point: &Path, lazy: bool) -> io::Result<()> {
+    use nix::errno::Errno;
+    use nix::mount::{umount2, MntFlags};
+
+    let flags = if lazy { MntFlags::MNT_DETACH } else { MntFlags::empty() };
+    match umount2(mount_point, flags) {
+        // Unprivileged processes have to go through the setuid fusermount helper.
+        Err(Errno::EPERM) => fusermount_unmount(mount_point, lazy),
+        result => result.map_err(io::Error::from),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unmount(mount_point: &Path, lazy: bool) -> io::Result<()> {
+    use nix::mount::{unmount, MntFlags};
+
+    let flags = if lazy { MntFlags::MNT_FORCE } else { MntFlags::empty() };
+    unmount(mount_point, flags).map_err(io::Error::from)
+}
+
+#[cfg(target_os = "linux")]
+fn fusermount_unmount(mount_point: &Path, lazy: bool) -> io::Result<()> {
+    use std::process::Command;
+
+    let mut last_err = io::Error::from(io::ErrorKind::NotFound);
+    for helper in ["fusermount3", "fusermount"] {
+        let mut cmd = Command::new(helper);
+        cmd.arg("-u").arg("-q");
+        if lazy {
+            cmd.arg("-z");
+        }
+        match cmd.arg(mount_point).status() {
+            Ok(status) if status.success() => return Ok(()),
+            // fusermount does not report the errno, so treat any failure as busy.
+            Ok(_) => return Err(io::Error::from_raw_os_error(libc::EBUSY)),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
@@ -0,0 +1,80 @@
+// src/user_dirs.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+// Default locations of the daemon's files. Run by root they are the
+// system-wide ones; run by another user, e.g. a developer mounting their own
+// pools without sudo, they are in that user's XDG base directories.
+
+use nix::unistd::{geteuid, User};
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+const SYSTEM_CONFIG_DIR: &str = "/opt/rfs/rfsd";
+pub const SYSTEM_SOCKET_PATH: &str = "/run/rfs/rfs-fuse.sock";
+const SOCKET_NAME: &str = "rfs-fuse.sock";
+
+// Whether this process runs as a user other than root.
+pub fn unprivileged() -> bool {
+    !geteuid().is_root()
+}
+
+// Configuration file `name`, such as pool.toml: in /opt/rfs/rfsd, or in
+// $XDG_CONFIG_HOME/rfs (~/.config/rfs) for users.
+pub fn config_file(name: &str) -> String {
+    if !unprivileged() {
+        return format!("{}/{}", SYSTEM_CONFIG_DIR, name);
+    }
+    base_dir("XDG_CONFIG_HOME", ".config").join(name).to_string_lossy().into_owned()
+}
+
+// The control socket: /run/rfs/rfs-fuse.sock, or in $XDG_RUNTIME_DIR/rfs for
+// users. Without XDG_RUNTIME_DIR it goes to a directory of the temporary
+// directory named after the uid.
+pub fn control_socket() -> String {
+    if !unprivileged() {
+        return SYSTEM_SOCKET_PATH.to_string();
+    }
+    let dir = match absolute(env::var_os("XDG_RUNTIME_DIR")) {
+        Some(dir) => dir.join("rfs"),
+        None => env::temp_dir().join(format!("rfs-{}", geteuid())),
+    };
+    dir.join(SOCKET_NAME).to_string_lossy().into_owned()
+}
+
+// The rfs directory of the XDG base directory named by `var`, which is
+// `fallback` in the home directory when unset.
+pub fn base_dir(var: &str, fallback: &str) -> PathBuf {
+    xdg_dir(env::var_os(var), home, fallback).join("rfs")
+}
+
+fn xdg_dir(value: Option<OsString>, home: impl FnOnce() -> PathBuf, fallback: &str) -> PathBuf {
+    // The specification has relative values ignored.
+    absolute(value).unwrap_or_else(|| home().join(fallback))
+}
+
+fn absolute(value: Option<OsString>) -> Option<PathBuf> {
+    value.map(PathBuf::from).filter(|path| path.is_absolute())
+}
+
+fn home() -> PathBuf {
+    absolute(env::var_os("HOME"))
+        .or_else(|| User::from_uid(geteuid()).ok().flatten().map(|user| user.dir))
+        .unwrap_or_else(|| Path::new("/").to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::xdg_dir;
+    use std::path::PathBuf;
+
+    #[test]
+    fn xdg_directories_fall_back_to_the_home_directory() {
+        let home = || PathBuf::from("/home/dev");
+        assert_eq!(xdg_dir(Some("/xdg/config".into()), home, ".config"), PathBuf::from("/xdg/config"));
+        assert_eq!(xdg_dir(None, home, ".config"), PathBuf::from("/home/dev/.config"));
+        assert_eq!(xdg_dir(Some("relative".into()), home, ".local/state"), PathBuf::from("/home/dev/.local/state"));
+        assert_eq!(xdg_dir(Some("".into()), home, ".config"), PathBuf::from("/home/dev/.config"));
+    }
+}
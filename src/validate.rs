@@ -0,0 +1,379 @@
+// src/validate.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::config::{LogTarget, Settings};
+use crate::error::FuseError;
+use crate::home::{homes_dir, USER_PLACEHOLDER};
+use crate::logging::{parse_level, Subsystem};
+use crate::mount::MountEntry;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml_edit::{ImDocument, Item, TableLike, Value};
+
+// Problems collected from one file before giving up on finding more; a file
+// this broken is most likely not meant for rfs-fuse at all.
+const MAX_PROBLEMS: usize = 50;
+
+// Something wrong in a configuration file, on `line` when it can be told.
+#[derive(Debug)]
+pub struct Problem {
+    file: String,
+    line: Option<usize>,
+    message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+// Fails with all of `problems` in one error, so they can be fixed in one go.
+pub fn report(problems: Vec<Problem>, error: fn(String) -> FuseError) -> Result<(), FuseError> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let listed: String = problems.iter().map(|problem| format!("\n  {}", problem)).collect();
+    Err(error(format!("{} problem(s) found:{}", problems.len(), listed)))
+}
+
+// Parses fuse.toml, reporting every unknown key, mistyped value and invalid
+// combination of options in it instead of only the first.
+pub fn parse_settings(path: &str, contents: &str) -> Result<Settings, FuseError> {
+    let mut problems = Vec::new();
+    // Each offending line is blanked out, keeping the line numbers, and the
+    // rest parsed again to find the next problem.
+    let mut text = contents.to_string();
+    let parsed = loop {
+        match toml::from_str::<Settings>(&text) {
+            Ok(settings) => break Some(settings),
+            Err(e) => {
+                let line = e.span().map(|span| line_of(&text, span.start));
+                problems.push(Problem {
+                    file: path.to_string(),
+                    line,
+                    message: e.message().to_string(),
+                });
+                match e.span() {
+                    Some(span) if problems.len() < MAX_PROBLEMS && blank(&mut text, &span) => {}
+                    _ => break None,
+                }
+            }
+        }
+    };
+    if let Some(settings) = &parsed {
+        problems.extend(option_problems(path, contents, settings));
+    }
+    report(problems, FuseError::Config)?;
+    Ok(parsed.unwrap_or_default())
+}
+
+// Replaces the lines of `span` with spaces, along with the rest of the table
+// if it starts with a table header. Returns false if nothing was left to blank.
+fn blank(text: &mut String, span: &Range<usize>) -> bool {
+    let start = text[..span.start.min(text.len())].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = |from: usize| text[from..].find('\n').map_or(text.len(), |i| from + i);
+    let mut end = line_end(span.end.clamp(start, text.len()));
+    if text[start..].trim_start().starts_with('[') {
+        while end < text.len() && !text[end + 1..].trim_start().starts_with('[') {
+            end = line_end(end + 1);
+        }
+    }
+    if text[start..end].trim().is_empty() {
+        return false;
+    }
+    let blanked: String = text[start..end].chars().map(|c| if c == '\n' { c } else { ' ' }).collect();
+    text.replace_range(start..end, &blanked);
+    true
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+// Values that parse but cannot work, alone or together with others.
+fn option_problems(path: &str, contents: &str, settings: &Settings) -> Vec<Problem> {
+    let doc = ImDocument::parse(contents).ok();
+    let mut problems = Vec::new();
+    let mut problem = |keys: &[&str], message: String| {
+        problems.push(Problem {
+            file: path.to_string(),
+            line: doc.as_ref().and_then(|doc| key_line(contents, doc, keys)),
+            message,
+        })
+    };
+
+    for (mount_point, options) in &settings.mounts {
+        let key = |name| ["mounts", mount_point.as_str(), name];
+        if !mount_point.starts_with('/') {
+            problem(&["mounts", mount_point], format!("mount point '{}' is not an absolute path", mount_point));
+        }
+        for (name, value) in [
+            ("chunk_size", options.chunk_size),
+            ("name_max", options.name_max),
+            ("metadata_workers", options.metadata_workers),
+            ("data_workers", options.data_workers),
+            ("queue_depth", options.queue_depth),
+        ] {
+            if value == 0 {
+                problem(&key(name), format!("{} of {} must be at least 1", name, mount_point));
+            }
+        }
+        if !options.home.is_empty() && homes_dir(&options.home).is_none() {
+            problem(
+                &key("home"),
+                format!("home '{}' must be an absolute path ending in /{}", options.home, USER_PLACEHOLDER),
+            );
+        }
+        if options.name_max > options.path_max {
+            problem(
+                &key("name_max"),
+                format!(
+                    "name_max ({}) of {} exceeds its path_max ({})",
+                    options.name_max, mount_point, options.path_max
+                ),
+            );
+        }
+    }
+
+    for (mount_point, view) in &settings.views {
+        if !mount_point.starts_with('/') {
+            problem(&["views", mount_point], format!("mount point '{}' is not an absolute path", mount_point));
+        }
+        if !view.of.starts_with('/') {
+            problem(&["views", mount_point, "of"], format!("view {} needs the mount point of the mount it shows", mount_point));
+        } else if Path::new(&view.of) == Path::new(mount_point) {
+            problem(&["views", mount_point, "of"], format!("view {} shows itself", mount_point));
+        }
+        if !view.subpath.starts_with('/') || view.subpath.split('/').any(|part| part == "." || part == "..") {
+            problem(
+                &["views", mount_point, "subpath"],
+                format!("subpath '{}' must be absolute and without . or ..", view.subpath),
+            );
+        }
+    }
+
+    let backend = &settings.backend;
+    if backend.reserve_percent > 100 {
+        problem(&["backend", "reserve_percent"], format!("reserve_percent ({}) exceeds 100", backend.reserve_percent));
+    }
+    if backend.retry_initial_backoff_ms > backend.retry_max_backoff_ms {
+        problem(
+            &["backend", "retry_initial_backoff_ms"],
+            "retry_initial_backoff_ms exceeds retry_max_backoff_ms".to_string(),
+        );
+    }
+    let startup = &settings.startup;
+    if startup.retry_initial_backoff() > startup.retry_max_backoff() {
+        problem(
+            &["startup", "retry_initial_backoff_ms"],
+            "retry_initial_backoff_ms exceeds retry_max_backoff_secs".to_string(),
+        );
+    }
+    let supervisor = &settings.supervisor;
+    if supervisor.enabled && supervisor.check_interval_ms == 0 {
+        problem(&["supervisor", "check_interval_ms"], "check_interval_ms must be at least 1".to_string());
+    }
+    if supervisor.initial_backoff() > supervisor.max_backoff() {
+        problem(&["supervisor", "initial_backoff_ms"], "initial_backoff_ms exceeds max_backoff_secs".to_string());
+    }
+
+    let control = &settings.control;
+    if control.mounts && !control.enabled {
+        problem(&["control", "mounts"], "mounts = true needs the control socket, which is disabled".to_string());
+    }
+    if control.enabled && control.socket_path.is_empty() {
+        problem(&["control", "socket_path"], "socket_path is empty".to_string());
+    }
+    let watchdog = &settings.watchdog;
+    if watchdog.abort && !watchdog.enabled {
+        problem(&["watchdog", "abort"], "abort = true has no effect while the watchdog is disabled".to_string());
+    }
+
+    let log = &settings.log;
+    if matches!(log.target, LogTarget::File) && log.file.path.is_empty() {
+        problem(&["log", "file", "path"], "target = \"file\" needs a file path".to_string());
+    }
+    for (name, value) in &log.levels {
+        if !Subsystem::ALL.iter().any(|subsystem| subsystem.name() == name) {
+            problem(&["log", "levels", name], format!("unknown log subsystem '{}'", name));
+        } else if parse_level(value).is_none() {
+            problem(&["log", "levels", name], format!("invalid log level '{}' for '{}'", value, name));
+        }
+    }
+    problems
+}
+
+// Line of the key at `keys` in `doc`, or of the closest table containing it
+// when the key itself is not in the file, such as for a default value.
+fn key_line(contents: &str, doc: &ImDocument<&str>, keys: &[&str]) -> Option<usize> {
+    (1..=keys.len()).rev().find_map(|len| {
+        let (last, parents) = keys[..len].split_last()?;
+        let mut table: &dyn TableLike = doc.as_table();
+        for key in parents {
+            table = table.get(key)?.as_table_like()?;
+        }
+        let (key, _) = table.get_key_value(last)?;
+        Some(line_of(contents, key.span()?.start))
+    })
+}
+
+// Mount points of pool.toml's entries that are relative, used twice or lie
+// inside another one, which the later mount would shadow.
+pub fn mount_problems(path: &str, entries: &[MountEntry]) -> Vec<Problem> {
+    let lines = fs::read_to_string(path).map(|contents| string_lines(&contents)).unwrap_or_default();
+    // The n-th entry with a mount point is taken to be its n-th occurrence in the file.
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let located: Vec<(PathBuf, Option<usize>)> = entries
+        .iter()
+        .map(|entry| {
+            let occurrence = seen.entry(&entry.mount_point).or_default();
+            *occurrence += 1;
+            let line = lines
+                .iter()
+                .filter(|(value, _)| *value == entry.mount_point)
+                .nth(*occurrence - 1)
+                .map(|(_, line)| *line);
+            (Path::new(&entry.mount_point).components().collect(), line)
+        })
+        .collect();
+
+    let mut problems = Vec::new();
+    for (index, (mount_point, line)) in located.iter().enumerate() {
+        let mut problem = |message: String| {
+            problems.push(Problem {
+                file: path.to_string(),
+                line: *line,
+                message,
+            })
+        };
+        if !mount_point.is_absolute() {
+            problem(format!("mount point '{}' is not an absolute path", mount_point.display()));
+            continue;
+        }
+        for (other_index, (other, other_line)) in located.iter().enumerate() {
+            let at = other_line.map(|line| format!(" on line {}", line)).unwrap_or_default();
+            // A mount point used twice is reported as such, not as nested twice.
+            let repeated = located[..other_index].iter().any(|(earlier, _)| earlier == other);
+            if other_index < index && other == mount_point {
+                problem(format!("mount point {} is already used by the entry{}", mount_point.display(), at));
+            } else if other != mount_point && !repeated && mount_point.starts_with(other) {
+                problem(format!(
+                    "mount point {} lies inside mount point {}{}",
+                    mount_point.display(),
+                    other.display(),
+                    at
+                ));
+            }
+        }
+    }
+    problems
+}
+
+// Every string value in the TOML document with the line it is on.
+fn string_lines(contents: &str) -> Vec<(String, usize)> {
+    let mut found = Vec::new();
+    if let Ok(doc) = ImDocument::parse(contents) {
+        collect_table(contents, doc.as_table(), &mut found);
+    }
+    found
+}
+
+fn collect_table(contents: &str, table: &dyn TableLike, found: &mut Vec<(String, usize)>) {
+    for (_, item) in table.iter() {
+        match item {
+            Item::Value(value) => collect_value(contents, value, found),
+            Item::Table(table) => collect_table(contents, table, found),
+            Item::ArrayOfTables(tables) => tables.iter().for_each(|table| collect_table(contents, table, found)),
+            Item::None => {}
+        }
+    }
+}
+
+fn collect_value(contents: &str, value: &Value, found: &mut Vec<(String, usize)>) {
+    match value {
+        Value::String(string) => {
+            if let Some(span) = string.span() {
+                found.push((string.value().clone(), line_of(contents, span.start)));
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|value| collect_value(contents, value, found)),
+        Value::InlineTable(table) => collect_table(contents, table, found),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mount_problems, parse_settings};
+    use crate::mount::MountEntry;
+    use std::fs;
+
+    #[test]
+    fn every_problem_in_the_settings_is_reported() {
+        let contents = "\
+[backend]
+timeout_ms = \"soon\"
+reserve_percent = 120
+
+[trash]
+enabled = true
+
+[mounts.\"/mnt/a\"]
+name_max = 300
+path_max = 255
+trash = true
+";
+        let err = parse_settings("fuse.toml", contents).unwrap_err().to_string();
+        assert!(err.contains("5 problem(s)"), "{}", err);
+        assert!(err.contains("fuse.toml:2: invalid type"), "{}", err);
+        assert!(err.contains("fuse.toml:5: unknown field `trash`"), "{}", err);
+        assert!(err.contains("fuse.toml:11: unknown field `trash`"), "{}", err);
+        assert!(err.contains("fuse.toml:3: reserve_percent (120) exceeds 100"), "{}", err);
+        assert!(err.contains("fuse.toml:9: name_max (300) of /mnt/a exceeds its path_max (255)"), "{}", err);
+
+        assert!(parse_settings("fuse.toml", "[mounts.\"/mnt/a\"]\nlazy = true\n").is_ok());
+    }
+
+    #[test]
+    fn duplicate_and_nested_mount_points_are_reported() {
+        let path = std::env::temp_dir().join(format!("rfs-validate-{}.toml", std::process::id()));
+        let contents = "\
+[[mounts]]
+mount_point = \"/mnt/a\"
+
+[[mounts]]
+mount_point = \"/mnt/a/b\"
+
+[[mounts]]
+mount_point = \"/mnt/a/\"
+";
+        fs::write(&path, contents).unwrap();
+        let entry = |mount_point: &str| MountEntry {
+            mount_point: mount_point.to_string(),
+            pool_id: 1,
+            pool_root: None,
+            subpath: "/".to_string(),
+            read_only: false,
+        };
+        let entries = [entry("/mnt/a"), entry("/mnt/a/b"), entry("/mnt/a/"), entry("/mnt/c")];
+        let path = path.to_str().unwrap();
+        let problems: Vec<String> = mount_problems(path, &entries).iter().map(|p| p.to_string()).collect();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            problems,
+            [
+                format!("{}:5: mount point /mnt/a/b lies inside mount point /mnt/a on line 2", path),
+                format!("{}:8: mount point /mnt/a is already used by the entry on line 2", path),
+            ]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+// src/verify.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::backend::Backend;
+use crate::config::{load_settings, Paths};
+use crate::error::FuseError;
+use crate::mount::find_pool;
+use crate::scrub;
+use crate::stats::Registry;
+use std::sync::Arc;
+use std::time::Duration;
+
+// `rfs verify <pool-id>`: walks a pool through librfs without mounting it,
+// with the same checks as the background scrub, and prints every problem
+// found. Returns the exit code: 0 for a clean pool, 1 if problems were found
+// and 2 if the pool could not be verified.
+pub async fn run(paths: &Paths, pool_id: &str) -> i32 {
+    match verify(paths, pool_id).await {
+        Ok(0) => 0,
+        Ok(_) => 1,
+        Err(e) => {
+            eprintln!("verify: {}", e);
+            2
+        }
+    }
+}
+
+async fn verify(paths: &Paths, pool_id: &str) -> Result<u64, FuseError> {
+    let settings = load_settings(&paths.settings)?;
+    let (pool_id, pool_root) = find_pool(&paths.pool_config, pool_id).await?;
+
+    let registry = Registry::default();
+    let label = format!("verify:{}", pool_id);
+    let stats = registry.register(&label, pool_id);
+    let breaker = registry.breaker(pool_id, &settings.backend);
+    let backend = Backend::new(pool_root.clone(), settings.backend.clone(), Arc::clone(&stats), breaker);
+
+    println!("Verifying pool {} at {}", pool_id, pool_root);
+    let walk = tokio::task::spawn_blocking(move || {
+        let (mut directories, mut entries, mut problems) = (0u64, 0u64, 0u64);
+        scrub::walk(
+            &backend,
+            Duration::ZERO,
+            || false,
+            |count| {
+                directories += 1;
+                entries += count;
+            },
+            |path, problem| {
+                problems += 1;
+                println!("{}: {}", path, problem);
+            },
+        );
+        (directories, entries, problems)
+    });
+    let (directories, entries, problems) = walk.await.map_err(|e| FuseError::Io(std::io::Error::other(e)))?;
+    println!("{} directories, {} entries, {} problems", directories, entries, problems);
+    Ok(problems)
+}
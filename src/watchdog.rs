@@ -0,0 +1,60 @@
+// src/watchdog.rs
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright (c) 2025 Canmi
+
+use crate::config::WatchdogSettings;
+use crate::logging::{Record, Subsystem};
+use crate::stats::{MountStats, Registry};
+use rfs_utils::LogLevel;
+use std::sync::Arc;
+use std::time::Duration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Periodically looks for FUSE operations running past the threshold. Each
+// stuck operation is logged once, with its path and the state of the
+// backend, and counted; with `abort` set its backend call is abandoned.
+pub fn spawn(registry: Arc<Registry>, settings: WatchdogSettings) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            for mount in registry.mounts() {
+                check_mount(&registry, &mount, &settings);
+            }
+        }
+    });
+}
+
+fn check_mount(registry: &Registry, mount: &MountStats, settings: &WatchdogSettings) {
+    for op in mount.inflight.snapshot() {
+        let age = op.started.elapsed();
+        if age < settings.stuck_after() || !op.mark_reported() {
+            continue;
+        }
+        mount.count(&mount.stuck_ops, 1);
+        let breaker = registry
+            .pool_breaker(mount.pool_id)
+            .map_or("closed", |breaker| breaker.state_name());
+        let action = if settings.abort { "aborting its backend call" } else { "still waiting" };
+        let message = format!(
+            "Operation stuck for {}s (uid {}, breaker {}, read_only {}, {} in flight); {}",
+            age.as_secs(),
+            op.uid,
+            breaker,
+            mount.is_read_only(),
+            mount.inflight.snapshot().len(),
+            action
+        );
+        Record::new(Subsystem::Fuse, LogLevel::Error, &message)
+            .op(op.op)
+            .mount(&mount.mount_point)
+            .path(op.path.to_string_lossy())
+            .duration(age)
+            .requester(&op)
+            .emit();
+        if settings.abort {
+            op.cancel();
+        }
+    }
+}